@@ -0,0 +1,8 @@
+//! Print the program IDL as JSON
+//!
+//! `cargo run --example idl --features idl > loans254.json`
+
+fn main() {
+    let idl = loans254::idl::generate_idl();
+    println!("{}", serde_json::to_string_pretty(&idl).expect("IDL is valid JSON"));
+}
@@ -0,0 +1,603 @@
+//! The accounts each instruction expects, in order.
+//!
+//! Every struct is generic over the account type: builders fill it with `Pubkey`s and
+//! turn it into account metas, the processor parses it from the accounts it was given.
+//! Both sides come from the same field list, so they cannot disagree on the order or
+//! on which accounts have to sign or be writable.
+
+use std::slice::Iter;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    instruction::AccountMeta,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::error::LoanError;
+
+/// What an instruction needs to be able to do with an account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccountFlag {
+    Signer,
+    Writable,
+}
+
+fn account_meta(pubkey: Pubkey, flags: &[AccountFlag]) -> AccountMeta {
+    let is_signer = flags.contains(&AccountFlag::Signer);
+    if flags.contains(&AccountFlag::Writable) {
+        AccountMeta::new(pubkey, is_signer)
+    } else {
+        AccountMeta::new_readonly(pubkey, is_signer)
+    }
+}
+
+fn check_account_flags(account_info: &AccountInfo, flags: &[AccountFlag]) -> Result<(), ProgramError> {
+    if flags.contains(&AccountFlag::Signer) && !account_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if flags.contains(&AccountFlag::Writable) && !account_info.is_writable {
+        return Err(LoanError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+macro_rules! instruction_accounts {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: [$($flag:ident),*],
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct $name<T> {
+            $(
+                $(#[$field_meta])*
+                pub $field: T,
+            )*
+        }
+
+        impl<T> $name<T> {
+            /// The number of accounts the instruction expects
+            pub const LEN: usize = [$(stringify!($field)),*].len();
+            /// The name and flags of each account, in order
+            pub const ACCOUNTS: &'static [(&'static str, &'static [AccountFlag])] = &[
+                $((stringify!($field), &[$(AccountFlag::$flag),*]),)*
+            ];
+        }
+
+        impl $name<Pubkey> {
+            /// The account metas, in the order the processor expects them
+            pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+                vec![
+                    $(account_meta(self.$field, &[$(AccountFlag::$flag),*]),)*
+                ]
+            }
+        }
+
+        impl<'a, 'b> $name<&'a AccountInfo<'b>> {
+            /// Take the accounts off the iterator, checking that they can sign and be
+            /// written to where required
+            pub fn from_account_infos(
+                account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+            ) -> Result<Self, ProgramError> {
+                $(
+                    let $field = next_account_info(account_info_iter)?;
+                    check_account_flags($field, &[$(AccountFlag::$flag),*])?;
+                )*
+                Ok(Self { $($field,)* })
+            }
+        }
+    };
+}
+
+instruction_accounts! {
+    /// Accounts expected by `InitLoan`
+    pub struct InitLoanAccounts {
+        /// The account of the person initializing the loan
+        initializer: [Signer],
+        /// The loan mint
+        loan_mint: [],
+        /// The initializer's token account that receives the loan
+        borrower_loan_receive: [],
+        /// The loan account
+        loan: [Writable],
+        /// The rent sysvar
+        rent: [],
+        /// The program config
+        config: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `GuaranteeLoan`
+    pub struct GuaranteeLoanAccounts {
+        /// The account of the person guaranteeing the loan, pays for the vault
+        guarantor: [Writable, Signer],
+        /// The guarantor's token account that holds the collateral
+        collateral_source: [Writable],
+        /// The guarantor's token account that receives their share of repayments
+        guarantor_repayment: [],
+        /// The loan account
+        loan: [Writable],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The collateral mint
+        collateral_mint: [],
+        /// The rent sysvar
+        rent: [],
+        /// The system program
+        system_program: [],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts needed to value collateral in a different mint from the loan
+    pub struct CollateralPriceAccounts {
+        /// The program config, names the oracle program and the publisher of the price feed
+        config: [],
+        /// The price feed of the collateral mint in the loan mint
+        price_feed: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts needed to value collateral in a different mint from the loan, when the
+    /// instruction already takes the program config
+    pub struct PriceFeedAccounts {
+        /// The price feed of the collateral mint in the loan mint
+        price_feed: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `AcceptLoan`
+    pub struct AcceptLoanAccounts {
+        /// The account of the person lending the money, pays for the repayment vault
+        lender: [Writable, Signer],
+        /// The lender's token account that the loan is paid out of
+        lender_loan_transfer: [Writable],
+        /// The lender's token account that repayments are withdrawn to
+        lender_repayment: [],
+        /// The borrower's token account that receives the loan
+        borrower_loan_receive: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The rent sysvar
+        rent: [],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+        /// The program config
+        config: [],
+        /// The treasury token account for the loan mint
+        treasury: [Writable],
+        /// The repayment vault
+        repayment_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The loan mint
+        loan_mint: [],
+        /// The system program
+        system_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `RepayLoan`
+    pub struct RepayLoanAccounts {
+        /// The account of the person repaying the loan
+        payer: [Signer],
+        /// The payer's token account that has the funds being repaid
+        payer_token: [Writable],
+        /// The guarantor's token account that receives their share
+        guarantor_repayment: [Writable],
+        /// The loan's repayment vault, or the lender's token account for loans without one
+        lender_repayment: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The program config
+        config: [],
+        /// The treasury token account for the loan mint
+        treasury: [Writable],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts needed to hand the collateral back to the guarantor, when a loan is
+    /// repaid in full or a guaranteed loan is cancelled
+    pub struct ReturnCollateralAccounts {
+        /// The guarantor's account, receives the collateral vault rent
+        guarantor: [Writable],
+        /// The guarantor's token account that the collateral is returned to
+        collateral_return: [Writable],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `CancelLoan`
+    pub struct CancelLoanAccounts {
+        /// The account of the person who initialized the loan
+        initializer: [Signer],
+        /// The loan account
+        loan: [Writable],
+        /// The clock sysvar
+        clock: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `ClaimDefault`
+    pub struct ClaimDefaultAccounts {
+        /// The account of the person who lent the money
+        lender: [Signer],
+        /// The lender's token account that receives the collateral
+        lender_collateral: [Writable],
+        /// The guarantor's account, receives the collateral vault rent
+        guarantor: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `InitConfig`
+    pub struct InitConfigAccounts {
+        /// The admin account, pays for the config account
+        admin: [Writable, Signer],
+        /// The program config
+        config: [Writable],
+        /// The rent sysvar
+        rent: [],
+        /// The system program
+        system_program: [],
+        /// The program data account, holds the upgrade authority of the program
+        program_data: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `UpdateConfig`
+    pub struct UpdateConfigAccounts {
+        /// The admin account
+        admin: [Signer],
+        /// The program config
+        config: [Writable],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `InitTreasury`
+    pub struct InitTreasuryAccounts {
+        /// The admin account, pays for the treasury account
+        admin: [Writable, Signer],
+        /// The program config
+        config: [],
+        /// The treasury token account
+        treasury: [Writable],
+        /// The mint
+        mint: [],
+        /// The rent sysvar
+        rent: [],
+        /// The system program
+        system_program: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `WithdrawTreasury`
+    pub struct WithdrawTreasuryAccounts {
+        /// The admin account
+        admin: [Signer],
+        /// The program config
+        config: [],
+        /// The treasury token account
+        treasury: [Writable],
+        /// The token account to send the fees to
+        destination: [Writable],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `MigrateLoan`
+    pub struct MigrateLoanAccounts {
+        /// The borrower, guarantor or lender of the loan, pays for the collateral vault
+        payer: [Writable, Signer],
+        /// The account of the person who initialized the loan, receives the old account's rent
+        initializer: [Writable],
+        /// The version 1 loan account
+        old_loan: [Writable],
+        /// The new loan account
+        new_loan: [Writable],
+        /// The loan mint
+        loan_mint: [],
+        /// The rent sysvar
+        rent: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts `MigrateLoan` needs to move the collateral of a guaranteed or accepted
+    /// version 1 loan out of the version 1 loan authority
+    pub struct MigrateCollateralAccounts {
+        /// The version 1 loan authority, shared by every version 1 loan
+        legacy_authority: [],
+        /// The guarantor's token account that the version 1 loan holds the collateral in
+        legacy_collateral: [Writable],
+        /// The guarantor's repayment token account
+        guarantor_repayment: [Writable],
+        /// The collateral vault of the new loan account
+        collateral_vault: [Writable],
+        /// The collateral mint
+        collateral_mint: [],
+        /// The loan authority of the new loan account
+        loan_authority: [],
+        /// The system program
+        system_program: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts `MigrateLoan` needs to hand an accepted version 1 loan's repayment account
+    /// back to the lender
+    pub struct MigrateRepaymentAccounts {
+        /// The lender's repayment token account
+        lender_repayment: [Writable],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `OfferLoan`
+    pub struct OfferLoanAccounts {
+        /// The account of the person making the offer, pays for the offer vault
+        lender: [Writable, Signer],
+        /// The lender's token account that the offered funds are taken from
+        lender_loan_transfer: [Writable],
+        /// The lender's token account that receives repayments if the offer is taken
+        lender_repayment: [],
+        /// The loan account
+        loan: [],
+        /// The offer account
+        offer: [Writable],
+        /// The offer vault
+        offer_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The loan mint
+        loan_mint: [],
+        /// The program config
+        config: [],
+        /// The rent sysvar
+        rent: [],
+        /// The system program
+        system_program: [],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `TakeOffer`
+    pub struct TakeOfferAccounts {
+        /// The account of the person who initialized the loan
+        borrower: [Signer],
+        /// The borrower's token account that receives the loan
+        borrower_loan_receive: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The offer account
+        offer: [Writable],
+        /// The offer vault, kept as the repayment vault
+        offer_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The account of the person who made the offer, receives the offer rent
+        lender: [Writable],
+        /// The program config
+        config: [],
+        /// The treasury token account for the loan mint
+        treasury: [Writable],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+        /// The collateral vault
+        collateral_vault: [],
+        /// The collateral mint
+        collateral_mint: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `CancelOffer`
+    pub struct CancelOfferAccounts {
+        /// The account of the person who made the offer, receives the offer rent
+        lender: [Writable, Signer],
+        /// The lender's token account that the offered funds are returned to
+        lender_refund: [Writable],
+        /// The loan account
+        loan: [],
+        /// The offer account
+        offer: [Writable],
+        /// The offer vault
+        offer_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `CheckHealth`
+    pub struct CheckHealthAccounts {
+        /// The loan account
+        loan: [],
+        /// The collateral vault
+        collateral_vault: [],
+        /// The collateral mint
+        collateral_mint: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `Liquidate`
+    pub struct LiquidateAccounts {
+        /// The account of the keeper liquidating the loan
+        liquidator: [Signer],
+        /// The liquidator's token account that has the funds being repaid
+        liquidator_token: [Writable],
+        /// The liquidator's token account that receives the collateral
+        liquidator_collateral: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The collateral mint
+        collateral_mint: [],
+        /// The guarantor's token account that receives their share
+        guarantor_repayment: [Writable],
+        /// The loan's repayment vault, or the lender's token account for loans without one
+        lender_repayment: [Writable],
+        /// The program config
+        config: [],
+        /// The treasury token account for the loan mint
+        treasury: [Writable],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `AddCollateral`
+    pub struct AddCollateralAccounts {
+        /// The account of the person adding the collateral
+        depositor: [Signer],
+        /// The depositor's token account that the collateral is taken from
+        collateral_source: [Writable],
+        /// The loan account
+        loan: [],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `ReleaseCollateral`
+    pub struct ReleaseCollateralAccounts {
+        /// The account of the person who guaranteed the loan
+        guarantor: [Signer],
+        /// The guarantor's token account that the collateral is returned to
+        collateral_return: [Writable],
+        /// The loan account
+        loan: [],
+        /// The collateral vault
+        collateral_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The collateral mint
+        collateral_mint: [],
+        /// The token program
+        token_program: [],
+        /// The clock sysvar
+        clock: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `ExpireLoan`
+    pub struct ExpireLoanAccounts {
+        /// The loan account
+        loan: [Writable],
+        /// The clock sysvar
+        clock: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `CloseLoan`
+    pub struct CloseLoanAccounts {
+        /// The account of the person who initialized the loan, receives the loan account rent
+        initializer: [Writable, Signer],
+        /// The loan account
+        loan: [Writable],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `WithdrawRepayment`
+    pub struct WithdrawRepaymentAccounts {
+        /// The account of the person who lent the money, receives the vault rent once the loan is settled
+        lender: [Writable, Signer],
+        /// The token account to send the repayments to
+        destination: [Writable],
+        /// The loan account
+        loan: [Writable],
+        /// The repayment vault
+        repayment_vault: [Writable],
+        /// The loan authority
+        loan_authority: [],
+        /// The token program
+        token_program: [],
+    }
+}
+
+instruction_accounts! {
+    /// Accounts expected by `MigrateConfig`
+    pub struct MigrateConfigAccounts {
+        /// The admin account, receives the config account rent
+        admin: [Writable, Signer],
+        /// The program config
+        config: [Writable],
+    }
+}
@@ -0,0 +1,517 @@
+//! Command line tool for operating the loans program.
+//!
+//! `cargo run --features cli -- --program-id <PROGRAM_ID> show-loan <LOAN>`
+
+use std::{convert::TryFrom, env, process::exit};
+
+use clap::{
+    crate_name, crate_version, value_t_or_exit, App, AppSettings, Arg, ArgMatches, SubCommand,
+};
+use solana_clap_utils::{
+    input_parsers::pubkey_of,
+    input_validators::{is_keypair, is_pubkey, is_url},
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+
+use loans254::{
+    client::LoanClient,
+    instruction::{init_config, migrate_config, update_config},
+    state::{Loan, LoanStatus, ProgramConfig, LOAN_V1_LEN, LOAN_VERSION},
+    utils::find_config_address,
+};
+
+type Error = Box<dyn std::error::Error>;
+type CommandResult = Result<(), Error>;
+
+/// The default keypair of the solana command line tools
+fn default_keypair_path() -> String {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    format!("{}/.config/solana/id.json", home)
+}
+
+/// Find the status whose name matches, ignoring case
+fn parse_status(value: &str) -> Option<LoanStatus> {
+    (0..=u8::MAX)
+        .filter_map(|byte| LoanStatus::try_from(byte).ok())
+        .find(|status| format!("{:?}", status).eq_ignore_ascii_case(value))
+}
+
+fn is_status(value: String) -> Result<(), String> {
+    parse_status(&value)
+        .map(|_| ())
+        .ok_or_else(|| format!("Unknown loan status {}", value))
+}
+
+fn read_keypair(matches: &ArgMatches) -> Result<Keypair, Error> {
+    let path = matches.value_of("keypair").unwrap();
+    read_keypair_file(path).map_err(|error| format!("Failed to read keypair {}: {}", path, error).into())
+}
+
+fn pubkey_arg<'a, 'b>(name: &'a str, long: &'a str, help: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(long)
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .required(true)
+        .validator(is_pubkey)
+        .help(help)
+}
+
+fn loan_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("loan")
+        .value_name("LOAN")
+        .takes_value(true)
+        .required(true)
+        .index(1)
+        .validator(is_pubkey)
+        .help("The loan account")
+}
+
+fn amount_arg<'a, 'b>(help: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name("amount")
+        .long("amount")
+        .value_name("AMOUNT")
+        .takes_value(true)
+        .required(true)
+        .help(help)
+}
+
+fn collateral_return_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("collateral_return")
+        .long("collateral-return")
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .validator(is_pubkey)
+        .help("The guarantor's token account the collateral is returned to, when the loan is closed")
+}
+
+fn price_feed_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("price_feed")
+        .long("price-feed")
+        .value_name("PUBKEY")
+        .takes_value(true)
+        .validator(is_pubkey)
+        .help("The price feed of the collateral mint in the loan mint, when they differ")
+}
+
+fn config_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args: Vec<Arg> = [
+        ("interest_rate", "interest-rate", "The annual interest rate in basis points"),
+        ("duration", "duration", "The loan duration in hours"),
+        ("processing_fee", "processing-fee", "The program's share of the interest in basis points"),
+        ("application_fee", "application-fee", "The application fee in basis points"),
+        ("guarantor_share", "guarantor-share", "The guarantor's share of the interest in basis points"),
+        ("lender_share", "lender-share", "The lender's share of the interest in basis points"),
+        ("collateral_ratio", "collateral-ratio", "The value of collateral required as a share of the loan in basis points"),
+        ("maintenance_ratio", "maintenance-ratio", "The collateral ratio below which loans can be liquidated in basis points, 0 for never"),
+        ("liquidation_bonus", "liquidation-bonus", "The extra collateral liquidators receive in basis points"),
+    ]
+    .iter()
+    .map(|&(name, long, help)| {
+        Arg::with_name(name)
+            .long(long)
+            .value_name("NUMBER")
+            .takes_value(true)
+            .required(true)
+            .help(help)
+    })
+    .collect();
+    args.push(pubkey_arg(
+        "oracle_program",
+        "oracle-program",
+        "The program whose price feeds value collateral in a different mint from the loan",
+    ));
+    args.push(pubkey_arg(
+        "oracle_publisher",
+        "oracle-publisher",
+        "The publisher whose price feeds are trusted",
+    ));
+    args
+}
+
+fn print_loan(loan_pubkey: &Pubkey, data_len: usize, loan: &Loan) {
+    let version = if data_len == LOAN_V1_LEN { 1 } else { LOAN_VERSION };
+    println!("Loan: {}", loan_pubkey);
+    println!("  Layout version: {}", version);
+    println!("  Status: {:?}", loan.status);
+    println!("  Initializer: {}", loan.initializer_pubkey);
+    println!("  Loan mint: {} ({} decimals)", loan.loan_mint_pubkey, loan.loan_mint_decimals);
+    println!("  Borrower receive account: {}", loan.borrower_loan_receive_pubkey);
+    println!("  Guarantor: {:?}", loan.guarantor_pubkey);
+    println!("  Guarantor repayment account: {:?}", loan.guarantor_repayment_pubkey);
+    println!("  Collateral vault: {:?}", loan.collateral_account_pubkey);
+    println!("  Collateral mint: {:?}", loan.collateral_mint_pubkey);
+    println!("  Lender: {:?}", loan.lender_pubkey);
+    println!("  Lender repayment account: {:?}", loan.lender_repayment_pubkey);
+    println!("  Repayment vault: {:?}", loan.repayment_vault_pubkey);
+    println!("  Expected amount: {}", loan.expected_amount);
+    println!("  Amount: {}", loan.amount);
+    println!("  Amount repaid: {}", loan.amount_repaid);
+    println!("  Amount liquidated: {}", loan.liquidated_amount);
+    println!("  Collateral liquidated: {}", loan.liquidated_collateral);
+    println!("  Outstanding amount: {}", loan.outstanding_amount());
+    println!("  Interest rate: {} basis points", loan.interest_rate);
+    println!("  Max interest rate: {} basis points", loan.max_interest_rate);
+    println!("  Collateral ratio: {} basis points", loan.required_collateral_ratio());
+    println!("  Duration: {} hours", loan.duration);
+    println!("  Created at: {}", loan.created_at);
+    println!("  Guaranteed at: {}", loan.guaranteed_at);
+    println!("  Accepted at: {}", loan.accepted_at);
+    println!("  Due at: {}", loan.due_at);
+    println!("  Closed at: {}", loan.closed_at);
+}
+
+fn print_config(config_pubkey: &Pubkey, config: &ProgramConfig) {
+    println!("Program config: {}", config_pubkey);
+    println!("  Admin: {}", config.admin_pubkey);
+    println!("  Interest rate: {} basis points", config.interest_rate);
+    println!("  Duration: {} hours", config.duration);
+    println!("  Processing fee: {} basis points", config.processing_fee);
+    println!("  Application fee: {} basis points", config.application_fee);
+    println!("  Guarantor share: {} basis points", config.guarantor_share);
+    println!("  Lender share: {} basis points", config.lender_share);
+    println!("  Collateral ratio: {} basis points", config.collateral_ratio);
+    println!("  Maintenance ratio: {} basis points", config.maintenance_ratio);
+    println!("  Liquidation bonus: {} basis points", config.liquidation_bonus);
+    println!("  Oracle program: {}", config.oracle_program_id);
+    println!("  Oracle publisher: {}", config.oracle_publisher);
+}
+
+async fn process_command(matches: &ArgMatches<'_>) -> CommandResult {
+    let program_id = pubkey_of(matches, "program_id").unwrap();
+    let rpc_client = RpcClient::new(matches.value_of("url").unwrap().to_string());
+    let mut client = LoanClient::new(rpc_client, program_id, read_keypair(matches)?);
+    // the payer signs every instruction, so that one keypair file is enough
+    let signer = read_keypair(matches)?;
+
+    match matches.subcommand() {
+        ("create-loan", Some(arg_matches)) => {
+            let loan_pubkey = client.init_loan(
+                &signer,
+                pubkey_of(arg_matches, "mint").unwrap(),
+                pubkey_of(arg_matches, "receive").unwrap(),
+                value_t_or_exit!(arg_matches, "amount", u64),
+                value_t_or_exit!(arg_matches, "duration", u32),
+                value_t_or_exit!(arg_matches, "max_interest_rate", u32),
+                pubkey_of(arg_matches, "collateral_mint").unwrap(),
+            ).await?;
+            println!("Loan: {}", loan_pubkey);
+        }
+        ("guarantee-loan", Some(arg_matches)) => {
+            client.guarantee_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "collateral_source").unwrap(),
+                pubkey_of(arg_matches, "repayment").unwrap(),
+                pubkey_of(arg_matches, "collateral_mint").unwrap(),
+                pubkey_of(arg_matches, "price_feed"),
+            ).await?;
+        }
+        ("accept-loan", Some(arg_matches)) => {
+            client.accept_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "transfer").unwrap(),
+                pubkey_of(arg_matches, "repayment").unwrap(),
+            ).await?;
+        }
+        ("repay-loan", Some(arg_matches)) => {
+            client.repay_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "from").unwrap(),
+                value_t_or_exit!(arg_matches, "amount", u64),
+                pubkey_of(arg_matches, "collateral_return"),
+            ).await?;
+        }
+        ("add-collateral", Some(arg_matches)) => {
+            client.add_collateral(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "collateral_source").unwrap(),
+                value_t_or_exit!(arg_matches, "amount", u64),
+            ).await?;
+        }
+        ("release-collateral", Some(arg_matches)) => {
+            client.release_collateral(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "collateral_return").unwrap(),
+                pubkey_of(arg_matches, "price_feed"),
+            ).await?;
+        }
+        ("liquidate-loan", Some(arg_matches)) => {
+            client.liquidate(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "from").unwrap(),
+                pubkey_of(arg_matches, "collateral_receive").unwrap(),
+                value_t_or_exit!(arg_matches, "amount", u64),
+                pubkey_of(arg_matches, "price_feed"),
+                pubkey_of(arg_matches, "collateral_return"),
+            ).await?;
+        }
+        ("cancel-loan", Some(arg_matches)) => {
+            client.cancel_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "collateral_return"),
+            ).await?;
+        }
+        ("expire-loan", Some(arg_matches)) => {
+            client.expire_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "collateral_return"),
+            ).await?;
+        }
+        ("close-loan", Some(arg_matches)) => {
+            client.close_loan(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+            ).await?;
+        }
+        ("withdraw-repayment", Some(arg_matches)) => {
+            client.withdraw_repayment(
+                pubkey_of(arg_matches, "loan").unwrap(),
+                &signer,
+                pubkey_of(arg_matches, "to").unwrap(),
+            ).await?;
+        }
+        ("show-loan", Some(arg_matches)) => {
+            let loan_pubkey = pubkey_of(arg_matches, "loan").unwrap();
+            let account = client.backend.get_account(&loan_pubkey)?;
+            print_loan(&loan_pubkey, account.data.len(), &Loan::unpack(&account.data)?);
+        }
+        ("list-loans", Some(arg_matches)) => {
+            let statuses: Vec<LoanStatus> = arg_matches
+                .values_of("status")
+                .map(|values| values.filter_map(parse_status).collect())
+                .unwrap_or_default();
+            for (loan_pubkey, account) in client.backend.get_program_accounts(&program_id)? {
+                // the program also owns the config account
+                let loan = match Loan::unpack(&account.data) {
+                    Ok(loan) => loan,
+                    Err(_) => continue,
+                };
+                if !statuses.is_empty() && !statuses.contains(&loan.status) {
+                    continue;
+                }
+                println!(
+                    "{} {:?} {} {}",
+                    loan_pubkey, loan.status, loan.expected_amount, loan.initializer_pubkey,
+                );
+            }
+        }
+        ("show-config", Some(_)) => {
+            let (config_pubkey, _bump_seed) = find_config_address(&program_id);
+            let account = client.backend.get_account(&config_pubkey)?;
+            print_config(&config_pubkey, &ProgramConfig::unpack(&account.data)?);
+        }
+        ("init-config", Some(arg_matches)) | ("update-config", Some(arg_matches)) => {
+            let build_instruction = if matches.subcommand_name() == Some("init-config") {
+                init_config
+            } else {
+                update_config
+            };
+            let instruction = build_instruction(
+                program_id,
+                signer.pubkey(),
+                value_t_or_exit!(arg_matches, "interest_rate", u32),
+                value_t_or_exit!(arg_matches, "duration", u32),
+                value_t_or_exit!(arg_matches, "processing_fee", u32),
+                value_t_or_exit!(arg_matches, "application_fee", u32),
+                value_t_or_exit!(arg_matches, "guarantor_share", u32),
+                value_t_or_exit!(arg_matches, "lender_share", u32),
+                value_t_or_exit!(arg_matches, "collateral_ratio", u32),
+                value_t_or_exit!(arg_matches, "maintenance_ratio", u32),
+                value_t_or_exit!(arg_matches, "liquidation_bonus", u32),
+                pubkey_of(arg_matches, "oracle_program").unwrap(),
+                pubkey_of(arg_matches, "oracle_publisher").unwrap(),
+            );
+            client.send(&[instruction], &[&signer]).await?;
+        }
+        ("migrate-config", Some(_)) => {
+            client.send(&[migrate_config(program_id, signer.pubkey())], &[&signer]).await?;
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let default_keypair = default_keypair_path();
+    let matches = App::new(crate_name!())
+        .about("Operate the loans program")
+        .version(crate_version!())
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .short("u")
+                .value_name("URL")
+                .takes_value(true)
+                .global(true)
+                .default_value("http://localhost:8899")
+                .validator(is_url)
+                .help("JSON RPC URL of the cluster, a local solana-test-validator by default"),
+        )
+        .arg(
+            Arg::with_name("keypair")
+                .long("keypair")
+                .short("k")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .default_value(&default_keypair)
+                .validator(is_keypair)
+                .help("Keypair file that signs and pays for the transactions"),
+        )
+        .arg(
+            Arg::with_name("program_id")
+                .long("program-id")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .global(true)
+                .required(true)
+                .validator(is_pubkey)
+                .help("The loans program"),
+        )
+        .subcommand(
+            SubCommand::with_name("create-loan")
+                .about("Create a loan account and request a loan in it")
+                .arg(pubkey_arg("mint", "mint", "The mint the loan is paid out in"))
+                .arg(pubkey_arg("receive", "receive", "The token account that receives the loan"))
+                .arg(amount_arg("The amount to borrow, in the smallest unit of the mint"))
+                .arg(
+                    Arg::with_name("duration")
+                        .long("duration")
+                        .value_name("HOURS")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The loan duration in hours, e.g. 168 for a week"),
+                )
+                .arg(
+                    Arg::with_name("max_interest_rate")
+                        .long("max-interest-rate")
+                        .value_name("BASIS_POINTS")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The highest annual interest rate accepted, in basis points"),
+                )
+                .arg(pubkey_arg("collateral_mint", "collateral-mint", "The mint the collateral has to be put up in")),
+        )
+        .subcommand(
+            SubCommand::with_name("guarantee-loan")
+                .about("Put up the collateral for a loan")
+                .arg(loan_arg())
+                .arg(pubkey_arg("collateral_source", "collateral-source", "The token account the collateral is taken from"))
+                .arg(pubkey_arg("repayment", "repayment", "The token account that receives the guarantor's share"))
+                .arg(pubkey_arg("collateral_mint", "collateral-mint", "The mint of the collateral"))
+                .arg(price_feed_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("accept-loan")
+                .about("Send a guaranteed loan to the borrower")
+                .arg(loan_arg())
+                .arg(pubkey_arg("transfer", "transfer", "The token account the loan is paid out of"))
+                .arg(pubkey_arg("repayment", "repayment", "The token account that receives the repayments")),
+        )
+        .subcommand(
+            SubCommand::with_name("repay-loan")
+                .about("Repay part or all of a loan")
+                .arg(loan_arg())
+                .arg(pubkey_arg("from", "from", "The token account the repayment is paid out of"))
+                .arg(amount_arg("The amount to repay, in the smallest unit of the mint"))
+                .arg(collateral_return_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("add-collateral")
+                .about("Move more collateral into the collateral vault of a loan")
+                .arg(loan_arg())
+                .arg(pubkey_arg("collateral_source", "collateral-source", "The token account the collateral is taken from"))
+                .arg(amount_arg("The amount of collateral to add, in the smallest unit of the mint")),
+        )
+        .subcommand(
+            SubCommand::with_name("release-collateral")
+                .about("Withdraw the collateral the outstanding amount of a loan no longer requires")
+                .arg(loan_arg())
+                .arg(pubkey_arg("collateral_return", "collateral-return", "The token account the collateral is returned to"))
+                .arg(price_feed_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("liquidate-loan")
+                .about("Repay part or all of an under-collateralized loan in exchange for collateral")
+                .arg(loan_arg())
+                .arg(pubkey_arg("from", "from", "The token account the repayment is paid out of"))
+                .arg(pubkey_arg("collateral_receive", "collateral-receive", "The token account that receives the collateral"))
+                .arg(amount_arg("The amount to repay, in the smallest unit of the mint"))
+                .arg(price_feed_arg())
+                .arg(collateral_return_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("cancel-loan")
+                .about("Withdraw a loan request")
+                .arg(loan_arg())
+                .arg(collateral_return_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("expire-loan")
+                .about("Expire a loan request that was not accepted in time")
+                .arg(loan_arg())
+                .arg(collateral_return_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("close-loan")
+                .about("Close a settled loan and take back the rent of its account")
+                .arg(loan_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("withdraw-repayment")
+                .about("Withdraw the repayments of a loan you lent")
+                .arg(loan_arg())
+                .arg(pubkey_arg("to", "to", "The token account the repayments are sent to")),
+        )
+        .subcommand(
+            SubCommand::with_name("show-loan")
+                .about("Show a decoded loan account, of any layout version")
+                .arg(loan_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("list-loans")
+                .about("List the loans of the program")
+                .arg(
+                    Arg::with_name("status")
+                        .long("status")
+                        .value_name("STATUS")
+                        .takes_value(true)
+                        .multiple(true)
+                        .validator(is_status)
+                        .help("Only list loans in this status, e.g. accepted"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("show-config").about("Show the program config"))
+        .subcommand(
+            SubCommand::with_name("init-config")
+                .about("Create the program config, the keypair becomes its admin")
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("update-config")
+                .about("Update the program config")
+                .args(&config_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-config")
+                .about("Close a program config created at an older size, so init-config can create it again"),
+        )
+        .get_matches();
+
+    if let Err(error) = process_command(&matches).await {
+        eprintln!("Error: {}", error);
+        exit(1);
+    }
+}
@@ -0,0 +1,559 @@
+//! An async client for the loan lifecycle, behind the `client` feature.
+//!
+//! `LoanClient` builds, signs and sends the transactions for each step of a loan.  The
+//! accounts and program derived addresses an instruction needs are worked out from the
+//! loan key and the loan state fetched from the cluster, so callers only pass in what
+//! the program cannot know, like the token accounts of a new party.
+//!
+//! It runs against anything that implements `LoanBackend`: `BanksClient` in program
+//! tests, or `RpcClient` against a cluster.
+
+use async_trait::async_trait;
+use solana_banks_client::BanksClient;
+use solana_client::{client_error::ClientError, rpc_client::RpcClient};
+use solana_program::{
+    hash::Hash,
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+    transport::TransportError,
+};
+use thiserror::Error;
+
+use crate::{
+    error::LoanError,
+    instruction::{
+        accept_loan,
+        add_collateral,
+        cancel_loan,
+        cancel_offer,
+        close_loan,
+        expire_loan,
+        guarantee_loan,
+        init_loan,
+        liquidate,
+        offer_loan,
+        release_collateral,
+        repay_loan,
+        take_offer,
+        withdraw_repayment,
+    },
+    state::{Loan, LoanAction, LoanOffer, LoanStatus},
+};
+
+#[derive(Error, Debug)]
+pub enum LoanClientError {
+    /// Banks client request failed
+    #[error("Banks Client Error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Banks client transaction failed
+    #[error("Transport Error: {0}")]
+    Transport(#[from] TransportError),
+    /// RPC client request failed
+    #[error("RPC Client Error: {0}")]
+    Rpc(#[from] ClientError),
+    /// The program or the loan state rejected the request
+    #[error("Program Error: {0}")]
+    Program(#[from] ProgramError),
+    /// Account Not Found
+    #[error("Account Not Found: {0}")]
+    AccountNotFound(Pubkey),
+    /// The collateral has to be returned, but there is no account to return it to
+    #[error("Collateral Return Account Required")]
+    CollateralReturnRequired,
+}
+
+/// The requests `LoanClient` makes to the cluster
+#[async_trait]
+pub trait LoanBackend {
+    async fn get_recent_blockhash(&mut self) -> Result<Hash, LoanClientError>;
+    async fn get_minimum_balance_for_rent_exemption(&mut self, data_len: usize) -> Result<u64, LoanClientError>;
+    async fn get_account(&mut self, pubkey: Pubkey) -> Result<Option<Account>, LoanClientError>;
+    async fn process_transaction(&mut self, transaction: Transaction) -> Result<(), LoanClientError>;
+}
+
+#[async_trait]
+impl LoanBackend for BanksClient {
+    async fn get_recent_blockhash(&mut self) -> Result<Hash, LoanClientError> {
+        Ok(BanksClient::get_recent_blockhash(self).await?)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(&mut self, data_len: usize) -> Result<u64, LoanClientError> {
+        Ok(self.get_rent().await?.minimum_balance(data_len))
+    }
+
+    async fn get_account(&mut self, pubkey: Pubkey) -> Result<Option<Account>, LoanClientError> {
+        Ok(BanksClient::get_account(self, pubkey).await?)
+    }
+
+    async fn process_transaction(&mut self, transaction: Transaction) -> Result<(), LoanClientError> {
+        Ok(BanksClient::process_transaction(self, transaction).await?)
+    }
+}
+
+/// `RpcClient` is blocking, so each request blocks the task it is awaited on until the
+/// cluster answers
+#[async_trait]
+impl LoanBackend for RpcClient {
+    async fn get_recent_blockhash(&mut self) -> Result<Hash, LoanClientError> {
+        let (recent_blockhash, _fee_calculator) = RpcClient::get_recent_blockhash(self)?;
+        Ok(recent_blockhash)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(&mut self, data_len: usize) -> Result<u64, LoanClientError> {
+        Ok(RpcClient::get_minimum_balance_for_rent_exemption(self, data_len)?)
+    }
+
+    async fn get_account(&mut self, pubkey: Pubkey) -> Result<Option<Account>, LoanClientError> {
+        Ok(self.get_account_with_commitment(&pubkey, self.commitment())?.value)
+    }
+
+    async fn process_transaction(&mut self, transaction: Transaction) -> Result<(), LoanClientError> {
+        self.send_and_confirm_transaction(&transaction)?;
+        Ok(())
+    }
+}
+
+pub struct LoanClient<B> {
+    pub backend: B,
+    pub program_id: Pubkey,
+    /// Pays the transaction fees and the rent of the accounts the client creates
+    pub payer: Keypair,
+}
+
+impl<B: LoanBackend> LoanClient<B> {
+    pub fn new(backend: B, program_id: Pubkey, payer: Keypair) -> Self {
+        LoanClient { backend, program_id, payer }
+    }
+
+    /// Sign the instructions with the payer and `signers`, and send them in a single
+    /// transaction
+    pub async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), LoanClientError> {
+        let recent_blockhash = self.backend.get_recent_blockhash().await?;
+        let mut transaction = Transaction::new_with_payer(instructions, Some(&self.payer.pubkey()));
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        transaction.sign(&all_signers, recent_blockhash);
+        self.backend.process_transaction(transaction).await
+    }
+
+    /// Fetch the loan state
+    pub async fn get_loan(&mut self, loan_pubkey: Pubkey) -> Result<Loan, LoanClientError> {
+        let account = self.backend
+            .get_account(loan_pubkey)
+            .await?
+            .ok_or(LoanClientError::AccountNotFound(loan_pubkey))?;
+        Ok(Loan::unpack(&account.data)?)
+    }
+
+    /// Fetch an offer to fund a loan
+    pub async fn get_offer(&mut self, offer_pubkey: Pubkey) -> Result<LoanOffer, LoanClientError> {
+        let account = self.backend
+            .get_account(offer_pubkey)
+            .await?
+            .ok_or(LoanClientError::AccountNotFound(offer_pubkey))?;
+        Ok(LoanOffer::unpack(&account.data)?)
+    }
+
+    /// Create a rent exempt loan account and request the loan in it, on the
+    /// borrower's terms.  Returns the key of the new loan account.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn init_loan(
+        &mut self,
+        initializer: &Keypair,
+        loan_mint_pubkey: Pubkey,
+        borrower_loan_receive_pubkey: Pubkey,
+        amount: u64,
+        duration: u32,
+        max_interest_rate: u32,
+        collateral_mint_pubkey: Pubkey,
+    ) -> Result<Pubkey, LoanClientError> {
+        let loan_account = Keypair::new();
+        let lamports = self.backend.get_minimum_balance_for_rent_exemption(Loan::LEN).await?;
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &loan_account.pubkey(),
+                lamports,
+                Loan::LEN as u64,
+                &self.program_id,
+            ),
+            init_loan(
+                self.program_id,
+                initializer.pubkey(),
+                loan_mint_pubkey,
+                borrower_loan_receive_pubkey,
+                loan_account.pubkey(),
+                amount,
+                duration,
+                max_interest_rate,
+                collateral_mint_pubkey,
+            ),
+        ];
+        self.send(&instructions, &[initializer, &loan_account]).await?;
+        Ok(loan_account.pubkey())
+    }
+
+    /// Put up the collateral for the loan.  The guarantor's share of the repayments
+    /// is paid into `guarantor_repayment_pubkey`.  Collateral that is not in the loan
+    /// mint is valued with the price feed at `price_feed_pubkey`.
+    pub async fn guarantee_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        guarantor: &Keypair,
+        collateral_source_pubkey: Pubkey,
+        guarantor_repayment_pubkey: Pubkey,
+        collateral_mint_pubkey: Pubkey,
+        price_feed_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let instruction = guarantee_loan(
+            self.program_id,
+            guarantor.pubkey(),
+            collateral_source_pubkey,
+            guarantor_repayment_pubkey,
+            loan_pubkey,
+            collateral_mint_pubkey,
+            price_feed_pubkey.into(),
+        );
+        self.send(&[instruction], &[guarantor]).await
+    }
+
+    /// Send the loan to the borrower.  Repayments are held in the loan's repayment
+    /// vault until they are withdrawn.
+    pub async fn accept_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        lender: &Keypair,
+        lender_loan_transfer_pubkey: Pubkey,
+        lender_repayment_pubkey: Pubkey,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        let instruction = accept_loan(
+            self.program_id,
+            lender.pubkey(),
+            lender_loan_transfer_pubkey,
+            lender_repayment_pubkey,
+            loan.borrower_loan_receive_pubkey,
+            loan_pubkey,
+            loan.loan_mint_pubkey,
+        );
+        self.send(&[instruction], &[lender]).await
+    }
+
+    /// Repay `amount` of the loan.  Repaying the whole outstanding amount hands the
+    /// collateral back, so `collateral_return_pubkey` is needed then.
+    pub async fn repay_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        payer: &Keypair,
+        payer_token_pubkey: Pubkey,
+        amount: u64,
+        collateral_return_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        // only accepted loans have somewhere to send the repayments
+        loan.status.transition(LoanAction::Repay)?;
+        let not_accepted = || ProgramError::from(LoanError::InvalidLoanStatus);
+        let guarantor_repayment_pubkey = loan.guarantor_repayment_pubkey.ok_or_else(not_accepted)?;
+        let lender_repayment_pubkey = loan.repayment_vault_pubkey
+            .or(loan.lender_repayment_pubkey)
+            .ok_or_else(not_accepted)?;
+        let guarantor_collateral_pubkey = if amount == loan.outstanding_amount() {
+            collateral_return_for(&loan, collateral_return_pubkey)?
+        } else {
+            COption::None
+        };
+        let instruction = repay_loan(
+            self.program_id,
+            payer.pubkey(),
+            payer_token_pubkey,
+            guarantor_repayment_pubkey,
+            lender_repayment_pubkey,
+            loan_pubkey,
+            loan.loan_mint_pubkey,
+            loan.guarantor_pubkey,
+            guarantor_collateral_pubkey,
+            amount,
+        );
+        self.send(&[instruction], &[payer]).await
+    }
+
+    /// Withdraw the loan request.  A guaranteed loan hands the collateral back, so
+    /// `collateral_return_pubkey` is needed then.
+    pub async fn cancel_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        initializer: &Keypair,
+        collateral_return_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        let guarantor_collateral_pubkey = if loan.status == LoanStatus::Guaranteed {
+            collateral_return_for(&loan, collateral_return_pubkey)?
+        } else {
+            COption::None
+        };
+        let instruction = cancel_loan(
+            self.program_id,
+            initializer.pubkey(),
+            loan_pubkey,
+            loan.guarantor_pubkey,
+            guarantor_collateral_pubkey,
+        );
+        self.send(&[instruction], &[initializer]).await
+    }
+
+    /// Expire a loan request that nobody accepted in time, `payer` only pays the fee.  A
+    /// guaranteed loan hands the collateral back, so `collateral_return_pubkey` is needed
+    /// then.
+    pub async fn expire_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        payer: &Keypair,
+        collateral_return_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        let guarantor_collateral_pubkey = if loan.status == LoanStatus::Guaranteed {
+            collateral_return_for(&loan, collateral_return_pubkey)?
+        } else {
+            COption::None
+        };
+        let instruction = expire_loan(
+            self.program_id,
+            loan_pubkey,
+            loan.guarantor_pubkey,
+            guarantor_collateral_pubkey,
+        );
+        self.send(&[instruction], &[payer]).await
+    }
+
+    /// Close a settled loan and take back the rent of its account
+    pub async fn close_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        initializer: &Keypair,
+    ) -> Result<(), LoanClientError> {
+        let instruction = close_loan(self.program_id, initializer.pubkey(), loan_pubkey);
+        self.send(&[instruction], &[initializer]).await
+    }
+
+    /// Send what the loan's repayment vault holds to `destination_pubkey`.  Once the
+    /// loan is settled the vault is closed too.
+    pub async fn withdraw_repayment(
+        &mut self,
+        loan_pubkey: Pubkey,
+        lender: &Keypair,
+        destination_pubkey: Pubkey,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        loan.status.transition(LoanAction::WithdrawRepayment)?;
+        let repayment_vault_pubkey = loan.repayment_vault_pubkey
+            .ok_or_else(|| ProgramError::from(LoanError::AccountMismatch))?;
+        let instruction = withdraw_repayment(
+            self.program_id,
+            lender.pubkey(),
+            destination_pubkey,
+            loan_pubkey,
+            repayment_vault_pubkey,
+        );
+        self.send(&[instruction], &[lender]).await
+    }
+
+    /// Create a rent exempt offer account and offer to fund the loan on the lender's
+    /// terms.  The loan amount is held in escrow until the offer is taken or cancelled.
+    /// Returns the key of the new offer account.
+    pub async fn offer_loan(
+        &mut self,
+        loan_pubkey: Pubkey,
+        lender: &Keypair,
+        lender_loan_transfer_pubkey: Pubkey,
+        lender_repayment_pubkey: Pubkey,
+        interest_rate: u32,
+        duration: u32,
+    ) -> Result<Pubkey, LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        let offer_account = Keypair::new();
+        let lamports = self.backend.get_minimum_balance_for_rent_exemption(LoanOffer::LEN).await?;
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer.pubkey(),
+                &offer_account.pubkey(),
+                lamports,
+                LoanOffer::LEN as u64,
+                &self.program_id,
+            ),
+            offer_loan(
+                self.program_id,
+                lender.pubkey(),
+                lender_loan_transfer_pubkey,
+                lender_repayment_pubkey,
+                loan_pubkey,
+                offer_account.pubkey(),
+                loan.loan_mint_pubkey,
+                interest_rate,
+                duration,
+            ),
+        ];
+        self.send(&instructions, &[lender, &offer_account]).await?;
+        Ok(offer_account.pubkey())
+    }
+
+    /// Take an offer, accepting the loan on the offered terms.  Collateral that is not in
+    /// the loan mint is valued with the price feed at `price_feed_pubkey`.
+    pub async fn take_offer(
+        &mut self,
+        offer_pubkey: Pubkey,
+        borrower: &Keypair,
+        price_feed_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let offer = self.get_offer(offer_pubkey).await?;
+        let loan = self.get_loan(offer.loan_pubkey).await?;
+        // only guaranteed loans can take an offer
+        loan.status.transition(LoanAction::Accept)?;
+        let collateral_mint_pubkey = loan.collateral_mint_pubkey
+            .ok_or_else(|| ProgramError::from(LoanError::InvalidLoanStatus))?;
+        let instruction = take_offer(
+            self.program_id,
+            borrower.pubkey(),
+            loan.borrower_loan_receive_pubkey,
+            offer.loan_pubkey,
+            offer_pubkey,
+            offer.lender_pubkey,
+            loan.loan_mint_pubkey,
+            collateral_mint_pubkey,
+            price_feed_pubkey.into(),
+        );
+        self.send(&[instruction], &[borrower]).await
+    }
+
+    /// Withdraw an offer, returning the offered funds to `lender_refund_pubkey`
+    pub async fn cancel_offer(
+        &mut self,
+        offer_pubkey: Pubkey,
+        lender: &Keypair,
+        lender_refund_pubkey: Pubkey,
+    ) -> Result<(), LoanClientError> {
+        let offer = self.get_offer(offer_pubkey).await?;
+        let instruction = cancel_offer(
+            self.program_id,
+            lender.pubkey(),
+            lender_refund_pubkey,
+            offer.loan_pubkey,
+            offer_pubkey,
+        );
+        self.send(&[instruction], &[lender]).await
+    }
+
+    /// Liquidate `amount` of an under-collateralized loan, taking the collateral into
+    /// `liquidator_collateral_pubkey`.  Liquidating the whole outstanding amount, or
+    /// emptying the vault, hands the rest of the collateral back, so
+    /// `collateral_return_pubkey` is needed then.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn liquidate(
+        &mut self,
+        loan_pubkey: Pubkey,
+        liquidator: &Keypair,
+        liquidator_token_pubkey: Pubkey,
+        liquidator_collateral_pubkey: Pubkey,
+        amount: u64,
+        price_feed_pubkey: Option<Pubkey>,
+        collateral_return_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        // only accepted loans can be liquidated
+        loan.status.transition(LoanAction::Liquidate)?;
+        let not_accepted = || ProgramError::from(LoanError::InvalidLoanStatus);
+        let guarantor_repayment_pubkey = loan.guarantor_repayment_pubkey.ok_or_else(not_accepted)?;
+        let lender_repayment_pubkey = loan.repayment_vault_pubkey
+            .or(loan.lender_repayment_pubkey)
+            .ok_or_else(not_accepted)?;
+        let collateral_mint_pubkey = loan.collateral_mint_pubkey.ok_or_else(not_accepted)?;
+        // a liquidation that empties the vault closes the loan too, so the guarantor
+        // accounts are passed whenever they are given
+        let guarantor_collateral_pubkey = match collateral_return_pubkey {
+            Some(collateral_return_pubkey) => COption::Some(collateral_return_pubkey),
+            None if amount == loan.outstanding_amount() => collateral_return_for(&loan, None)?,
+            None => COption::None,
+        };
+        let instruction = liquidate(
+            self.program_id,
+            liquidator.pubkey(),
+            liquidator_token_pubkey,
+            liquidator_collateral_pubkey,
+            loan_pubkey,
+            loan.loan_mint_pubkey,
+            collateral_mint_pubkey,
+            guarantor_repayment_pubkey,
+            lender_repayment_pubkey,
+            price_feed_pubkey.into(),
+            loan.guarantor_pubkey,
+            guarantor_collateral_pubkey,
+            amount,
+        );
+        self.send(&[instruction], &[liquidator]).await
+    }
+
+    /// Move `amount` more collateral from `collateral_source_pubkey` into the loan's
+    /// collateral vault
+    pub async fn add_collateral(
+        &mut self,
+        loan_pubkey: Pubkey,
+        depositor: &Keypair,
+        collateral_source_pubkey: Pubkey,
+        amount: u64,
+    ) -> Result<(), LoanClientError> {
+        let instruction = add_collateral(
+            self.program_id,
+            depositor.pubkey(),
+            collateral_source_pubkey,
+            loan_pubkey,
+            amount,
+        );
+        self.send(&[instruction], &[depositor]).await
+    }
+
+    /// Withdraw the collateral that the outstanding amount no longer requires into
+    /// `collateral_return_pubkey`
+    pub async fn release_collateral(
+        &mut self,
+        loan_pubkey: Pubkey,
+        guarantor: &Keypair,
+        collateral_return_pubkey: Pubkey,
+        price_feed_pubkey: Option<Pubkey>,
+    ) -> Result<(), LoanClientError> {
+        let loan = self.get_loan(loan_pubkey).await?;
+        // only guaranteed loans hold collateral
+        loan.status.transition(LoanAction::ReleaseCollateral)?;
+        let collateral_mint_pubkey = loan.collateral_mint_pubkey
+            .ok_or_else(|| ProgramError::from(LoanError::InvalidLoanStatus))?;
+        let instruction = release_collateral(
+            self.program_id,
+            guarantor.pubkey(),
+            collateral_return_pubkey,
+            loan_pubkey,
+            collateral_mint_pubkey,
+            price_feed_pubkey.into(),
+        );
+        self.send(&[instruction], &[guarantor]).await
+    }
+}
+
+/// The account the collateral is returned to, if the loan has collateral
+fn collateral_return_for(
+    loan: &Loan,
+    collateral_return_pubkey: Option<Pubkey>,
+) -> Result<COption<Pubkey>, LoanClientError> {
+    if loan.guarantor_pubkey.is_none() {
+        return Ok(COption::None);
+    }
+    collateral_return_pubkey
+        .map(COption::Some)
+        .ok_or(LoanClientError::CollateralReturnRequired)
+}
@@ -3,8 +3,10 @@
 #![cfg(not(feature = "no-entrypoint"))]
 
 use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult,
+    program_error::PrintProgramError, pubkey::Pubkey,
 };
+use crate::error::LoanError;
 
 entrypoint!(process_instruction);
 fn process_instruction(
@@ -12,5 +14,10 @@ fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    crate::processor::process_instruction(program_id, accounts, instruction_data)
+    if let Err(error) = crate::processor::process_instruction(program_id, accounts, instruction_data) {
+        // catch the error so we can print it
+        error.print::<LoanError>();
+        return Err(error);
+    }
+    Ok(())
 }
@@ -1,7 +1,12 @@
+use num_derive::FromPrimitive;
 use thiserror::Error;
-use solana_program::program_error::ProgramError;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 
-#[derive(Error, Debug, Copy, Clone)]
+#[derive(Error, Debug, Copy, Clone, FromPrimitive, PartialEq)]
 pub enum LoanError {
     /// Invalid instruction
     #[error("Invalid Instruction")]
@@ -12,6 +17,72 @@ pub enum LoanError {
     /// Not Rent Exempt
     #[error("Not Rent Exempt")]
     NotRentExempt,
+    /// Invalid program config
+    #[error("Invalid Program Config")]
+    InvalidConfig,
+    /// Math overflow
+    #[error("Math Overflow")]
+    MathOverflow,
+    /// Token account balance is too low
+    #[error("Insufficient Token Balance")]
+    InsufficientTokenBalance,
+    /// Token account is frozen
+    #[error("Token Account Frozen")]
+    TokenAccountFrozen,
+    /// Token account is not owned by the expected account
+    #[error("Token Account Owner Mismatch")]
+    TokenOwnerMismatch,
+    /// Token account or mint does not match the loan
+    #[error("Token Mint Mismatch")]
+    TokenMintMismatch,
+    /// Token account has a delegate
+    #[error("Token Account Has Delegate")]
+    TokenAccountDelegated,
+    /// Token account has a close authority
+    #[error("Token Account Has Close Authority")]
+    TokenAccountCloseAuthority,
+    /// The loan is not in the status the instruction requires
+    #[error("Invalid Loan Status")]
+    InvalidLoanStatus,
+    /// The collateral token account holds less than the loan amount
+    #[error("Insufficient Collateral")]
+    InsufficientCollateral,
+    /// An account is not the one recorded on the loan for its role
+    #[error("Account Does Not Match The Loan")]
+    AccountMismatch,
+    /// The amount is zero or more than the loan allows
+    #[error("Invalid Amount")]
+    InvalidAmount,
+    /// The loan is not yet due
+    #[error("Loan Not Due")]
+    LoanNotDue,
+    /// The loan has expired
+    #[error("Loan Expired")]
+    LoanExpired,
+    /// An account the instruction writes to was passed as read-only
+    #[error("Account Not Writable")]
+    AccountNotWritable,
+    /// The requested loan duration is outside what the program allows
+    #[error("Invalid Loan Duration")]
+    InvalidDuration,
+    /// The interest rate is higher than the borrower accepts
+    #[error("Interest Rate Too High")]
+    InterestRateTooHigh,
+    /// The price feed has not been updated recently enough to be used
+    #[error("Stale Price")]
+    StalePrice,
+    /// The price feed does not have a usable price
+    #[error("Invalid Price")]
+    InvalidPrice,
+    /// The collateral still covers the loan above the maintenance ratio
+    #[error("Loan Not Liquidatable")]
+    NotLiquidatable,
+    /// The loan request is still open for a guarantor and a lender
+    #[error("Loan Request Not Expired")]
+    RequestNotExpired,
+    /// The repayment vault still has to be withdrawn and closed by the lender
+    #[error("Repayment Not Withdrawn")]
+    RepaymentNotWithdrawn,
 }
 
 impl From<LoanError> for ProgramError {
@@ -19,3 +90,18 @@ impl From<LoanError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+impl<T> DecodeError<T> for LoanError {
+    fn type_of() -> &'static str {
+        "LoanError"
+    }
+}
+
+impl PrintProgramError for LoanError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + num_traits::FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
@@ -0,0 +1,179 @@
+//! Structured events logged on every loan state change.
+//!
+//! An event is logged as `EVENT_PREFIX` followed by its base64 encoded Borsh bytes.
+//! Indexers get them back out of the transaction logs with `parse_events`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{msg, pubkey::Pubkey};
+
+use crate::state::LoanStatus;
+
+/// Marks a log line as a loan event
+pub const EVENT_PREFIX: &str = "loans254-event:";
+
+/// How the runtime prefixes the lines a program logs
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// New variants are only ever appended, so existing events keep decoding.
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum LoanEvent {
+    /// The borrower requested a loan
+    LoanInitialized {
+        loan: Pubkey,
+        initializer: Pubkey,
+        loan_mint: Pubkey,
+        expected_amount: u64,
+        amount: u64,
+        interest_rate: u32,
+        duration: u32,
+        status: LoanStatus,
+    },
+    /// The guarantor put up the collateral
+    LoanGuaranteed {
+        loan: Pubkey,
+        guarantor: Pubkey,
+        collateral_mint: Pubkey,
+        collateral_amount: u64,
+        status: LoanStatus,
+    },
+    /// The lender sent the loan to the borrower, less the application fee
+    LoanAccepted {
+        loan: Pubkey,
+        lender: Pubkey,
+        amount: u64,
+        application_fee: u64,
+        due_at: i64,
+        status: LoanStatus,
+    },
+    /// Part or all of the loan was repaid
+    LoanRepaid {
+        loan: Pubkey,
+        payer: Pubkey,
+        amount: u64,
+        amount_repaid: u64,
+        outstanding_amount: u64,
+        status: LoanStatus,
+    },
+    /// The borrower withdrew the loan request
+    LoanCancelled {
+        loan: Pubkey,
+        initializer: Pubkey,
+        status: LoanStatus,
+    },
+    /// The lender claimed the collateral of an overdue loan
+    LoanDefaulted {
+        loan: Pubkey,
+        lender: Pubkey,
+        collateral_amount: u64,
+        status: LoanStatus,
+    },
+    /// A version 1 loan was copied into a new account in the current layout
+    LoanMigrated {
+        loan: Pubkey,
+        old_loan: Pubkey,
+        due_at: i64,
+        status: LoanStatus,
+    },
+    /// A lender offered to fund the loan on their own terms
+    LoanOffered {
+        loan: Pubkey,
+        offer: Pubkey,
+        lender: Pubkey,
+        interest_rate: u32,
+        duration: u32,
+        amount: u64,
+    },
+    /// A lender withdrew their offer and got the offered funds back
+    LoanOfferCancelled {
+        loan: Pubkey,
+        offer: Pubkey,
+        lender: Pubkey,
+    },
+    /// A keeper repaid part of an under-collateralized loan and took collateral for it
+    LoanLiquidated {
+        loan: Pubkey,
+        liquidator: Pubkey,
+        amount: u64,
+        collateral_amount: u64,
+        outstanding_amount: u64,
+        status: LoanStatus,
+    },
+    /// More collateral was moved into the collateral vault
+    CollateralAdded {
+        loan: Pubkey,
+        depositor: Pubkey,
+        amount: u64,
+        collateral_amount: u64,
+    },
+    /// The guarantor withdrew the collateral beyond what the outstanding amount requires
+    CollateralReleased {
+        loan: Pubkey,
+        guarantor: Pubkey,
+        amount: u64,
+        collateral_amount: u64,
+    },
+    /// Nobody accepted the loan request in time
+    LoanExpired {
+        loan: Pubkey,
+        status: LoanStatus,
+    },
+    /// The borrower closed a settled loan and took back the rent of its account
+    LoanClosed {
+        loan: Pubkey,
+        initializer: Pubkey,
+        status: LoanStatus,
+    },
+    /// The lender withdrew the repayments held in the repayment vault
+    RepaymentWithdrawn {
+        loan: Pubkey,
+        lender: Pubkey,
+        amount: u64,
+        status: LoanStatus,
+    },
+}
+
+impl LoanEvent {
+    /// Log the event
+    pub fn emit(&self) {
+        let data = self.try_to_vec().expect("try_to_vec");
+        msg!("{}{}", EVENT_PREFIX, base64::encode(data));
+    }
+
+    /// Decode the event in a logged message, if there is one
+    pub fn from_log_message(message: &str) -> Option<Self> {
+        let payload = message.strip_prefix(EVENT_PREFIX)?;
+        let data = base64::decode(payload).ok()?;
+        Self::try_from_slice(&data).ok()
+    }
+}
+
+/// Recover the events from the log messages of a transaction, in the order they were
+/// logged.
+///
+/// Only messages logged while `program_id` itself was executing are decoded, so other
+/// programs in the transaction cannot forge events by logging the prefix.
+pub fn parse_events<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<LoanEvent> {
+    let program_id = program_id.to_string();
+    let mut invocations: Vec<&str> = vec![];
+    let mut events = vec![];
+    for log in logs {
+        let log = log.as_ref();
+        if let Some(message) = log.strip_prefix(PROGRAM_LOG_PREFIX) {
+            if invocations.last() == Some(&program_id.as_str()) {
+                events.extend(LoanEvent::from_log_message(message));
+            }
+        } else if let Some(rest) = log.strip_prefix("Program ") {
+            // "Program <id> invoke [<depth>]", then "Program <id> success" or
+            // "Program <id> failed: <error>" once it returns
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(id), Some("invoke")) => invocations.push(id),
+                (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                    invocations.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    events
+}
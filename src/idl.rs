@@ -0,0 +1,178 @@
+//! A JSON description of the program interface for client code generators.
+//!
+//! Instruction arguments come from the Borsh schema of `LoanInstruction`, the accounts
+//! from the per-instruction account lists and the errors from `LoanError`, so the IDL
+//! follows the program without being maintained by hand.
+
+use std::collections::HashMap;
+use borsh::{
+    schema::{Declaration, Definition, Fields},
+    BorshSchema,
+};
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+
+use crate::{
+    accounts::{
+        AcceptLoanAccounts,
+        AccountFlag,
+        AddCollateralAccounts,
+        CancelLoanAccounts,
+        CancelOfferAccounts,
+        CheckHealthAccounts,
+        ClaimDefaultAccounts,
+        CloseLoanAccounts,
+        CollateralPriceAccounts,
+        ExpireLoanAccounts,
+        GuaranteeLoanAccounts,
+        InitConfigAccounts,
+        InitLoanAccounts,
+        InitTreasuryAccounts,
+        LiquidateAccounts,
+        MigrateConfigAccounts,
+        MigrateCollateralAccounts,
+        MigrateLoanAccounts,
+        MigrateRepaymentAccounts,
+        OfferLoanAccounts,
+        PriceFeedAccounts,
+        ReleaseCollateralAccounts,
+        RepayLoanAccounts,
+        ReturnCollateralAccounts,
+        TakeOfferAccounts,
+        UpdateConfigAccounts,
+        WithdrawRepaymentAccounts,
+        WithdrawTreasuryAccounts,
+    },
+    error::LoanError,
+    instruction::{LoanInstruction, INSTRUCTION_VERSION},
+};
+
+type AccountList = &'static [(&'static str, &'static [AccountFlag])];
+
+/// `Liquidate` takes the price feed when the collateral is in a different mint from the
+/// loan, then the accounts to return what is left of the collateral once the loan closes
+const LIQUIDATE_OPTIONAL_ACCOUNTS: AccountList = &[
+    PriceFeedAccounts::<()>::ACCOUNTS[0],
+    ReturnCollateralAccounts::<()>::ACCOUNTS[0],
+    ReturnCollateralAccounts::<()>::ACCOUNTS[1],
+    ReturnCollateralAccounts::<()>::ACCOUNTS[2],
+    ReturnCollateralAccounts::<()>::ACCOUNTS[3],
+];
+
+/// `MigrateLoan` takes the accounts to move the collateral when the loan has been
+/// guaranteed, then the lender's repayment account when it has also been accepted
+const MIGRATE_LOAN_OPTIONAL_ACCOUNTS: AccountList = &[
+    MigrateCollateralAccounts::<()>::ACCOUNTS[0],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[1],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[2],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[3],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[4],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[5],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[6],
+    MigrateCollateralAccounts::<()>::ACCOUNTS[7],
+    MigrateRepaymentAccounts::<()>::ACCOUNTS[0],
+];
+
+/// The accounts an instruction always needs, and the ones it only needs sometimes
+fn instruction_accounts(name: &str) -> (AccountList, AccountList) {
+    match name {
+        "InitLoan" => (InitLoanAccounts::<()>::ACCOUNTS, &[]),
+        "GuaranteeLoan" => (GuaranteeLoanAccounts::<()>::ACCOUNTS, CollateralPriceAccounts::<()>::ACCOUNTS),
+        "AcceptLoan" => (AcceptLoanAccounts::<()>::ACCOUNTS, &[]),
+        "RepayLoan" => (RepayLoanAccounts::<()>::ACCOUNTS, ReturnCollateralAccounts::<()>::ACCOUNTS),
+        "CancelLoan" => (CancelLoanAccounts::<()>::ACCOUNTS, ReturnCollateralAccounts::<()>::ACCOUNTS),
+        "ClaimDefault" => (ClaimDefaultAccounts::<()>::ACCOUNTS, &[]),
+        "InitConfig" => (InitConfigAccounts::<()>::ACCOUNTS, &[]),
+        "UpdateConfig" => (UpdateConfigAccounts::<()>::ACCOUNTS, &[]),
+        "InitTreasury" => (InitTreasuryAccounts::<()>::ACCOUNTS, &[]),
+        "WithdrawTreasury" => (WithdrawTreasuryAccounts::<()>::ACCOUNTS, &[]),
+        "MigrateLoan" => (MigrateLoanAccounts::<()>::ACCOUNTS, MIGRATE_LOAN_OPTIONAL_ACCOUNTS),
+        "OfferLoan" => (OfferLoanAccounts::<()>::ACCOUNTS, &[]),
+        "TakeOffer" => (TakeOfferAccounts::<()>::ACCOUNTS, PriceFeedAccounts::<()>::ACCOUNTS),
+        "CancelOffer" => (CancelOfferAccounts::<()>::ACCOUNTS, &[]),
+        "CheckHealth" => (CheckHealthAccounts::<()>::ACCOUNTS, CollateralPriceAccounts::<()>::ACCOUNTS),
+        "Liquidate" => (LiquidateAccounts::<()>::ACCOUNTS, LIQUIDATE_OPTIONAL_ACCOUNTS),
+        "AddCollateral" => (AddCollateralAccounts::<()>::ACCOUNTS, &[]),
+        "ReleaseCollateral" => (ReleaseCollateralAccounts::<()>::ACCOUNTS, CollateralPriceAccounts::<()>::ACCOUNTS),
+        "ExpireLoan" => (ExpireLoanAccounts::<()>::ACCOUNTS, ReturnCollateralAccounts::<()>::ACCOUNTS),
+        "CloseLoan" => (CloseLoanAccounts::<()>::ACCOUNTS, &[]),
+        "MigrateConfig" => (MigrateConfigAccounts::<()>::ACCOUNTS, &[]),
+        "WithdrawRepayment" => (WithdrawRepaymentAccounts::<()>::ACCOUNTS, &[]),
+        _ => panic!("No account list for instruction {}", name),
+    }
+}
+
+fn accounts_idl(accounts: AccountList, is_optional: bool) -> Vec<Value> {
+    accounts
+        .iter()
+        .map(|(name, flags)| {
+            json!({
+                "name": name,
+                "isSigner": flags.contains(&AccountFlag::Signer),
+                "isWritable": flags.contains(&AccountFlag::Writable),
+                "isOptional": is_optional,
+            })
+        })
+        .collect()
+}
+
+fn fields_idl(definitions: &HashMap<Declaration, Definition>, declaration: &str) -> Vec<Value> {
+    match definitions.get(declaration) {
+        Some(Definition::Struct { fields: Fields::NamedFields(fields) }) => fields
+            .iter()
+            .map(|(name, declaration)| json!({ "name": name, "type": declaration }))
+            .collect(),
+        Some(Definition::Struct { fields: Fields::UnnamedFields(fields) }) => fields
+            .iter()
+            .enumerate()
+            .map(|(index, declaration)| json!({ "name": index.to_string(), "type": declaration }))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn errors_idl() -> Vec<Value> {
+    let mut errors = vec![];
+    let mut code = 0;
+    while let Some(error) = LoanError::from_u32(code) {
+        errors.push(json!({
+            "code": code,
+            "name": format!("{:?}", error),
+            "msg": error.to_string(),
+        }));
+        code += 1;
+    }
+    errors
+}
+
+/// Generate the IDL of the program
+pub fn generate_idl() -> Value {
+    let container = LoanInstruction::schema_container();
+    let variants = match container.definitions.get(&container.declaration) {
+        Some(Definition::Enum { variants }) => variants,
+        _ => panic!("LoanInstruction has no enum schema"),
+    };
+    let instructions: Vec<Value> = variants
+        .iter()
+        .enumerate()
+        .map(|(index, (name, declaration))| {
+            let (accounts, optional_accounts) = instruction_accounts(name);
+            let mut instruction_accounts = accounts_idl(accounts, false);
+            instruction_accounts.extend(accounts_idl(optional_accounts, true));
+            json!({
+                "name": name,
+                "index": index,
+                "args": fields_idl(&container.definitions, declaration),
+                "accounts": instruction_accounts,
+            })
+        })
+        .collect();
+
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "instructionVersion": INSTRUCTION_VERSION,
+        "instructions": instructions,
+        "errors": errors_idl(),
+    })
+}
@@ -1,13 +1,77 @@
-use std::convert::TryInto;
 use solana_program::{
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
+    program_option::COption,
     pubkey::Pubkey,
+    system_program,
     sysvar,
 };
 use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
 
+use crate::accounts::{
+    AcceptLoanAccounts,
+    AddCollateralAccounts,
+    CancelLoanAccounts,
+    CancelOfferAccounts,
+    CheckHealthAccounts,
+    ClaimDefaultAccounts,
+    CloseLoanAccounts,
+    CollateralPriceAccounts,
+    ExpireLoanAccounts,
+    GuaranteeLoanAccounts,
+    InitConfigAccounts,
+    InitLoanAccounts,
+    InitTreasuryAccounts,
+    LiquidateAccounts,
+    MigrateConfigAccounts,
+    MigrateCollateralAccounts,
+    MigrateLoanAccounts,
+    MigrateRepaymentAccounts,
+    OfferLoanAccounts,
+    PriceFeedAccounts,
+    ReleaseCollateralAccounts,
+    RepayLoanAccounts,
+    ReturnCollateralAccounts,
+    TakeOfferAccounts,
+    UpdateConfigAccounts,
+    WithdrawRepaymentAccounts,
+    WithdrawTreasuryAccounts,
+};
 use crate::error::LoanError::InvalidInstruction;
+use crate::utils::{
+    find_collateral_vault_address,
+    find_config_address,
+    find_legacy_loan_authority_address,
+    find_loan_authority_address,
+    find_offer_vault_address,
+    find_program_data_address,
+    find_repayment_vault_address,
+    find_treasury_address,
+};
+
+/// The version of the instruction encoding, the first byte of the instruction data.
+///
+/// The rest of the data is the Borsh encoded `LoanInstruction`, whose first byte is the
+/// index of the variant.  New variants are only ever appended, so adding one does not
+/// change how existing instructions are encoded.  Changing the fields of an existing
+/// variant needs a new version, and `unpack` keeps decoding the older ones where it can.
+///
+/// Version 2 added the borrower's terms to `InitLoan`, version 3 the collateral ratio to
+/// `InitConfig` and `UpdateConfig`, version 4 the liquidation terms to both, version 5
+/// the oracle program to both, version 6 the oracle publisher to both.
+pub const INSTRUCTION_VERSION: u8 = 6;
+/// The variant indexes of the instructions whose fields changed
+const INIT_LOAN_TAG: u8 = 0;
+const INIT_CONFIG_TAG: u8 = 6;
+const UPDATE_CONFIG_TAG: u8 = 7;
+/// The variants each version after the first changed, starting with version 2
+const CHANGED_TAGS: [&[u8]; 5] = [
+    &[INIT_LOAN_TAG],
+    &[INIT_CONFIG_TAG, UPDATE_CONFIG_TAG],
+    &[INIT_CONFIG_TAG, UPDATE_CONFIG_TAG],
+    &[INIT_CONFIG_TAG, UPDATE_CONFIG_TAG],
+    &[INIT_CONFIG_TAG, UPDATE_CONFIG_TAG],
+];
 
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize, BorshSchema, PartialEq)]
 pub enum LoanInstruction {
@@ -22,9 +86,17 @@ pub enum LoanInstruction {
     /// 2. `[]` The initializer's token account for the token they will receive should the loan go through
     /// 3. `[writable]` The loan account, it will hold all necessary info about the loan.  Owned by the program
     /// 4. `[]` The rent sysvar
+    /// 5. `[]` The program config
+    /// 6. `[]` The clock sysvar
     InitLoan {
         /// The amount party A expects to receive as a loan of token Y
-        amount: u64
+        amount: u64,
+        /// The loan duration in hours, between `MIN_DURATION` and the program config duration
+        duration: u32,
+        /// The highest annual interest rate the borrower accepts, in basis points
+        max_interest_rate: u32,
+        /// The mint the collateral has to be put up in
+        collateral_mint: Pubkey,
     },
     /// Guarantee a loan
     ///
@@ -32,75 +104,515 @@ pub enum LoanInstruction {
     ///
     /// Basically meant to be a mechanism through which collateral is provided for a loan
     /// This could be by a third party of by the borrower
+    /// The collateral is moved into a vault token account created by the program for this loan
     ///
-    /// 0. `[signer]` The account of the person guaranteeing the loan
+    /// 0. `[writable, signer]` The account of the person guaranteeing the loan, pays for the vault
     /// 1. `[writable]` Token account that holds the collateral.  Should be owned by guarantor
-    /// 2. `[writable]` Token account to which the guarantor's payment should be sent.
+    /// 2. `[]` Token account to which the guarantor's payment should be sent.
     /// 3. `[writable]` The loan account, has information about the loan
-    /// 4. `[]` The rent sysvar
-    /// 5. `[]` The token program
+    /// 4. `[writable]` The collateral vault, a PDA derived from the loan account
+    /// 5. `[]` The loan authority, a PDA derived from the loan account
+    /// 6. `[]` The collateral mint
+    /// 7. `[]` The rent sysvar
+    /// 8. `[]` The system program
+    /// 9. `[]` The token program
+    /// 10. `[]` The clock sysvar
+    ///
+    /// Collateral in the loan mint covers the loan one for one.  Collateral in any other
+    /// mint is valued in the loan mint at the oracle price, and also needs:
+    ///
+    /// 11. `[]` The program config, names the oracle program and the publisher of the price feed
+    /// 12. `[]` The price feed of the collateral mint in the loan mint
     GuaranteeLoan,
     /// Accept the loan
     ///
     /// Accounts expected:
     ///
-    /// Basically, sends money to the borrower, from the lender
-    /// 0. `[signer]` The account of the person lending the money
+    /// Basically, sends money to the borrower, from the lender.  Repayments are held in
+    /// a repayment vault the lender withdraws them from, so the lender's own token
+    /// account cannot stop the loan from being repaid
+    /// 0. `[writable, signer]` The account of the person lending the money, pays for the repayment vault
     /// 1. `[writable]` Token account that whose funds will be transferred to borrower
-    /// 2. `[writable]` The lender's token account for the token they will receive should when loan is repaid
+    /// 2. `[]` The lender's token account for the token they will receive should when loan is repaid
     /// 3. `[writable]` The borrower's token account to receive the borrowed loan amount
     /// 4. `[writable]` The loan account, has information about the loan
     /// 5. `[]` The rent sysvar
     /// 6. `[]` The token program
+    /// 7. `[]` The clock sysvar
+    /// 8. `[]` The program config
+    /// 9. `[writable]` The treasury token account for the loan mint, receives the application fee
+    /// 10. `[writable]` The repayment vault, a PDA derived from the loan account
+    /// 11. `[]` The loan authority, a PDA derived from the loan account
+    /// 12. `[]` The loan mint
+    /// 13. `[]` The system program
     AcceptLoan,
-    /// Repay the loan
+    /// Repay the loan, in full or in installments
+    ///
+    /// Each payment is split between the lender and the guarantor in proportion to the
+    /// interest and principal it covers.  The collateral is only returned to the guarantor
+    /// once the whole loan amount has been repaid.
     ///
     /// Accounts expected:
     ///
     /// 0. `[signer]` The account of the person repaying the loan
     /// 1. `[writable]` The payer's token account that has the funds being repaid
-    /// 2. `[writable]` The guarantor's account
-    /// 3. `[writable]` The collateral account to be returned to guarantor
-    /// 4. `[writable]` The guarantor's token account to be returned to guarantor
-    /// 5. `[writable]` The lender's account
-    /// 6. `[writable]` The lender's token account that will receive the repaid loan
-    /// 7. `[writable]` The loan account, has information about the loan
-    /// 8. `[]` The PDA account
+    /// 2. `[writable]` The guarantor's token account that will receive their share
+    /// 3. `[writable]` The loan's repayment vault, or the lender's token account for loans without one
+    /// 4. `[writable]` The loan account, has information about the loan
+    /// 5. `[]` The program config
+    /// 6. `[writable]` The treasury token account for the loan mint, receives the program share
+    /// 7. `[]` The token program
+    /// 8. `[]` The clock sysvar
+    ///
+    /// Only required for the payment that completes the loan:
+    ///
+    /// 9. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 10. `[writable]` The guarantor's token account that the collateral is returned to
+    /// 11. `[writable]` The collateral vault
+    /// 12. `[]` The loan authority
+    RepayLoan {
+        /// The amount being repaid, at most the outstanding loan amount
+        amount: u64
+    },
+    /// Cancel the loan request
+    ///
+    /// Accounts expected:
+    ///
+    /// Only the borrower can cancel, and only before a lender has accepted the loan.
+    /// If the loan has been guaranteed, the collateral is handed back to the guarantor.
+    ///
+    /// 0. `[signer]` The account of the person who initialized the loan
+    /// 1. `[writable]` The loan account, has information about the loan
+    /// 2. `[]` The clock sysvar
+    /// 3. `[]` The token program
+    ///
+    /// Only required if the loan has been guaranteed:
+    ///
+    /// 4. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 5. `[writable]` The guarantor's token account that the collateral is returned to
+    /// 6. `[writable]` The collateral vault
+    /// 7. `[]` The loan authority
+    CancelLoan,
+    /// Claim the collateral of a loan that was not repaid in time
+    ///
+    /// Accounts expected:
+    ///
+    /// Only the lender can claim, and only once the loan duration has elapsed since
+    /// the loan was accepted.  The collateral is sent to a lender token account of the collateral mint.
+    ///
+    /// 0. `[signer]` The account of the person who lent the money
+    /// 1. `[writable]` The lender's token account that will receive the collateral
+    /// 2. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 3. `[writable]` The loan account, has information about the loan
+    /// 4. `[writable]` The collateral vault
+    /// 5. `[]` The loan authority
+    /// 6. `[]` The token program
+    /// 7. `[]` The clock sysvar
+    ClaimDefault,
+    /// Create the program config, which holds the loan pricing
+    ///
+    /// Accounts expected:
+    ///
+    /// Should be called right after the program is deployed.  Only the upgrade authority
+    /// of the program can sign, and it becomes the admin
+    ///
+    /// 0. `[writable, signer]` The admin account, pays for the config account
+    /// 1. `[writable]` The program config, a PDA derived from the program id
+    /// 2. `[]` The rent sysvar
+    /// 3. `[]` The system program
+    /// 4. `[]` The program data account of the program, owned by the upgradeable loader
+    InitConfig {
+        /// The loan interest rate annualized in basis points, e.g. 900 represents 9/100
+        interest_rate: u32,
+        /// The longest loan duration a borrower can request, in hours
+        duration: u32,
+        /// The share of the loan interest kept by the program in basis points, e.g. 100 represents 1/100
+        processing_fee: u32,
+        /// The loan application fee in basis points, e.g. 10 represents 10/10000
+        application_fee: u32,
+        /// The guarantor's share of the interest in basis points, e.g. 5000 represents 50/100
+        guarantor_share: u32,
+        /// The lender's share of the interest in basis points, e.g. 5000 represents 50/100
+        lender_share: u32,
+        /// The value of collateral required as a share of the loan amount in basis points, e.g. 15000 represents 150/100
+        collateral_ratio: u32,
+        /// The collateral ratio below which anyone can liquidate the loan in basis points, 0 turns liquidations off
+        maintenance_ratio: u32,
+        /// The extra collateral a liquidator receives on top of the value they repay in basis points, e.g. 500 represents 5/100
+        liquidation_bonus: u32,
+        /// The program whose price feeds value collateral in a different mint from the loan
+        oracle_program_id: Pubkey,
+        /// The publisher whose price feeds are trusted, the default pubkey for none
+        oracle_publisher: Pubkey,
+    },
+    /// Update the loan pricing in the program config
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin account
+    /// 1. `[writable]` The program config
+    UpdateConfig {
+        /// The loan interest rate annualized in basis points, e.g. 900 represents 9/100
+        interest_rate: u32,
+        /// The longest loan duration a borrower can request, in hours
+        duration: u32,
+        /// The share of the loan interest kept by the program in basis points, e.g. 100 represents 1/100
+        processing_fee: u32,
+        /// The loan application fee in basis points, e.g. 10 represents 10/10000
+        application_fee: u32,
+        /// The guarantor's share of the interest in basis points, e.g. 5000 represents 50/100
+        guarantor_share: u32,
+        /// The lender's share of the interest in basis points, e.g. 5000 represents 50/100
+        lender_share: u32,
+        /// The value of collateral required as a share of the loan amount in basis points, e.g. 15000 represents 150/100
+        collateral_ratio: u32,
+        /// The collateral ratio below which anyone can liquidate the loan in basis points, 0 turns liquidations off
+        maintenance_ratio: u32,
+        /// The extra collateral a liquidator receives on top of the value they repay in basis points, e.g. 500 represents 5/100
+        liquidation_bonus: u32,
+        /// The program whose price feeds value collateral in a different mint from the loan
+        oracle_program_id: Pubkey,
+        /// The publisher whose price feeds are trusted, the default pubkey for none
+        oracle_publisher: Pubkey,
+    },
+    /// Create the treasury token account that collects the program's fees in a mint
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The admin account, pays for the treasury account
+    /// 1. `[]` The program config, owns the treasury account
+    /// 2. `[writable]` The treasury token account, a PDA derived from the mint
+    /// 3. `[]` The mint
+    /// 4. `[]` The rent sysvar
+    /// 5. `[]` The system program
+    /// 6. `[]` The token program
+    InitTreasury,
+    /// Withdraw collected fees from a treasury token account
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The admin account
+    /// 1. `[]` The program config
+    /// 2. `[writable]` The treasury token account
+    /// 3. `[writable]` The token account to send the fees to
+    /// 4. `[]` The token program
+    WithdrawTreasury {
+        /// The amount to withdraw
+        amount: u64
+    },
+    /// Move a version 1 loan account into the current account layout
+    ///
+    /// Accounts expected:
+    ///
+    /// Accounts cannot be resized, and nothing can sign to create the loan account again
+    /// at its address, so the loan is copied into a new account created by the caller
+    /// with `Loan::LEN` bytes and owned by the program.  The old account is
+    /// closed and its rent returned to the borrower.  Version 1 did not record when the
+    /// loan was made, so its request runs from the migration.  Nor did it record when the
+    /// loan was accepted, so an accepted loan is due from the migration on rather than
+    /// getting a new term, and the lender can claim the collateral unless it is repaid.
+    ///
+    /// A guaranteed or accepted version 1 loan holds the guarantor's collateral account
+    /// and the repayment accounts under the version 1 loan authority.  The collateral is
+    /// moved into the collateral vault of the new loan account, and each token account
+    /// is handed back to the guarantor or lender it belongs to.  Any party of the loan
+    /// can migrate it, so the guarantor and lender can get at those tokens without the
+    /// borrower.
+    ///
+    /// 0. `[writable, signer]` The borrower, guarantor or lender of the loan, pays for the collateral vault
+    /// 1. `[writable]` The account of the person who initialized the loan, receives the old account's rent
+    /// 2. `[writable]` The version 1 loan account
+    /// 3. `[writable]` The new loan account
+    /// 4. `[]` The loan mint
+    /// 5. `[]` The rent sysvar
+    /// 6. `[]` The clock sysvar
+    ///
+    /// Only required if the loan has been guaranteed:
+    ///
+    /// 7. `[]` The version 1 loan authority
+    /// 8. `[writable]` The guarantor's token account that the version 1 loan holds the collateral in
+    /// 9. `[writable]` The guarantor's repayment token account
+    /// 10. `[writable]` The collateral vault of the new loan account
+    /// 11. `[]` The collateral mint
+    /// 12. `[]` The loan authority of the new loan account
+    /// 13. `[]` The system program
+    /// 14. `[]` The token program
+    ///
+    /// Only required if the loan has been accepted:
+    ///
+    /// 15. `[writable]` The lender's repayment token account
+    MigrateLoan,
+    /// Offer to fund a guaranteed loan on the lender's own terms
+    ///
+    /// Accounts expected:
+    ///
+    /// The offer account is created by the caller with `LoanOffer::LEN` bytes and owned
+    /// by the program.  The loan's expected amount is moved into a vault derived from
+    /// the offer account, where it waits until the borrower takes the offer or the
+    /// lender cancels it.  A loan can have any number of offers.
+    ///
+    /// 0. `[writable, signer]` The account of the person making the offer, pays for the offer vault
+    /// 1. `[writable]` Token account that the offered funds are taken from.  Should be owned by the lender
+    /// 2. `[]` Token account that repayments should be sent to if the offer is taken
+    /// 3. `[]` The loan account
+    /// 4. `[writable]` The offer account
+    /// 5. `[writable]` The offer vault, a PDA derived from the offer account
+    /// 6. `[]` The loan authority, a PDA derived from the loan account
+    /// 7. `[]` The loan mint
+    /// 8. `[]` The program config
+    /// 9. `[]` The rent sysvar
+    /// 10. `[]` The system program
+    /// 11. `[]` The token program
+    /// 12. `[]` The clock sysvar
+    OfferLoan {
+        /// The offered interest rate annualized in basis points, at most the borrower's maximum
+        interest_rate: u32,
+        /// The offered loan duration in hours, within the program limits
+        duration: u32,
+    },
+    /// Accept a lender's offer, taking the loan on the offered terms
+    ///
+    /// Accounts expected:
+    ///
+    /// The loan amount is worked out again for the offered interest rate and duration,
+    /// and the collateral has to still cover it at the loan's collateral ratio.  The
+    /// offered funds less the application fee are sent to the borrower, the fee to the
+    /// treasury, and the offer account is closed with its rent going back to the lender.
+    /// The emptied offer vault is kept as the loan's repayment vault.
+    ///
+    /// 0. `[signer]` The account of the person who initialized the loan
+    /// 1. `[writable]` The borrower's token account that receives the loan
+    /// 2. `[writable]` The loan account
+    /// 3. `[writable]` The offer account
+    /// 4. `[writable]` The offer vault, kept as the repayment vault
+    /// 5. `[]` The loan authority
+    /// 6. `[writable]` The account of the person who made the offer
+    /// 7. `[]` The program config
+    /// 8. `[writable]` The treasury token account for the loan mint
     /// 9. `[]` The token program
-    RepayLoan,
+    /// 10. `[]` The clock sysvar
+    /// 11. `[]` The collateral vault
+    /// 12. `[]` The collateral mint
+    ///
+    /// Only required if the collateral is not in the loan mint:
+    ///
+    /// 13. `[]` The price feed of the collateral mint in the loan mint
+    TakeOffer,
+    /// Withdraw an offer that has not been taken
+    ///
+    /// Accounts expected:
+    ///
+    /// The offered funds are returned to the lender, and the offer vault and account
+    /// are closed with their rent going back to the lender.  The offer can still be
+    /// withdrawn after the borrower has closed the loan.
+    ///
+    /// 0. `[writable, signer]` The account of the person who made the offer
+    /// 1. `[writable]` Token account that the offered funds are returned to
+    /// 2. `[]` The loan account
+    /// 3. `[writable]` The offer account
+    /// 4. `[writable]` The offer vault
+    /// 5. `[]` The loan authority
+    /// 6. `[]` The token program
+    CancelOffer,
+    /// Log how well the collateral covers what is left of the loan
+    ///
+    /// Accounts expected:
+    ///
+    /// Changes nothing.  Logs the value of the collateral in the loan mint, the
+    /// collateral ratio against the outstanding amount and the health factor, the
+    /// collateral ratio as a share of the ratio the loan requires.  All in basis points,
+    /// a health factor under 10000 means the loan is under-collateralized.
+    ///
+    /// 0. `[]` The loan account
+    /// 1. `[]` The collateral vault
+    /// 2. `[]` The collateral mint
+    /// 3. `[]` The clock sysvar
+    ///
+    /// Only required if the collateral is not in the loan mint:
+    ///
+    /// 4. `[]` The program config, names the oracle program and the publisher of the price feed
+    /// 5. `[]` The price feed of the collateral mint in the loan mint
+    CheckHealth,
+    /// Repay part of a loan whose collateral ratio has fallen below the maintenance
+    /// ratio, in exchange for collateral
+    ///
+    /// Anyone can liquidate.  The payment is split the same way as a repayment, and the
+    /// liquidator receives collateral worth the amount repaid plus the liquidation bonus,
+    /// or all of it if the vault holds less.  The liquidation that completes the loan
+    /// returns whatever collateral is left to the guarantor.  A liquidation that empties
+    /// the vault also closes the loan, as nothing is left to secure the rest of it.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the keeper liquidating the loan
+    /// 1. `[writable]` The liquidator's token account that has the funds being repaid
+    /// 2. `[writable]` The liquidator's token account that receives the collateral
+    /// 3. `[writable]` The loan account
+    /// 4. `[writable]` The collateral vault
+    /// 5. `[]` The loan authority
+    /// 6. `[]` The collateral mint
+    /// 7. `[writable]` The guarantor's token account that will receive their share
+    /// 8. `[writable]` The loan's repayment vault, or the lender's token account for loans without one
+    /// 9. `[]` The program config
+    /// 10. `[writable]` The treasury token account for the loan mint, receives the program share
+    /// 11. `[]` The token program
+    /// 12. `[]` The clock sysvar
+    ///
+    /// When the collateral is in the loan mint, only required for the liquidation that
+    /// completes the loan or empties the vault:
+    ///
+    /// 13. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 14. `[writable]` The guarantor's token account that the rest of the collateral is returned to
+    /// 15. `[writable]` The collateral vault
+    /// 16. `[]` The loan authority
+    ///
+    /// When the collateral is not in the loan mint, it is valued with the oracle program
+    /// the program config names:
+    ///
+    /// 13. `[]` The price feed of the collateral mint in the loan mint
+    ///
+    /// Then, only required for the liquidation that completes the loan or empties the vault:
+    ///
+    /// 14. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 15. `[writable]` The guarantor's token account that the rest of the collateral is returned to
+    /// 16. `[writable]` The collateral vault
+    /// 17. `[]` The loan authority
+    Liquidate {
+        /// The amount of the loan to repay
+        amount: u64,
+    },
+    /// Move more collateral into the collateral vault of a guaranteed or accepted loan
+    ///
+    /// Anyone can add collateral, usually the guarantor to keep the loan clear of
+    /// liquidation.  The collateral is returned to the guarantor with the rest when the
+    /// loan closes.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person adding the collateral
+    /// 1. `[writable]` The depositor's token account that the collateral is taken from
+    /// 2. `[]` The loan account
+    /// 3. `[writable]` The collateral vault
+    /// 4. `[]` The token program
+    AddCollateral {
+        /// The amount of collateral to add
+        amount: u64,
+    },
+    /// Return the collateral that exceeds the loan's collateral ratio against the
+    /// outstanding amount to the guarantor
+    ///
+    /// As the loan is paid down it needs less collateral, the guarantor can withdraw the
+    /// rest without waiting for the loan to close.  The collateral is valued the same way
+    /// as when it was put up.  Once an accepted loan is due the collateral stays in the
+    /// vault for the lender to claim.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[signer]` The account of the person who guaranteed the loan
+    /// 1. `[writable]` The guarantor's token account that the collateral is returned to
+    /// 2. `[]` The loan account
+    /// 3. `[writable]` The collateral vault
+    /// 4. `[]` The loan authority
+    /// 5. `[]` The collateral mint
+    /// 6. `[]` The token program
+    /// 7. `[]` The clock sysvar
+    ///
+    /// Only required if the collateral is not in the loan mint:
+    ///
+    /// 8. `[]` The program config, names the oracle program and the publisher of the price feed
+    /// 9. `[]` The price feed of the collateral mint in the loan mint
+    ReleaseCollateral,
+    /// Expire a loan request that was not accepted in time
+    ///
+    /// Anyone can expire a loan that is still waiting for a guarantor or a lender
+    /// `REQUEST_DURATION` hours after it was requested.  If the loan has been guaranteed,
+    /// the collateral is handed back to the guarantor.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The loan account, has information about the loan
+    /// 1. `[]` The clock sysvar
+    /// 2. `[]` The token program
+    ///
+    /// Only required if the loan has been guaranteed:
+    ///
+    /// 3. `[writable]` The guarantor's account, receives the collateral vault rent
+    /// 4. `[writable]` The guarantor's token account that the collateral is returned to
+    /// 5. `[writable]` The collateral vault
+    /// 6. `[]` The loan authority
+    ExpireLoan,
+    /// Close a settled loan and send the rent of its account to the borrower
+    ///
+    /// Only the borrower can close the loan, once it has been repaid, defaulted,
+    /// liquidated, cancelled or has expired, and the lender has withdrawn the
+    /// repayments.  Offers that are still open on the loan can be cancelled after it is
+    /// closed.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account of the person who initialized the loan
+    /// 1. `[writable]` The loan account
+    CloseLoan,
+    /// Close a program config created at an older size, so it can be created again at
+    /// the current size
+    ///
+    /// Accounts cannot be resized, so a config created before the collateral ratio, the
+    /// liquidation terms or the oracle program has no room for them and keeps their
+    /// defaults.  Only the admin can close it, its rent is returned to them.  `InitConfig`
+    /// then creates it again at the same address with the new terms, and as only the
+    /// upgrade authority can sign that, nobody else can take the config in between.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The admin account, receives the config account rent
+    /// 1. `[writable]` The program config
+    MigrateConfig,
+    /// Send what the repayment vault of a loan holds to the lender
+    ///
+    /// Only the lender can withdraw, at any time after the loan is accepted.  Once the
+    /// loan is repaid, defaulted or liquidated the vault is closed and its rent returned
+    /// to the lender.
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable, signer]` The account of the person who lent the money
+    /// 1. `[writable]` The token account to send the repayments to
+    /// 2. `[writable]` The loan account
+    /// 3. `[writable]` The repayment vault
+    /// 4. `[]` The loan authority
+    /// 5. `[]` The token program
+    WithdrawRepayment,
 }
 
 impl LoanInstruction {
     /// Unpacks a byte buffer into a [LoanInstruction](enum.LoanInstruction.html).
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
-
-        Ok(match tag {
-            0 => Self::InitLoan {
-                amount: Self::unpack_amount(rest)?,
-            },
-            1 => Self::GuaranteeLoan,
-            2 => Self::AcceptLoan,
-            3 => Self::RepayLoan,
-            _ => return Err(InvalidInstruction.into()),
-        })
-    }
-
-    fn unpack_amount(input: &[u8]) -> Result<u64, ProgramError> {
-        let amount = input
-            .get(..8)
-            .and_then(|slice| slice.try_into().ok())
-            .map(u64::from_le_bytes)
-            .ok_or(InvalidInstruction)?;
-        Ok(amount)
+        let (version, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        match *version {
+            INSTRUCTION_VERSION => Self::try_from_slice(rest).map_err(|_| InvalidInstruction.into()),
+            // an older instruction is encoded the same as in the current version, unless
+            // its fields have changed since
+            1..=5 => {
+                let tag = rest.first().ok_or(InvalidInstruction)?;
+                if CHANGED_TAGS[*version as usize - 1..].iter().any(|tags| tags.contains(tag)) {
+                    return Err(InvalidInstruction.into());
+                }
+                Self::try_from_slice(rest).map_err(|_| InvalidInstruction.into())
+            }
+            _ => Err(InvalidInstruction.into()),
+        }
     }
 
+    /// Packs the instruction, prefixed with the current instruction version
     pub fn pack_into_vec(&self) -> Vec<u8> {
-        self.try_to_vec().expect("try_to_vec")
+        let mut data = vec![INSTRUCTION_VERSION];
+        data.extend(self.try_to_vec().expect("try_to_vec"));
+        data
     }
 }
 
 /// Creates an 'InitLoan' instruction.
+#[allow(clippy::too_many_arguments)]
 pub fn init_loan(
     program_id: Pubkey,
     initializer_pubkey: Pubkey,
@@ -108,41 +620,63 @@ pub fn init_loan(
     initializer_loan_receive_pubkey: Pubkey,
     loan_account_pubkey: Pubkey,
     amount: u64,
+    duration: u32,
+    max_interest_rate: u32,
+    collateral_mint_pubkey: Pubkey,
 ) -> Instruction {
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(initializer_pubkey, true),
-            AccountMeta::new_readonly(loan_mint_pubkey, false),
-            AccountMeta::new_readonly(initializer_loan_receive_pubkey, false),
-            AccountMeta::new(loan_account_pubkey, false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-        ],
+        accounts: InitLoanAccounts {
+            initializer: initializer_pubkey,
+            loan_mint: loan_mint_pubkey,
+            borrower_loan_receive: initializer_loan_receive_pubkey,
+            loan: loan_account_pubkey,
+            rent: sysvar::rent::id(),
+            config: find_config_address(&program_id).0,
+            clock: sysvar::clock::id(),
+        }
+        .to_account_metas(),
         data: LoanInstruction::InitLoan {
             amount,
+            duration,
+            max_interest_rate,
+            collateral_mint: collateral_mint_pubkey,
         }
         .pack_into_vec(),
     }
 }
 
 /// Creates an 'GuaranteeLoan' instruction.
+///
+/// The price feed is only needed when the collateral is not in the loan mint, pass
+/// `COption::None` otherwise.
 pub fn guarantee_loan(
     program_id: Pubkey,
     guarantor_pubkey: Pubkey,
     collateral_account_pubkey: Pubkey,
     guarantor_repayment_pubkey: Pubkey,
     loan_account_pubkey: Pubkey,
+    collateral_mint_pubkey: Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
 ) -> Instruction {
+    let mut accounts = GuaranteeLoanAccounts {
+        guarantor: guarantor_pubkey,
+        collateral_source: collateral_account_pubkey,
+        guarantor_repayment: guarantor_repayment_pubkey,
+        loan: loan_account_pubkey,
+        collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+        loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+        collateral_mint: collateral_mint_pubkey,
+        rent: sysvar::rent::id(),
+        system_program: system_program::id(),
+        token_program: spl_token::id(),
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    accounts.extend(collateral_price_account_metas(&program_id, price_feed_pubkey));
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(guarantor_pubkey, true),
-            AccountMeta::new(collateral_account_pubkey, false),
-            AccountMeta::new(guarantor_repayment_pubkey, false),
-            AccountMeta::new(loan_account_pubkey, false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
+        accounts,
         data: LoanInstruction::GuaranteeLoan
         .pack_into_vec(),
     }
@@ -156,19 +690,694 @@ pub fn accept_loan(
     lender_repayment_token_pubkey: Pubkey,
     borrower_loan_receive_pubkey: Pubkey,
     loan_account_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
 ) -> Instruction {
     Instruction {
         program_id,
-        accounts: vec![
-            AccountMeta::new(lender_pubkey, true),
-            AccountMeta::new_readonly(lender_loan_transfer_token_pubkey, false),
-            AccountMeta::new_readonly(lender_repayment_token_pubkey, false),
-            AccountMeta::new(loan_account_pubkey, false),
-            AccountMeta::new(borrower_loan_receive_pubkey, false),
-            AccountMeta::new_readonly(sysvar::rent::id(), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-        ],
+        accounts: AcceptLoanAccounts {
+            lender: lender_pubkey,
+            lender_loan_transfer: lender_loan_transfer_token_pubkey,
+            lender_repayment: lender_repayment_token_pubkey,
+            borrower_loan_receive: borrower_loan_receive_pubkey,
+            loan: loan_account_pubkey,
+            rent: sysvar::rent::id(),
+            token_program: spl_token::id(),
+            clock: sysvar::clock::id(),
+            config: find_config_address(&program_id).0,
+            treasury: find_treasury_address(&program_id, &loan_mint_pubkey).0,
+            repayment_vault: find_repayment_vault_address(&program_id, &loan_account_pubkey).0,
+            loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+            loan_mint: loan_mint_pubkey,
+            system_program: system_program::id(),
+        }
+        .to_account_metas(),
         data: LoanInstruction::AcceptLoan
         .pack_into_vec(),
     }
 }
+
+/// Creates a 'RepayLoan' instruction.
+///
+/// The collateral accounts are only included for the payment that completes the
+/// loan, pass `COption::None` for installments.
+///
+/// The lender's share goes to the loan's repayment vault, or to the lender's token
+/// account for loans accepted before they had one.
+#[allow(clippy::too_many_arguments)]
+pub fn repay_loan(
+    program_id: Pubkey,
+    payer_pubkey: Pubkey,
+    payer_token_pubkey: Pubkey,
+    guarantor_repayment_pubkey: Pubkey,
+    lender_repayment_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
+    guarantor_pubkey: COption<Pubkey>,
+    guarantor_collateral_pubkey: COption<Pubkey>,
+    amount: u64,
+) -> Instruction {
+    let mut accounts = RepayLoanAccounts {
+        payer: payer_pubkey,
+        payer_token: payer_token_pubkey,
+        guarantor_repayment: guarantor_repayment_pubkey,
+        lender_repayment: lender_repayment_pubkey,
+        loan: loan_account_pubkey,
+        config: find_config_address(&program_id).0,
+        treasury: find_treasury_address(&program_id, &loan_mint_pubkey).0,
+        token_program: spl_token::id(),
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    accounts.extend(return_collateral_account_metas(
+        &program_id,
+        &loan_account_pubkey,
+        guarantor_pubkey,
+        guarantor_collateral_pubkey,
+    ));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::RepayLoan {
+            amount,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'CancelLoan' instruction.
+///
+/// The guarantor is taken as recorded on the loan, and the collateral accounts are
+/// only included when the loan has been guaranteed.
+pub fn cancel_loan(
+    program_id: Pubkey,
+    initializer_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    guarantor_pubkey: COption<Pubkey>,
+    guarantor_collateral_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = CancelLoanAccounts {
+        initializer: initializer_pubkey,
+        loan: loan_account_pubkey,
+        clock: sysvar::clock::id(),
+        token_program: spl_token::id(),
+    }
+    .to_account_metas();
+    accounts.extend(return_collateral_account_metas(
+        &program_id,
+        &loan_account_pubkey,
+        guarantor_pubkey,
+        guarantor_collateral_pubkey,
+    ));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::CancelLoan
+        .pack_into_vec(),
+    }
+}
+
+/// The accounts needed to price the collateral, if it is in a different mint from the loan
+fn collateral_price_account_metas(
+    program_id: &Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
+) -> Vec<AccountMeta> {
+    match price_feed_pubkey {
+        COption::Some(price_feed) => CollateralPriceAccounts {
+            config: find_config_address(program_id).0,
+            price_feed,
+        }
+        .to_account_metas(),
+        COption::None => vec![],
+    }
+}
+
+/// The accounts needed to hand the collateral back to the guarantor, if there is one
+fn return_collateral_account_metas(
+    program_id: &Pubkey,
+    loan_account_pubkey: &Pubkey,
+    guarantor_pubkey: COption<Pubkey>,
+    guarantor_collateral_pubkey: COption<Pubkey>,
+) -> Vec<AccountMeta> {
+    match (guarantor_pubkey, guarantor_collateral_pubkey) {
+        (COption::Some(guarantor_pubkey), COption::Some(guarantor_collateral_pubkey)) => {
+            ReturnCollateralAccounts {
+                guarantor: guarantor_pubkey,
+                collateral_return: guarantor_collateral_pubkey,
+                collateral_vault: find_collateral_vault_address(program_id, loan_account_pubkey).0,
+                loan_authority: find_loan_authority_address(program_id, loan_account_pubkey).0,
+            }
+            .to_account_metas()
+        }
+        _ => vec![],
+    }
+}
+
+/// Creates a 'ClaimDefault' instruction.
+pub fn claim_default(
+    program_id: Pubkey,
+    lender_pubkey: Pubkey,
+    lender_collateral_pubkey: Pubkey,
+    guarantor_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: ClaimDefaultAccounts {
+            lender: lender_pubkey,
+            lender_collateral: lender_collateral_pubkey,
+            guarantor: guarantor_pubkey,
+            loan: loan_account_pubkey,
+            collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+            loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+            token_program: spl_token::id(),
+            clock: sysvar::clock::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::ClaimDefault
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'InitConfig' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn init_config(
+    program_id: Pubkey,
+    admin_pubkey: Pubkey,
+    interest_rate: u32,
+    duration: u32,
+    processing_fee: u32,
+    application_fee: u32,
+    guarantor_share: u32,
+    lender_share: u32,
+    collateral_ratio: u32,
+    maintenance_ratio: u32,
+    liquidation_bonus: u32,
+    oracle_program_id: Pubkey,
+    oracle_publisher: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: InitConfigAccounts {
+            admin: admin_pubkey,
+            config: find_config_address(&program_id).0,
+            rent: sysvar::rent::id(),
+            system_program: system_program::id(),
+            program_data: find_program_data_address(&program_id).0,
+        }
+        .to_account_metas(),
+        data: LoanInstruction::InitConfig {
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'UpdateConfig' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn update_config(
+    program_id: Pubkey,
+    admin_pubkey: Pubkey,
+    interest_rate: u32,
+    duration: u32,
+    processing_fee: u32,
+    application_fee: u32,
+    guarantor_share: u32,
+    lender_share: u32,
+    collateral_ratio: u32,
+    maintenance_ratio: u32,
+    liquidation_bonus: u32,
+    oracle_program_id: Pubkey,
+    oracle_publisher: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: UpdateConfigAccounts {
+            admin: admin_pubkey,
+            config: find_config_address(&program_id).0,
+        }
+        .to_account_metas(),
+        data: LoanInstruction::UpdateConfig {
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'InitTreasury' instruction.
+pub fn init_treasury(
+    program_id: Pubkey,
+    admin_pubkey: Pubkey,
+    mint_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: InitTreasuryAccounts {
+            admin: admin_pubkey,
+            config: find_config_address(&program_id).0,
+            treasury: find_treasury_address(&program_id, &mint_pubkey).0,
+            mint: mint_pubkey,
+            rent: sysvar::rent::id(),
+            system_program: system_program::id(),
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::InitTreasury
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'WithdrawTreasury' instruction.
+pub fn withdraw_treasury(
+    program_id: Pubkey,
+    admin_pubkey: Pubkey,
+    mint_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: WithdrawTreasuryAccounts {
+            admin: admin_pubkey,
+            config: find_config_address(&program_id).0,
+            treasury: find_treasury_address(&program_id, &mint_pubkey).0,
+            destination: destination_pubkey,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::WithdrawTreasury {
+            amount,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'MigrateLoan' instruction.
+///
+/// The collateral accounts are only needed when the loan has been guaranteed, and the
+/// lender's repayment account only when it has been accepted, pass `COption::None`
+/// otherwise.
+#[allow(clippy::too_many_arguments)]
+pub fn migrate_loan(
+    program_id: Pubkey,
+    payer_pubkey: Pubkey,
+    initializer_pubkey: Pubkey,
+    old_loan_account_pubkey: Pubkey,
+    new_loan_account_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
+    legacy_collateral_pubkey: COption<Pubkey>,
+    guarantor_repayment_pubkey: COption<Pubkey>,
+    collateral_mint_pubkey: COption<Pubkey>,
+    lender_repayment_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = MigrateLoanAccounts {
+        payer: payer_pubkey,
+        initializer: initializer_pubkey,
+        old_loan: old_loan_account_pubkey,
+        new_loan: new_loan_account_pubkey,
+        loan_mint: loan_mint_pubkey,
+        rent: sysvar::rent::id(),
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    if let (COption::Some(legacy_collateral), COption::Some(guarantor_repayment), COption::Some(collateral_mint)) =
+        (legacy_collateral_pubkey, guarantor_repayment_pubkey, collateral_mint_pubkey)
+    {
+        accounts.extend(MigrateCollateralAccounts {
+            legacy_authority: find_legacy_loan_authority_address(&program_id).0,
+            legacy_collateral,
+            guarantor_repayment,
+            collateral_vault: find_collateral_vault_address(&program_id, &new_loan_account_pubkey).0,
+            collateral_mint,
+            loan_authority: find_loan_authority_address(&program_id, &new_loan_account_pubkey).0,
+            system_program: system_program::id(),
+            token_program: spl_token::id(),
+        }
+        .to_account_metas());
+    }
+    if let COption::Some(lender_repayment) = lender_repayment_pubkey {
+        accounts.extend(MigrateRepaymentAccounts {
+            lender_repayment,
+        }
+        .to_account_metas());
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::MigrateLoan
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'OfferLoan' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn offer_loan(
+    program_id: Pubkey,
+    lender_pubkey: Pubkey,
+    lender_loan_transfer_token_pubkey: Pubkey,
+    lender_repayment_token_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    offer_account_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
+    interest_rate: u32,
+    duration: u32,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: OfferLoanAccounts {
+            lender: lender_pubkey,
+            lender_loan_transfer: lender_loan_transfer_token_pubkey,
+            lender_repayment: lender_repayment_token_pubkey,
+            loan: loan_account_pubkey,
+            offer: offer_account_pubkey,
+            offer_vault: find_offer_vault_address(&program_id, &offer_account_pubkey).0,
+            loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+            loan_mint: loan_mint_pubkey,
+            config: find_config_address(&program_id).0,
+            rent: sysvar::rent::id(),
+            system_program: system_program::id(),
+            token_program: spl_token::id(),
+            clock: sysvar::clock::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::OfferLoan {
+            interest_rate,
+            duration,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'TakeOffer' instruction.
+#[allow(clippy::too_many_arguments)]
+pub fn take_offer(
+    program_id: Pubkey,
+    borrower_pubkey: Pubkey,
+    borrower_loan_receive_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    offer_account_pubkey: Pubkey,
+    lender_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
+    collateral_mint_pubkey: Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = TakeOfferAccounts {
+        borrower: borrower_pubkey,
+        borrower_loan_receive: borrower_loan_receive_pubkey,
+        loan: loan_account_pubkey,
+        offer: offer_account_pubkey,
+        offer_vault: find_offer_vault_address(&program_id, &offer_account_pubkey).0,
+        loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+        lender: lender_pubkey,
+        config: find_config_address(&program_id).0,
+        treasury: find_treasury_address(&program_id, &loan_mint_pubkey).0,
+        token_program: spl_token::id(),
+        clock: sysvar::clock::id(),
+        collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+        collateral_mint: collateral_mint_pubkey,
+    }
+    .to_account_metas();
+    if let COption::Some(price_feed) = price_feed_pubkey {
+        accounts.extend(PriceFeedAccounts { price_feed }.to_account_metas());
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::TakeOffer
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'CancelOffer' instruction.
+pub fn cancel_offer(
+    program_id: Pubkey,
+    lender_pubkey: Pubkey,
+    lender_refund_token_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    offer_account_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: CancelOfferAccounts {
+            lender: lender_pubkey,
+            lender_refund: lender_refund_token_pubkey,
+            loan: loan_account_pubkey,
+            offer: offer_account_pubkey,
+            offer_vault: find_offer_vault_address(&program_id, &offer_account_pubkey).0,
+            loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::CancelOffer
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'CheckHealth' instruction.
+///
+/// The price feed is only needed when the collateral is not in the loan mint, pass
+/// `COption::None` otherwise.
+pub fn check_health(
+    program_id: Pubkey,
+    loan_account_pubkey: Pubkey,
+    collateral_mint_pubkey: Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = CheckHealthAccounts {
+        loan: loan_account_pubkey,
+        collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+        collateral_mint: collateral_mint_pubkey,
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    accounts.extend(collateral_price_account_metas(&program_id, price_feed_pubkey));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::CheckHealth
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'Liquidate' instruction.
+///
+/// The price feed is only needed when the collateral is not in the loan mint, and the
+/// guarantor accounts only when the liquidation completes the loan or empties the
+/// vault, pass `COption::None` otherwise.  The lender's share goes to the loan's
+/// repayment vault, or to the lender's token account for loans accepted before they
+/// had one.
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate(
+    program_id: Pubkey,
+    liquidator_pubkey: Pubkey,
+    liquidator_token_pubkey: Pubkey,
+    liquidator_collateral_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    loan_mint_pubkey: Pubkey,
+    collateral_mint_pubkey: Pubkey,
+    guarantor_repayment_pubkey: Pubkey,
+    lender_repayment_pubkey: Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
+    guarantor_pubkey: COption<Pubkey>,
+    guarantor_collateral_pubkey: COption<Pubkey>,
+    amount: u64,
+) -> Instruction {
+    let mut accounts = LiquidateAccounts {
+        liquidator: liquidator_pubkey,
+        liquidator_token: liquidator_token_pubkey,
+        liquidator_collateral: liquidator_collateral_pubkey,
+        loan: loan_account_pubkey,
+        collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+        loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+        collateral_mint: collateral_mint_pubkey,
+        guarantor_repayment: guarantor_repayment_pubkey,
+        lender_repayment: lender_repayment_pubkey,
+        config: find_config_address(&program_id).0,
+        treasury: find_treasury_address(&program_id, &loan_mint_pubkey).0,
+        token_program: spl_token::id(),
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    if let COption::Some(price_feed) = price_feed_pubkey {
+        accounts.extend(PriceFeedAccounts { price_feed }.to_account_metas());
+    }
+    accounts.extend(return_collateral_account_metas(
+        &program_id,
+        &loan_account_pubkey,
+        guarantor_pubkey,
+        guarantor_collateral_pubkey,
+    ));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::Liquidate {
+            amount,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'AddCollateral' instruction.
+pub fn add_collateral(
+    program_id: Pubkey,
+    depositor_pubkey: Pubkey,
+    collateral_source_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: AddCollateralAccounts {
+            depositor: depositor_pubkey,
+            collateral_source: collateral_source_pubkey,
+            loan: loan_account_pubkey,
+            collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::AddCollateral {
+            amount,
+        }
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'ReleaseCollateral' instruction.
+///
+/// The price feed is only needed when the collateral is not in the loan mint, pass
+/// `COption::None` otherwise.
+pub fn release_collateral(
+    program_id: Pubkey,
+    guarantor_pubkey: Pubkey,
+    guarantor_collateral_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    collateral_mint_pubkey: Pubkey,
+    price_feed_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = ReleaseCollateralAccounts {
+        guarantor: guarantor_pubkey,
+        collateral_return: guarantor_collateral_pubkey,
+        loan: loan_account_pubkey,
+        collateral_vault: find_collateral_vault_address(&program_id, &loan_account_pubkey).0,
+        loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+        collateral_mint: collateral_mint_pubkey,
+        token_program: spl_token::id(),
+        clock: sysvar::clock::id(),
+    }
+    .to_account_metas();
+    accounts.extend(collateral_price_account_metas(&program_id, price_feed_pubkey));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::ReleaseCollateral
+        .pack_into_vec(),
+    }
+}
+
+/// Creates an 'ExpireLoan' instruction.
+///
+/// The guarantor is taken as recorded on the loan, and the collateral accounts are
+/// only included when the loan has been guaranteed.
+pub fn expire_loan(
+    program_id: Pubkey,
+    loan_account_pubkey: Pubkey,
+    guarantor_pubkey: COption<Pubkey>,
+    guarantor_collateral_pubkey: COption<Pubkey>,
+) -> Instruction {
+    let mut accounts = ExpireLoanAccounts {
+        loan: loan_account_pubkey,
+        clock: sysvar::clock::id(),
+        token_program: spl_token::id(),
+    }
+    .to_account_metas();
+    accounts.extend(return_collateral_account_metas(
+        &program_id,
+        &loan_account_pubkey,
+        guarantor_pubkey,
+        guarantor_collateral_pubkey,
+    ));
+    Instruction {
+        program_id,
+        accounts,
+        data: LoanInstruction::ExpireLoan
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'CloseLoan' instruction.
+pub fn close_loan(
+    program_id: Pubkey,
+    initializer_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: CloseLoanAccounts {
+            initializer: initializer_pubkey,
+            loan: loan_account_pubkey,
+        }
+        .to_account_metas(),
+        data: LoanInstruction::CloseLoan
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'MigrateConfig' instruction.
+pub fn migrate_config(
+    program_id: Pubkey,
+    admin_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: MigrateConfigAccounts {
+            admin: admin_pubkey,
+            config: find_config_address(&program_id).0,
+        }
+        .to_account_metas(),
+        data: LoanInstruction::MigrateConfig
+        .pack_into_vec(),
+    }
+}
+
+/// Creates a 'WithdrawRepayment' instruction.
+pub fn withdraw_repayment(
+    program_id: Pubkey,
+    lender_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    loan_account_pubkey: Pubkey,
+    repayment_vault_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: WithdrawRepaymentAccounts {
+            lender: lender_pubkey,
+            destination: destination_pubkey,
+            loan: loan_account_pubkey,
+            repayment_vault: repayment_vault_pubkey,
+            loan_authority: find_loan_authority_address(&program_id, &loan_account_pubkey).0,
+            token_program: spl_token::id(),
+        }
+        .to_account_metas(),
+        data: LoanInstruction::WithdrawRepayment
+        .pack_into_vec(),
+    }
+}
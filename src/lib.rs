@@ -1,6 +1,15 @@
+pub mod accounts;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod entrypoint;
 pub mod instruction;
 pub mod error;
+pub mod events;
+#[cfg(feature = "idl")]
+pub mod idl;
+#[cfg(feature = "mock-oracle")]
+pub mod mock_oracle;
+pub mod oracle;
 pub mod processor;
 pub mod state;
 pub mod utils;
@@ -0,0 +1,132 @@
+//! A stand-in for the oracle program, behind the `mock-oracle` feature.
+//!
+//! It writes whatever price it is given into a price feed account it owns, so program
+//! tests can value collateral at a known price, or at a stale one.  It has its own
+//! program id, so a program config only trusts its feeds when it names it as the oracle
+//! program, and the authority of the feed as the oracle publisher.  Each feed belongs to
+//! the authority its address is derived from, and only that authority can set its
+//! price.  Register it with `solana-program-test`, and preload the feed accounts at
+//! `find_price_feed_address`:
+//!
+//! ```ignore
+//! program_test.add_program(
+//!     "mock_oracle",
+//!     mock_oracle_program::id(),
+//!     processor!(mock_oracle::process_instruction),
+//! );
+//! ```
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    clock::UnixTimestamp,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    msg,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+use crate::oracle::{self, PriceFeed};
+
+/// The program id of the mock oracle
+pub mod mock_oracle_program {
+    solana_program::declare_id!("ibUKyN7okb4MNiVBtviaCBxv3B67u5xQEWq4UKT2KF6");
+}
+
+/// find the address of the price feed of `base_mint` in `quote_mint` that `authority` sets
+pub fn find_price_feed_address(
+    authority: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> (Pubkey, u8) {
+    oracle::find_price_feed_address(&mock_oracle_program::id(), authority, base_mint, quote_mint)
+}
+
+#[derive(Clone, Debug, BorshSerialize, BorshDeserialize, PartialEq)]
+pub enum MockOracleInstruction {
+    /// Write a price into a price feed
+    ///
+    /// Accounts expected:
+    ///
+    /// 0. `[writable]` The price feed account, owned by the mock oracle and derived from
+    ///    the authority and the mints
+    /// 1. `[signer]` The authority of the price feed
+    SetPrice {
+        base_mint: Pubkey,
+        quote_mint: Pubkey,
+        price: i64,
+        exponent: i32,
+        confidence: u64,
+        publish_time: UnixTimestamp,
+    },
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = MockOracleInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        MockOracleInstruction::SetPrice { base_mint, quote_mint, price, exponent, confidence, publish_time } => {
+            msg!("Instruction: SetPrice");
+            let account_info_iter = &mut accounts.iter();
+            let price_feed_info = next_account_info(account_info_iter)?;
+            let authority_info = next_account_info(account_info_iter)?;
+            if *price_feed_info.owner != *program_id {
+                return Err(ProgramError::IncorrectProgramId);
+            }
+            // only the authority the feed is derived from can set its price
+            if !authority_info.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if *price_feed_info.key != find_price_feed_address(authority_info.key, &base_mint, &quote_mint).0 {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            PriceFeed::pack(
+                PriceFeed {
+                    is_initialized: true,
+                    base_mint,
+                    quote_mint,
+                    price,
+                    exponent,
+                    confidence,
+                    publish_time,
+                },
+                &mut price_feed_info.data.borrow_mut(),
+            )
+        }
+    }
+}
+
+/// Creates a 'SetPrice' instruction for the price feed `authority_pubkey` sets.
+pub fn set_price(
+    authority_pubkey: Pubkey,
+    base_mint: Pubkey,
+    quote_mint: Pubkey,
+    price: i64,
+    exponent: i32,
+    confidence: u64,
+    publish_time: UnixTimestamp,
+) -> Instruction {
+    Instruction {
+        program_id: mock_oracle_program::id(),
+        accounts: vec![
+            AccountMeta::new(find_price_feed_address(&authority_pubkey, &base_mint, &quote_mint).0, false),
+            AccountMeta::new_readonly(authority_pubkey, true),
+        ],
+        data: MockOracleInstruction::SetPrice {
+            base_mint,
+            quote_mint,
+            price,
+            exponent,
+            confidence,
+            publish_time,
+        }
+        .try_to_vec()
+        .expect("try_to_vec"),
+    }
+}
@@ -0,0 +1,142 @@
+//! The price feed accounts collateral is valued with.
+//!
+//! A price feed is published by the oracle program and prices one whole token of its
+//! base mint in whole tokens of its quote mint, as `price * 10^exponent`.  The oracle
+//! program keeps a feed for each publisher and pair of mints, at an address derived
+//! from them, so the program only trusts the feed at `find_price_feed_address` for the
+//! oracle program and the publisher named in the program config.
+
+use std::convert::TryFrom;
+use solana_program::{
+    clock::UnixTimestamp,
+    program_error::ProgramError,
+    program_pack::{IsInitialized, Pack, Sealed},
+    pubkey::Pubkey,
+};
+use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+
+use crate::error::LoanError;
+
+/// The program that publishes the price feeds, trusted by the program configs created
+/// before the oracle program was stored on them
+pub mod oracle_program {
+    solana_program::declare_id!("3L8ZHZWS5ZwYc4BwK6qeFBuMoHUG7ohXsXBxzg5XDJEk");
+}
+
+/// Seed of the program derived address of a price feed
+pub const PRICE_FEED_SEED: &[u8] = b"price_feed";
+
+/// find the address of the price feed of `base_mint` in `quote_mint` that `publisher`
+/// publishes with `oracle_program_id`
+pub fn find_price_feed_address(
+    oracle_program_id: &Pubkey,
+    publisher: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PRICE_FEED_SEED, publisher.as_ref(), base_mint.as_ref(), quote_mint.as_ref()],
+        oracle_program_id,
+    )
+}
+
+/// How old a price can be and still be used, in seconds
+pub const MAX_PRICE_AGE: UnixTimestamp = 60;
+
+pub struct PriceFeed {
+    pub is_initialized: bool,
+    pub base_mint: Pubkey,  // the mint being priced
+    pub quote_mint: Pubkey,  // the mint the price is in
+    pub price: i64,  // the price of a whole base token in whole quote tokens, scaled by 10^exponent
+    pub exponent: i32,
+    pub confidence: u64,  // how far off the price may be, in the same scale as the price
+    pub publish_time: UnixTimestamp,
+}
+
+impl PriceFeed {
+    /// The lowest price the feed vouches for, i.e. the price less its confidence.
+    /// Fails if the price was published more than `MAX_PRICE_AGE` seconds before `now`.
+    pub fn get_price(&self, now: UnixTimestamp) -> Result<u64, ProgramError> {
+        if now.saturating_sub(self.publish_time) > MAX_PRICE_AGE {
+            return Err(LoanError::StalePrice.into());
+        }
+        let confidence = i64::try_from(self.confidence).map_err(|_| LoanError::InvalidPrice)?;
+        let price = self.price
+            .checked_sub(confidence)
+            .ok_or(LoanError::InvalidPrice)?;
+        if price <= 0 {
+            return Err(LoanError::InvalidPrice.into());
+        }
+        Ok(price as u64)
+    }
+}
+
+impl Sealed for PriceFeed {}
+
+impl IsInitialized for PriceFeed {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for PriceFeed {
+    const LEN: usize = 93;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, PriceFeed::LEN];
+        let (
+            is_initialized,
+            base_mint,
+            quote_mint,
+            price,
+            exponent,
+            confidence,
+            publish_time,
+        ) = array_refs![src, 1, 32, 32, 8, 4, 8, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(PriceFeed {
+            is_initialized,
+            base_mint: Pubkey::new_from_array(*base_mint),
+            quote_mint: Pubkey::new_from_array(*quote_mint),
+            price: i64::from_le_bytes(*price),
+            exponent: i32::from_le_bytes(*exponent),
+            confidence: u64::from_le_bytes(*confidence),
+            publish_time: i64::from_le_bytes(*publish_time),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, PriceFeed::LEN];
+        let (
+            is_initialized_dst,
+            base_mint_dst,
+            quote_mint_dst,
+            price_dst,
+            exponent_dst,
+            confidence_dst,
+            publish_time_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 8, 4, 8, 8];
+
+        let PriceFeed {
+            is_initialized,
+            base_mint,
+            quote_mint,
+            price,
+            exponent,
+            confidence,
+            publish_time,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        base_mint_dst.copy_from_slice(base_mint.as_ref());
+        quote_mint_dst.copy_from_slice(quote_mint.as_ref());
+        *price_dst = price.to_le_bytes();
+        *exponent_dst = exponent.to_le_bytes();
+        *confidence_dst = confidence.to_le_bytes();
+        *publish_time_dst = publish_time.to_le_bytes();
+    }
+}
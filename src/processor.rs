@@ -1,23 +1,81 @@
+use std::slice::Iter;
+use arrayref::{array_ref, array_refs};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
+    bpf_loader_upgradeable,
+    clock::UnixTimestamp,
     entrypoint::ProgramResult,
     program_option::COption,
     program_error::ProgramError,
     msg,
     pubkey::Pubkey,
     program_pack::{Pack, IsInitialized},
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
     program::{invoke, invoke_signed},
+    system_instruction,
 };
-use crate::{instruction::LoanInstruction, error::LoanError, state::{Loan, LoanStatus}};
+use crate::{accounts::{
+    AcceptLoanAccounts,
+    AddCollateralAccounts,
+    CancelLoanAccounts,
+    CancelOfferAccounts,
+    CheckHealthAccounts,
+    ClaimDefaultAccounts,
+    CloseLoanAccounts,
+    CollateralPriceAccounts,
+    ExpireLoanAccounts,
+    GuaranteeLoanAccounts,
+    InitConfigAccounts,
+    InitLoanAccounts,
+    InitTreasuryAccounts,
+    LiquidateAccounts,
+    MigrateConfigAccounts,
+    MigrateCollateralAccounts,
+    MigrateLoanAccounts,
+    MigrateRepaymentAccounts,
+    OfferLoanAccounts,
+    PriceFeedAccounts,
+    ReleaseCollateralAccounts,
+    RepayLoanAccounts,
+    ReturnCollateralAccounts,
+    TakeOfferAccounts,
+    UpdateConfigAccounts,
+    WithdrawRepaymentAccounts,
+    WithdrawTreasuryAccounts,
+}};
+use crate::{events::LoanEvent, instruction::LoanInstruction, error::LoanError, oracle::{find_price_feed_address, PriceFeed}, state::{Loan, LoanAction, LoanOffer, LoanStatus, ProgramConfig, LOAN_V1_LEN}};
 use crate::{utils::{
+    apply_basis_points,
     get_application_fee,
     get_borrowed_amount,
-    get_duration,
+    get_basis_points,
+    get_collateral_amount,
+    get_collateral_ratio,
+    get_collateral_value,
+    get_due_at,
+    get_max_duration,
     get_interest_rate,
-    get_guarantor_share,
-    get_lender_share,
-    get_processing_fee,
+    get_repayment_split,
+    RepaymentSplit,
+    config_id,
+    find_collateral_vault_address,
+    find_config_address,
+    find_legacy_loan_authority_address,
+    find_loan_authority_address,
+    find_offer_vault_address,
+    find_program_data_address,
+    find_repayment_vault_address,
+    find_treasury_address,
+    loan_authority_id,
+    COLLATERAL_VAULT_SEED,
+    MIN_DURATION,
+    REQUEST_DURATION,
+    CONFIG_SEED,
+    LOAN_AUTHORITY_SEED,
+    OFFER_VAULT_SEED,
+    REPAYMENT_VAULT_SEED,
+    BASIS_POINTS,
+    TREASURY_SEED,
 }};
 
 pub struct Processor;
@@ -26,9 +84,14 @@ impl Processor {
         let instruction = LoanInstruction::unpack(instruction_data)?;
 
         match instruction {
-            LoanInstruction::InitLoan { amount } => {
+            LoanInstruction::InitLoan {
+                amount,
+                duration,
+                max_interest_rate,
+                collateral_mint,
+            } => {
                 msg!("Instruction: InitLoan");
-                process_init_loan(program_id, accounts, amount)
+                process_init_loan(program_id, accounts, amount, duration, max_interest_rate, collateral_mint)
             }
             LoanInstruction::GuaranteeLoan => {
                 msg!("Instruction: GuaranteeLoan");
@@ -38,9 +101,133 @@ impl Processor {
                 msg!("Instruction: AcceptLoan");
                 process_accept_loan(program_id, accounts)
             }
-            LoanInstruction::RepayLoan => {
+            LoanInstruction::RepayLoan { amount } => {
                 msg!("Instruction: RepayLoan");
-                process_repay_loan(program_id, accounts)
+                process_repay_loan(program_id, accounts, amount)
+            }
+            LoanInstruction::CancelLoan => {
+                msg!("Instruction: CancelLoan");
+                process_cancel_loan(program_id, accounts)
+            }
+            LoanInstruction::ClaimDefault => {
+                msg!("Instruction: ClaimDefault");
+                process_claim_default(program_id, accounts)
+            }
+            LoanInstruction::InitConfig {
+                interest_rate,
+                duration,
+                processing_fee,
+                application_fee,
+                guarantor_share,
+                lender_share,
+                collateral_ratio,
+                maintenance_ratio,
+                liquidation_bonus,
+                oracle_program_id,
+                oracle_publisher,
+            } => {
+                msg!("Instruction: InitConfig");
+                process_init_config(
+                    program_id,
+                    accounts,
+                    interest_rate,
+                    duration,
+                    processing_fee,
+                    application_fee,
+                    guarantor_share,
+                    lender_share,
+                    collateral_ratio,
+                    maintenance_ratio,
+                    liquidation_bonus,
+                    oracle_program_id,
+                    oracle_publisher,
+                )
+            }
+            LoanInstruction::UpdateConfig {
+                interest_rate,
+                duration,
+                processing_fee,
+                application_fee,
+                guarantor_share,
+                lender_share,
+                collateral_ratio,
+                maintenance_ratio,
+                liquidation_bonus,
+                oracle_program_id,
+                oracle_publisher,
+            } => {
+                msg!("Instruction: UpdateConfig");
+                process_update_config(
+                    program_id,
+                    accounts,
+                    interest_rate,
+                    duration,
+                    processing_fee,
+                    application_fee,
+                    guarantor_share,
+                    lender_share,
+                    collateral_ratio,
+                    maintenance_ratio,
+                    liquidation_bonus,
+                    oracle_program_id,
+                    oracle_publisher,
+                )
+            }
+            LoanInstruction::InitTreasury => {
+                msg!("Instruction: InitTreasury");
+                process_init_treasury(program_id, accounts)
+            }
+            LoanInstruction::WithdrawTreasury { amount } => {
+                msg!("Instruction: WithdrawTreasury");
+                process_withdraw_treasury(program_id, accounts, amount)
+            }
+            LoanInstruction::MigrateLoan => {
+                msg!("Instruction: MigrateLoan");
+                process_migrate_loan(program_id, accounts)
+            }
+            LoanInstruction::OfferLoan { interest_rate, duration } => {
+                msg!("Instruction: OfferLoan");
+                process_offer_loan(program_id, accounts, interest_rate, duration)
+            }
+            LoanInstruction::TakeOffer => {
+                msg!("Instruction: TakeOffer");
+                process_take_offer(program_id, accounts)
+            }
+            LoanInstruction::CancelOffer => {
+                msg!("Instruction: CancelOffer");
+                process_cancel_offer(program_id, accounts)
+            }
+            LoanInstruction::CheckHealth => {
+                msg!("Instruction: CheckHealth");
+                process_check_health(program_id, accounts)
+            }
+            LoanInstruction::Liquidate { amount } => {
+                msg!("Instruction: Liquidate");
+                process_liquidate(program_id, accounts, amount)
+            }
+            LoanInstruction::AddCollateral { amount } => {
+                msg!("Instruction: AddCollateral");
+                process_add_collateral(program_id, accounts, amount)
+            }
+            LoanInstruction::ReleaseCollateral => {
+                msg!("Instruction: ReleaseCollateral");
+                process_release_collateral(program_id, accounts)
+            }
+            LoanInstruction::ExpireLoan => {
+                msg!("Instruction: ExpireLoan");
+                process_expire_loan(program_id, accounts)
+            }
+            LoanInstruction::CloseLoan => {
+                msg!("Instruction: CloseLoan");
+                process_close_loan(program_id, accounts)
+            }
+            LoanInstruction::MigrateConfig => {
+                msg!("Instruction: MigrateConfig");
+                process_migrate_config(program_id, accounts)
+            }
+            LoanInstruction::WithdrawRepayment => {
+                msg!("Instruction: WithdrawRepayment");
+                process_withdraw_repayment(program_id, accounts)
             }
         }
     }
@@ -55,9 +242,14 @@ pub fn process_instruction(
     let instruction = LoanInstruction::unpack(instruction_data)?;
 
     match instruction {
-        LoanInstruction::InitLoan { amount } => {
+        LoanInstruction::InitLoan {
+            amount,
+            duration,
+            max_interest_rate,
+            collateral_mint,
+        } => {
             msg!("Instruction: InitLoan");
-            process_init_loan(program_id, accounts, amount)
+            process_init_loan(program_id, accounts, amount, duration, max_interest_rate, collateral_mint)
         }
         LoanInstruction::GuaranteeLoan => {
             msg!("Instruction: GuaranteeLoan");
@@ -67,9 +259,133 @@ pub fn process_instruction(
             msg!("Instruction: AcceptLoan");
             process_accept_loan(program_id, accounts)
         }
-        LoanInstruction::RepayLoan => {
+        LoanInstruction::RepayLoan { amount } => {
             msg!("Instruction: RepayLoan");
-            process_repay_loan(program_id, accounts)
+            process_repay_loan(program_id, accounts, amount)
+        }
+        LoanInstruction::CancelLoan => {
+            msg!("Instruction: CancelLoan");
+            process_cancel_loan(program_id, accounts)
+        }
+        LoanInstruction::ClaimDefault => {
+            msg!("Instruction: ClaimDefault");
+            process_claim_default(program_id, accounts)
+        }
+        LoanInstruction::InitConfig {
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        } => {
+            msg!("Instruction: InitConfig");
+            process_init_config(
+                program_id,
+                accounts,
+                interest_rate,
+                duration,
+                processing_fee,
+                application_fee,
+                guarantor_share,
+                lender_share,
+                collateral_ratio,
+                maintenance_ratio,
+                liquidation_bonus,
+                oracle_program_id,
+                oracle_publisher,
+            )
+        }
+        LoanInstruction::UpdateConfig {
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        } => {
+            msg!("Instruction: UpdateConfig");
+            process_update_config(
+                program_id,
+                accounts,
+                interest_rate,
+                duration,
+                processing_fee,
+                application_fee,
+                guarantor_share,
+                lender_share,
+                collateral_ratio,
+                maintenance_ratio,
+                liquidation_bonus,
+                oracle_program_id,
+                oracle_publisher,
+            )
+        }
+        LoanInstruction::InitTreasury => {
+            msg!("Instruction: InitTreasury");
+            process_init_treasury(program_id, accounts)
+        }
+        LoanInstruction::WithdrawTreasury { amount } => {
+            msg!("Instruction: WithdrawTreasury");
+            process_withdraw_treasury(program_id, accounts, amount)
+        }
+        LoanInstruction::MigrateLoan => {
+            msg!("Instruction: MigrateLoan");
+            process_migrate_loan(program_id, accounts)
+        }
+        LoanInstruction::OfferLoan { interest_rate, duration } => {
+            msg!("Instruction: OfferLoan");
+            process_offer_loan(program_id, accounts, interest_rate, duration)
+        }
+        LoanInstruction::TakeOffer => {
+            msg!("Instruction: TakeOffer");
+            process_take_offer(program_id, accounts)
+        }
+        LoanInstruction::CancelOffer => {
+            msg!("Instruction: CancelOffer");
+            process_cancel_offer(program_id, accounts)
+        }
+        LoanInstruction::CheckHealth => {
+            msg!("Instruction: CheckHealth");
+            process_check_health(program_id, accounts)
+        }
+        LoanInstruction::Liquidate { amount } => {
+            msg!("Instruction: Liquidate");
+            process_liquidate(program_id, accounts, amount)
+        }
+        LoanInstruction::AddCollateral { amount } => {
+            msg!("Instruction: AddCollateral");
+            process_add_collateral(program_id, accounts, amount)
+        }
+        LoanInstruction::ReleaseCollateral => {
+            msg!("Instruction: ReleaseCollateral");
+            process_release_collateral(program_id, accounts)
+        }
+        LoanInstruction::ExpireLoan => {
+            msg!("Instruction: ExpireLoan");
+            process_expire_loan(program_id, accounts)
+        }
+        LoanInstruction::CloseLoan => {
+            msg!("Instruction: CloseLoan");
+            process_close_loan(program_id, accounts)
+        }
+        LoanInstruction::MigrateConfig => {
+            msg!("Instruction: MigrateConfig");
+            process_migrate_config(program_id, accounts)
+        }
+        LoanInstruction::WithdrawRepayment => {
+            msg!("Instruction: WithdrawRepayment");
+            process_withdraw_repayment(program_id, accounts)
         }
     }
 }
@@ -78,41 +394,49 @@ pub fn process_init_loan(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount: u64,
+    duration: u32,
+    max_interest_rate: u32,
+    collateral_mint: Pubkey,
 ) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-
-    // get the initializer and assert that they can sign
-    let initializer = next_account_info(account_info_iter)?;
-    if !initializer.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
+    let InitLoanAccounts {
+        initializer,
+        loan_mint: loan_mint_account,
+        borrower_loan_receive: token_to_receive_account,
+        loan: loan_account,
+        rent: rent_info,
+        config: config_info,
+        clock: clock_info,
+    } = InitLoanAccounts::from_account_infos(&mut accounts.iter())?;
 
     // get the mint account to be used for this loan
-    let loan_mint_account = next_account_info(account_info_iter)?;
+    let loan_mint = unpack_mint(loan_mint_account)?;
 
     // the account that will receive the loan if it goes through
-    // ensure that it is owned by the program
-    let token_to_receive_account = next_account_info(account_info_iter)?;
-    if *token_to_receive_account.owner != spl_token::id() {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    // ensure that it is a token account of the loan mint owned by the initializer
+    unpack_token_account(token_to_receive_account, loan_mint_account.key, Some(initializer.key))?;
 
     // next get the loan account.  This will be used to store state/data
     // about the loan.  We need to ensure it is owned by the program
-    let loan_account = next_account_info(account_info_iter)?;
     if *loan_account.owner != *program_id {
         return Err(ProgramError::IncorrectProgramId);
     }
 
     // get the rent sysvar and check if the loan account is rent exempt
-    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let rent = &Rent::from_account_info(rent_info)?;
     if !rent.is_exempt(loan_account.lamports(), loan_account.data_len()) {
         return Err(LoanError::NotRentExempt.into());
     }
-    // fail if loan account does not cover application fee
-    let fee = get_application_fee(&initializer.key, amount) * amount as f64;
-    if loan_account.lamports() < fee as u64  {
-        return Err(ProgramError::InsufficientFunds);
+    // get the program config, it holds the loan pricing
+    let config = unpack_config(program_id, config_info)?;
+    let clock = &Clock::from_account_info(clock_info)?;
+    // the borrower picks the duration, within the limits set by the program
+    if duration < MIN_DURATION || duration > get_max_duration(&config, initializer.key, amount) {
+        return Err(LoanError::InvalidDuration.into());
+    }
+    // fail if the program charges more interest than the borrower accepts
+    let interest_rate = get_interest_rate(&config, initializer.key, amount);
+    if interest_rate > max_interest_rate {
+        return Err(LoanError::InterestRateTooHigh.into());
     }
 
     // get the loan information
@@ -124,15 +448,34 @@ pub fn process_init_loan(
     // create the Loan object
     msg!("Saving loan information...");
     loan_info.is_initialized = true;
-    loan_info.status = LoanStatus::Initialized as u8;
+    loan_info.status = loan_info.status.transition(LoanAction::Init)?;
     loan_info.initializer_pubkey = *initializer.key;
     loan_info.loan_mint_pubkey = *loan_mint_account.key;
+    loan_info.loan_mint_decimals = loan_mint.decimals;
     loan_info.borrower_loan_receive_pubkey = *token_to_receive_account.key;
+    loan_info.created_at = clock.unix_timestamp;
     loan_info.expected_amount = amount;
-    loan_info.interest_rate = get_interest_rate(&initializer.key,  amount);
-    loan_info.duration = get_duration(&initializer.key,  amount);
-    loan_info.amount = get_borrowed_amount(&initializer.key, amount, loan_info.duration, loan_info.interest_rate);
+    loan_info.interest_rate = interest_rate;
+    loan_info.max_interest_rate = max_interest_rate;
+    loan_info.duration = duration;
+    loan_info.collateral_mint_pubkey = COption::Some(collateral_mint);
+    loan_info.collateral_ratio = get_collateral_ratio(&config, initializer.key, amount);
+    loan_info.amount = get_borrowed_amount(&config, &initializer.key, amount, loan_info.duration, loan_info.interest_rate)?;
+    // the loan's vaults are owned by a program derived address unique to this loan
+    let (_loan_authority, bump_seed) = find_loan_authority_address(program_id, loan_account.key);
+    loan_info.bump_seed = bump_seed;
+    let event = LoanEvent::LoanInitialized {
+        loan: *loan_account.key,
+        initializer: loan_info.initializer_pubkey,
+        loan_mint: loan_info.loan_mint_pubkey,
+        expected_amount: loan_info.expected_amount,
+        amount: loan_info.amount,
+        interest_rate: loan_info.interest_rate,
+        duration: loan_info.duration,
+        status: loan_info.status,
+    };
     Loan::pack(loan_info, &mut loan_account.data.borrow_mut())?;
+    event.emit();
 
     Ok(())
 }
@@ -142,89 +485,138 @@ pub fn process_guarantee_loan(
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    // get the guarantor and assert that they can sign
-    let guarantor_info = next_account_info(account_info_iter)?;
-    if !guarantor_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-    // get the collateral_account owned by the guarantor
-    let collateral_account_info = next_account_info(account_info_iter)?;
-    // get the collateral_account owned by the guarantor
-    let guarantor_payment_account_info = next_account_info(account_info_iter)?;
+    let GuaranteeLoanAccounts {
+        guarantor: guarantor_info,
+        collateral_source: collateral_source_account_info,
+        guarantor_repayment: guarantor_payment_account_info,
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        loan_authority: loan_authority_info,
+        collateral_mint: collateral_mint_info,
+        rent: rent_info,
+        system_program,
+        token_program,
+        clock: clock_info,
+    } = GuaranteeLoanAccounts::from_account_infos(account_info_iter)?;
 
     // get the loan account and assert that it is owned by the program
-    let loan_account_info = next_account_info(account_info_iter)?;
     if *loan_account_info.owner != *program_id {
         return Err(ProgramError::IncorrectProgramId);
     }
     // get the rent sysvar and check if the loan account is rent exempt
-    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let rent = &Rent::from_account_info(rent_info)?;
     if !rent.is_exempt(loan_account_info.lamports(), loan_account_info.data_len()) {
         return Err(LoanError::NotRentExempt.into());
     }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let clock = &Clock::from_account_info(clock_info)?;
     // get the loan data
     let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
     // fail if loan is not initialized
     if !loan_data.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
-    // fail if the status is not LoanStatus::Initialized
-    if loan_data.status != LoanStatus::Initialized as u8 {
-        return Err(LoanError::InvalidInstruction.into());
+    // fail if the loan can not be guaranteed in its current status
+    let status = loan_data.status.transition(LoanAction::Guarantee)?;
+    // the collateral may be in a different mint from the loan, but it has to be the
+    // one the borrower asked for.  Loans migrated from version 1 did not name one
+    if loan_data.collateral_mint_pubkey.is_some()
+        && loan_data.collateral_mint_pubkey != COption::Some(*collateral_mint_info.key)
+    {
+        return Err(LoanError::TokenMintMismatch.into());
     }
+    let collateral_mint = unpack_mint(collateral_mint_info)?;
+    // the collateral has to be worth the loan's collateral ratio of the loan amount
+    let (loan_mint_decimals, price, exponent) = get_collateral_price(
+        program_id,
+        None,
+        &loan_data,
+        collateral_mint_info.key,
+        &collateral_mint,
+        account_info_iter,
+        clock.unix_timestamp,
+    )?;
+    let collateral_amount = get_collateral_amount(
+        loan_data.amount,
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+        loan_data.required_collateral_ratio(),
+    )?;
     // fail if collateral is not sufficient
-    if collateral_account_info.lamports() < loan_data.amount {
-        return Err(ProgramError::InsufficientFunds);
+    let collateral_source_account = unpack_token_account(
+        collateral_source_account_info,
+        collateral_mint_info.key,
+        Some(guarantor_info.key),
+    )?;
+    if collateral_source_account.amount < collateral_amount {
+        return Err(LoanError::InsufficientCollateral.into());
     }
+    // the guarantor is repaid in the loan mint
+    unpack_token_account(guarantor_payment_account_info, &loan_data.loan_mint_pubkey, Some(guarantor_info.key))?;
     // fail if guarantor_payment_account_info is not rent-exempt
     if !rent.is_exempt(guarantor_payment_account_info.lamports(), guarantor_payment_account_info.data_len()) {
         return Err(LoanError::NotRentExempt.into());
     }
+    // the collateral vault and its authority are derived from the loan account
+    let (collateral_vault, vault_bump_seed) = find_collateral_vault_address(program_id, loan_account_info.key);
+    if *collateral_vault_info.key != collateral_vault {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let loan_authority = loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)?;
+    if *loan_authority_info.key != loan_authority {
+        return Err(ProgramError::InvalidSeeds);
+    }
     // update loan info
     msg!("Updating loan information with guarantor details...");
-    loan_data.status = LoanStatus::Guaranteed as u8;
+    loan_data.status = status;
     loan_data.guarantor_pubkey = Some(*guarantor_info.key).into();
     loan_data.guarantor_repayment_pubkey = Some(*guarantor_payment_account_info.key).into();
-    loan_data.collateral_account_pubkey = Some(*collateral_account_info.key).into();
+    loan_data.collateral_account_pubkey = Some(collateral_vault).into();
+    loan_data.collateral_mint_pubkey = Some(*collateral_mint_info.key).into();
+    loan_data.guaranteed_at = clock.unix_timestamp;
     Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
-    // get the program derived address
-    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"loan"], program_id);
-    // change the owner of the collateral account to be the pda
-    // essentially the program now fully controls the loan collateral
-    let token_program = next_account_info(account_info_iter)?;
-    let collateral_acc_owner_change_ix = spl_token::instruction::set_authority(
-        token_program.key,
-        collateral_account_info.key,
-        Some(&pda),
-        spl_token::instruction::AuthorityType::AccountOwner,
-        guarantor_info.key,
-        &[&guarantor_info.key],
-    )?;
-    msg!("Calling the token program to transfer collateral account ownership...");
-    invoke(
-        &collateral_acc_owner_change_ix,
-        &[
-            collateral_account_info.clone(),
-            guarantor_info.clone(),
-            token_program.clone(),
-        ],
+    LoanEvent::LoanGuaranteed {
+        loan: *loan_account_info.key,
+        guarantor: *guarantor_info.key,
+        collateral_mint: *collateral_mint_info.key,
+        collateral_amount,
+        status,
+    }.emit();
+
+    // create the collateral vault.  It is a token account that only the loan
+    // authority can move funds out of, so the collateral is isolated from other loans
+    create_loan_vault(
+        COLLATERAL_VAULT_SEED,
+        loan_account_info.key,
+        vault_bump_seed,
+        guarantor_info,
+        collateral_vault_info,
+        collateral_mint_info,
+        loan_authority_info,
+        rent,
+        rent_info,
+        system_program,
+        token_program,
     )?;
-    // similar change the ownership of the repayment account to be owned by
-    // program.  This prevents future errors e.g. we don't want the account
-    // deleted by the time we try to repay
-    let guarantor_payment_acc_owner_change_ix = spl_token::instruction::set_authority(
+    // move the collateral into the vault
+    let transfer_collateral_ix = spl_token::instruction::transfer(
         token_program.key,
-        guarantor_payment_account_info.key,
-        Some(&pda),
-        spl_token::instruction::AuthorityType::AccountOwner,
+        collateral_source_account_info.key,
+        &collateral_vault,
         guarantor_info.key,
         &[&guarantor_info.key],
+        collateral_amount,
     )?;
-    msg!("Calling the token program to transfer guarantor payment account ownership...");
+    msg!("Calling the token program to transfer the collateral into the vault...");
     invoke(
-        &guarantor_payment_acc_owner_change_ix,
+        &transfer_collateral_ix,
         &[
-            guarantor_payment_account_info.clone(),
+            collateral_source_account_info.clone(),
+            collateral_vault_info.clone(),
             guarantor_info.clone(),
             token_program.clone(),
         ],
@@ -237,31 +629,33 @@ pub fn process_accept_loan(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
+    let AcceptLoanAccounts {
+        lender: lender_info,
+        lender_loan_transfer: lender_loan_transfer_info,
+        lender_repayment: lender_repayment_account_info,
+        borrower_loan_receive: borrower_loan_receive_account_info,
+        loan: loan_account_info,
+        rent: rent_info,
+        token_program,
+        clock: clock_info,
+        config: config_info,
+        treasury: treasury_info,
+        repayment_vault: repayment_vault_info,
+        loan_authority: loan_authority_info,
+        loan_mint: loan_mint_info,
+        system_program,
+    } = AcceptLoanAccounts::from_account_infos(&mut accounts.iter())?;
 
-    // get the lender and assert that they can sign
-    let lender_info = next_account_info(account_info_iter)?;
-    if !lender_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-    // get the loan transfer account owned by the lender
-    let lender_loan_transfer_info = next_account_info(account_info_iter)?;
-
-    // the account that will receive the loan when it is repaid
-    let lender_repayment_account_info = next_account_info(account_info_iter)?;
-    if *lender_repayment_account_info.owner != spl_token::id() {
-        return Err(ProgramError::IncorrectProgramId);
-    }
-    // the account that will receive the loan when it is repaid
-    let borrower_loan_receive_account_info = next_account_info(account_info_iter)?;
     // next get the loan account.  This will be used to store state/data
     // about the loan.  We need to ensure it is owned by the program
-    let loan_account_info = next_account_info(account_info_iter)?;
     if *loan_account_info.owner != *program_id {
         return Err(ProgramError::IncorrectProgramId);
     }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
     // get the rent sysvar and check if the loan account is rent exempt
-    let rent = &Rent::from_account_info(next_account_info(account_info_iter)?)?;
+    let rent = &Rent::from_account_info(rent_info)?;
     if !rent.is_exempt(loan_account_info.lamports(), loan_account_info.data_len()) {
         return Err(LoanError::NotRentExempt.into());
     }
@@ -275,62 +669,115 @@ pub fn process_accept_loan(
     if !loan_data.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
-    // fail if the status is not LoanStatus::Guaranteed
-    if loan_data.status != LoanStatus::Guaranteed as u8 {
-        return Err(LoanError::InvalidInstruction.into());
-    }
+    // fail if the loan can not be accepted in its current status
+    let status = loan_data.status.transition(LoanAction::Accept)?;
     // Ensure we have the right account to send borrowed funds to
     if *borrower_loan_receive_account_info.key != loan_data.borrower_loan_receive_pubkey {
-        return Err(LoanError::NotAuthorized.into());
+        return Err(LoanError::AccountMismatch.into());
     }
+    // all the token accounts must be in the loan mint
+    let lender_loan_transfer_account = unpack_token_account(
+        lender_loan_transfer_info,
+        &loan_data.loan_mint_pubkey,
+        Some(lender_info.key),
+    )?;
+    unpack_token_account(lender_repayment_account_info, &loan_data.loan_mint_pubkey, Some(lender_info.key))?;
+    unpack_token_account(borrower_loan_receive_account_info, &loan_data.loan_mint_pubkey, None)?;
     // fail if loan transfer account balance is not sufficient
-    if lender_loan_transfer_info.lamports() < loan_data.expected_amount {
-        return Err(ProgramError::InsufficientFunds);
+    assert_token_balance(&lender_loan_transfer_account, loan_data.expected_amount)?;
+    // repayments are held in a vault derived from the loan account until the lender
+    // withdraws them, so nothing the lender does to their own account can fail them
+    if *loan_mint_info.key != loan_data.loan_mint_pubkey {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    let (repayment_vault, vault_bump_seed) = find_repayment_vault_address(program_id, loan_account_info.key);
+    if *repayment_vault_info.key != repayment_vault {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // the loan duration starts running from the time the loan is accepted
+    let clock = &Clock::from_account_info(clock_info)?;
+    // get the program config and the treasury that collects the application fee
+    let config = unpack_config(program_id, config_info)?;
+    if *treasury_info.key != find_treasury_address(program_id, &loan_data.loan_mint_pubkey).0 {
+        return Err(ProgramError::InvalidSeeds);
     }
-    let amount: u64 = loan_data.expected_amount;
+    // the application fee is taken out of the amount sent to the borrower
+    let application_fee = apply_basis_points(loan_data.expected_amount, get_application_fee(
+        &config,
+        &loan_data.initializer_pubkey,
+        loan_data.expected_amount,
+    ))?;
+    let amount: u64 = loan_data.expected_amount
+        .checked_sub(application_fee)
+        .ok_or(LoanError::MathOverflow)?;
     // update loan info
     msg!("Updating loan information with lender details...");
-    loan_data.status = LoanStatus::Accepted as u8;
+    loan_data.status = status;
     loan_data.lender_pubkey = Some(*lender_info.key).into();
     loan_data.lender_repayment_pubkey = Some(*lender_repayment_account_info.key).into();
+    loan_data.repayment_vault_pubkey = COption::Some(repayment_vault);
+    loan_data.accepted_at = clock.unix_timestamp;
+    loan_data.due_at = get_due_at(clock.unix_timestamp, loan_data.duration)?;
+    let due_at = loan_data.due_at;
     Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
-    // change the owner of the loan repayment info account to be the pda
-    // essentially the program now fully controls the loan repayment account
-    // get the program derived address
-    let (pda, _bump_seed) = Pubkey::find_program_address(&[b"loan"], program_id);
-    let token_program = next_account_info(account_info_iter)?;
-    let owner_change_ix = spl_token::instruction::set_authority(
+    LoanEvent::LoanAccepted {
+        loan: *loan_account_info.key,
+        lender: *lender_info.key,
+        amount,
+        application_fee,
+        due_at,
+        status,
+    }.emit();
+    create_loan_vault(
+        REPAYMENT_VAULT_SEED,
+        loan_account_info.key,
+        vault_bump_seed,
+        lender_info,
+        repayment_vault_info,
+        loan_mint_info,
+        loan_authority_info,
+        rent,
+        rent_info,
+        system_program,
+        token_program,
+    )?;
+    // transfer the funds to the borrower
+    let transfer_to_initializer_ix = spl_token::instruction::transfer(
         token_program.key,
-        lender_repayment_account_info.key,
-        Some(&pda),
-        spl_token::instruction::AuthorityType::AccountOwner,
+        lender_loan_transfer_info.key,
+        borrower_loan_receive_account_info.key,
         lender_info.key,
         &[&lender_info.key],
+        amount,
     )?;
-    msg!("Calling the token program to transfer loan repayment account ownership...");
+    msg!("Calling the token program to transfer tokens to the borrower...");
     invoke(
-        &owner_change_ix,
+        &transfer_to_initializer_ix,
         &[
-            lender_repayment_account_info.clone(),
+            lender_loan_transfer_info.clone(),
+            borrower_loan_receive_account_info.clone(),
             lender_info.clone(),
             token_program.clone(),
         ],
     )?;
-    // transfer the funds to the borrower
-    let transfer_to_initializer_ix = spl_token::instruction::transfer(
+    // transfer the application fee to the treasury
+    let transfer_to_treasury_ix = spl_token::instruction::transfer(
         token_program.key,
         lender_loan_transfer_info.key,
-        borrower_loan_receive_account_info.key,
+        treasury_info.key,
         lender_info.key,
         &[&lender_info.key],
-        amount,
+        application_fee,
     )?;
-    msg!("Calling the token program to transfer tokens to the borrower...");
+    msg!("Calling the token program to transfer the application fee to the treasury...");
     invoke(
-        &transfer_to_initializer_ix,
+        &transfer_to_treasury_ix,
         &[
             lender_loan_transfer_info.clone(),
-            borrower_loan_receive_account_info.clone(),
+            treasury_info.clone(),
             lender_info.clone(),
             token_program.clone(),
         ],
@@ -342,21 +789,28 @@ pub fn process_accept_loan(
 pub fn process_repay_loan(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
+    amount: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
-    // get the payer and assert that they can sign
-    let payer_info = next_account_info(account_info_iter)?;
-    if !payer_info.is_signer {
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-    // get the accounts
-    let payer_token_account_info = next_account_info(account_info_iter)?;
-    let guarantor_account_info = next_account_info(account_info_iter)?;
-    let collateral_token_account_info = next_account_info(account_info_iter)?;
-    let guarantor_token_account_info = next_account_info(account_info_iter)?;
-    let lender_account_info = next_account_info(account_info_iter)?;
-    let lender_token_account_info = next_account_info(account_info_iter)?;
-    let loan_account_info = next_account_info(account_info_iter)?;
+    let RepayLoanAccounts {
+        payer: payer_info,
+        payer_token: payer_token_account_info,
+        guarantor_repayment: guarantor_token_account_info,
+        lender_repayment: lender_token_account_info,
+        loan: loan_account_info,
+        config: config_info,
+        treasury: treasury_info,
+        token_program,
+        clock: clock_info,
+    } = RepayLoanAccounts::from_account_infos(account_info_iter)?;
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the program config, it holds the repayment shares
+    let config = unpack_config(program_id, config_info)?;
 
     // get the loan data
     let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
@@ -364,164 +818,2168 @@ pub fn process_repay_loan(
     if !loan_data.is_initialized() {
         return Err(ProgramError::UninitializedAccount);
     }
-    // fail if the status is not LoanStatus::Accepted
-    if loan_data.status != LoanStatus::Accepted as u8 {
-        return Err(LoanError::InvalidInstruction.into());
+    // fail if the loan can not be repaid in its current status
+    let status = if amount == loan_data.outstanding_amount() {
+        loan_data.status.transition(LoanAction::RepayInFull)?
+    } else {
+        loan_data.status.transition(LoanAction::Repay)?
+    };
+    // fail if nothing is being repaid, or more than what is outstanding
+    if amount == 0 || amount > loan_data.outstanding_amount() {
+        return Err(LoanError::InvalidAmount.into());
     }
     // fail if repayment transfer account balance is not sufficient
-    if payer_token_account_info.lamports() < loan_data.amount {
-        return Err(ProgramError::InsufficientFunds);
+    let payer_token_account = unpack_token_account(
+        payer_token_account_info,
+        &loan_data.loan_mint_pubkey,
+        Some(payer_info.key),
+    )?;
+    assert_token_balance(&payer_token_account, amount)?;
+    // Ensure we have the right accounts to send the repayment to
+    check_repayment_accounts(
+        program_id,
+        &loan_data,
+        guarantor_token_account_info,
+        lender_token_account_info,
+        treasury_info,
+    )?;
+    // calculate repayments.  The payment covers interest and principal in the same
+    // proportion as the loan amount does
+    let split = get_repayment_split(&config, &loan_data, amount)?;
+
+    // get the token program
+    let clock = &Clock::from_account_info(clock_info)?;
+
+    transfer_repayment(
+        payer_info,
+        payer_token_account_info,
+        guarantor_token_account_info,
+        lender_token_account_info,
+        treasury_info,
+        token_program,
+        &split,
+    )?;
+
+    // update loan info
+    loan_data.amount_repaid = loan_data.amount_repaid
+        .checked_add(amount)
+        .ok_or(LoanError::MathOverflow)?;
+    loan_data.status = status;
+    let event = LoanEvent::LoanRepaid {
+        loan: *loan_account_info.key,
+        payer: *payer_info.key,
+        amount,
+        amount_repaid: loan_data.amount_repaid,
+        outstanding_amount: loan_data.outstanding_amount(),
+        status,
+    };
+    if status != LoanStatus::Repaid {
+        msg!("Updating loan information with the repaid amount...");
+        Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+        event.emit();
+        return Ok(());
     }
-    // Ensure we have the right account to send guarantor funds to
-    let guarantor_account_option = Some(*guarantor_account_info.key);
-    let guarantor_account_c_option: COption<Pubkey> = guarantor_account_option.into();
-    if guarantor_account_c_option != loan_data.guarantor_pubkey {
-        return Err(LoanError::NotAuthorized.into());
+    msg!("Updating loan information, setting status to repaid...");
+    loan_data.closed_at = clock.unix_timestamp;
+    let guarantor_pubkey = loan_data.guarantor_pubkey;
+    let collateral_account_pubkey = loan_data.collateral_account_pubkey;
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey;
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    event.emit();
+
+    // the loan is repaid, hand the collateral back to the guarantor
+    let collateral_accounts = ReturnCollateralAccounts::from_account_infos(account_info_iter)?;
+    return_collateral(
+        program_id,
+        loan_account_info.key,
+        bump_seed,
+        &guarantor_pubkey,
+        &collateral_account_pubkey,
+        &collateral_mint_pubkey,
+        &collateral_accounts,
+        token_program,
+    )
+}
+
+pub fn process_cancel_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let CancelLoanAccounts {
+        initializer: initializer_info,
+        loan: loan_account_info,
+        clock: clock_info,
+        token_program,
+    } = CancelLoanAccounts::from_account_infos(account_info_iter)?;
+    // get the loan account and assert that it is owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
     }
-    let guarantor_token_account_option = Some(*guarantor_token_account_info.key);
-    let guarantor_token_account_c_option: COption<Pubkey> = guarantor_token_account_option.into();
-    if guarantor_token_account_c_option != loan_data.guarantor_repayment_pubkey {
-        return Err(LoanError::NotAuthorized.into());
+    let clock = &Clock::from_account_info(clock_info)?;
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
     }
-    let collateral_token_account_option = Some(*collateral_token_account_info.key);
-    let collateral_token_account_c_option: COption<Pubkey> = collateral_token_account_option.into();
-    if collateral_token_account_c_option != loan_data.collateral_account_pubkey {
+    // only the borrower can cancel the loan
+    if *initializer_info.key != loan_data.initializer_pubkey {
         return Err(LoanError::NotAuthorized.into());
     }
-    // Ensure we have the right account to send repaid funds to
+    // fail if the loan has already been accepted, repaid or cancelled
+    let is_guaranteed = loan_data.status == LoanStatus::Guaranteed;
+    let status = loan_data.status.transition(LoanAction::Cancel)?;
+    // update loan info
+    msg!("Updating loan information, setting status to cancelled...");
+    loan_data.status = status;
+    loan_data.closed_at = clock.unix_timestamp;
+    let guarantor_pubkey = loan_data.guarantor_pubkey;
+    let collateral_account_pubkey = loan_data.collateral_account_pubkey;
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey;
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    LoanEvent::LoanCancelled {
+        loan: *loan_account_info.key,
+        initializer: *initializer_info.key,
+        status,
+    }.emit();
+
+    if !is_guaranteed {
+        return Ok(());
+    }
+    // the loan was guaranteed, hand the collateral back to the guarantor
+    let collateral_accounts = ReturnCollateralAccounts::from_account_infos(account_info_iter)?;
+    return_collateral(
+        program_id,
+        loan_account_info.key,
+        bump_seed,
+        &guarantor_pubkey,
+        &collateral_account_pubkey,
+        &collateral_mint_pubkey,
+        &collateral_accounts,
+        token_program,
+    )
+}
+
+pub fn process_claim_default(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let ClaimDefaultAccounts {
+        lender: lender_account_info,
+        lender_collateral: lender_collateral_account_info,
+        guarantor: guarantor_account_info,
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        loan_authority: loan_authority_info,
+        token_program,
+        clock: clock_info,
+    } = ClaimDefaultAccounts::from_account_infos(&mut accounts.iter())?;
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let clock = &Clock::from_account_info(clock_info)?;
+
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan can not be defaulted in its current status
+    let status = loan_data.status.transition(LoanAction::ClaimDefault)?;
+    // Ensure only the lender can claim, and that the collateral goes to them
     let lender_account_option = Some(*lender_account_info.key);
     let lender_account_c_option: COption<Pubkey> = lender_account_option.into();
     if lender_account_c_option != loan_data.lender_pubkey {
         return Err(LoanError::NotAuthorized.into());
     }
-    let lender_token_option = Some(*lender_token_account_info.key);
-    let lender_token_c_option: COption<Pubkey> = lender_token_option.into();
-    if lender_token_c_option != loan_data.lender_repayment_pubkey {
-        return Err(LoanError::NotAuthorized.into());
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey.ok_or(LoanError::TokenMintMismatch)?;
+    unpack_token_account(lender_collateral_account_info, &collateral_mint_pubkey, Some(lender_account_info.key))?;
+    // the guarantor paid for the vault, so they get its rent back
+    let guarantor_account_option = Some(*guarantor_account_info.key);
+    let guarantor_account_c_option: COption<Pubkey> = guarantor_account_option.into();
+    if guarantor_account_c_option != loan_data.guarantor_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    let collateral_vault_option = Some(*collateral_vault_info.key);
+    let collateral_vault_c_option: COption<Pubkey> = collateral_vault_option.into();
+    if collateral_vault_c_option != loan_data.collateral_account_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // fail if the loan is not yet due
+    if clock.unix_timestamp < loan_data.due_at {
+        return Err(LoanError::LoanNotDue.into());
     }
-    // calculate repayments
-    let loan_interest = (loan_data.amount - loan_data.expected_amount) as f64;
-    let program_share = loan_interest as f64 * get_processing_fee(
-        &loan_data.initializer_pubkey,
-        loan_data.expected_amount,
-        loan_data.duration,
-        loan_data.interest_rate
-    ) as f64 / 100 as f64;
-    let lender_share = (loan_interest - program_share) * (get_lender_share(lender_account_info.key, loan_data.amount) as f64 / 100 as f64);
-    let total_lender_share = lender_share as u64 + loan_data.expected_amount;
-    let guarantor_share = (loan_interest - program_share) * (get_guarantor_share(guarantor_account_info.key, loan_data.amount) as f64 / 100 as f64);
     // update loan info
-    msg!("Updating loan information, setting status to repaid...");
-    loan_data.status = LoanStatus::Repaid as u8;
+    msg!("Updating loan information, setting status to defaulted...");
+    loan_data.status = status;
+    loan_data.closed_at = clock.unix_timestamp;
+    let bump_seed = loan_data.bump_seed;
     Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
 
-    // get the pda and token acconts
-    let pda_account_info = next_account_info(account_info_iter)?;
-    let token_program = next_account_info(account_info_iter)?;
-
-    // transfer the funds to the guarantor repayment account
-    let transfer_to_guarantor_ix = spl_token::instruction::transfer(
-        token_program.key,
-        payer_token_account_info.key,
-        guarantor_token_account_info.key,
-        payer_info.key,
-        &[&payer_info.key],
-        guarantor_share as u64,
+    // transfer the whole collateral balance to the lender
+    let collateral_amount = spl_token::state::Account::unpack(&collateral_vault_info.data.borrow())?.amount;
+    LoanEvent::LoanDefaulted {
+        loan: *loan_account_info.key,
+        lender: *lender_account_info.key,
+        collateral_amount,
+        status,
+    }.emit();
+    msg!("Calling the token program to transfer the collateral to the lender...");
+    transfer_from_vault(
+        loan_account_info.key,
+        bump_seed,
+        collateral_vault_info,
+        lender_collateral_account_info,
+        loan_authority_info,
+        token_program,
+        collateral_amount,
     )?;
-    msg!("Calling the token program to transfer funds to the guarantor payment account...");
-    invoke(
-        &transfer_to_guarantor_ix,
+    msg!("Calling the token program to close the collateral vault...");
+    close_vault(
+        loan_account_info.key,
+        bump_seed,
+        collateral_vault_info,
+        guarantor_account_info,
+        loan_authority_info,
+        token_program,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn process_init_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    interest_rate: u32,
+    duration: u32,
+    processing_fee: u32,
+    application_fee: u32,
+    guarantor_share: u32,
+    lender_share: u32,
+    collateral_ratio: u32,
+    maintenance_ratio: u32,
+    liquidation_bonus: u32,
+    oracle_program_id: Pubkey,
+    oracle_publisher: Pubkey,
+) -> ProgramResult {
+    let InitConfigAccounts {
+        admin: admin_info,
+        config: config_info,
+        rent: rent_info,
+        system_program,
+        program_data: program_data_info,
+    } = InitConfigAccounts::from_account_infos(&mut accounts.iter())?;
+    // only whoever can upgrade the program can create its config, otherwise anyone
+    // watching the deployment could make themselves the admin first
+    if get_upgrade_authority(program_id, program_data_info)? != Some(*admin_info.key) {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    // get the config account, it is derived from the program id so there is only one
+    let (config_pubkey, bump_seed) = find_config_address(program_id);
+    if *config_info.key != config_pubkey {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let rent = &Rent::from_account_info(rent_info)?;
+
+    // create the config account
+    let create_config_ix = system_instruction::create_account(
+        admin_info.key,
+        &config_pubkey,
+        rent.minimum_balance(ProgramConfig::LEN),
+        ProgramConfig::LEN as u64,
+        program_id,
+    );
+    msg!("Calling the system program to create the program config...");
+    invoke_signed(
+        &create_config_ix,
         &[
-            payer_token_account_info.clone(),
-            guarantor_token_account_info.clone(),
-            payer_info.clone(),
-            token_program.clone(),
+            admin_info.clone(),
+            config_info.clone(),
+            system_program.clone(),
         ],
+        &[&[CONFIG_SEED, &[bump_seed]]],
     )?;
 
-    // transfer the funds to the lender repayment account
-    let transfer_to_lender_ix = spl_token::instruction::transfer(
+    msg!("Saving program config...");
+    let config = ProgramConfig {
+        is_initialized: true,
+        admin_pubkey: *admin_info.key,
+        interest_rate,
+        duration,
+        processing_fee,
+        application_fee,
+        guarantor_share,
+        lender_share,
+        bump_seed,
+        collateral_ratio,
+        maintenance_ratio,
+        liquidation_bonus,
+        oracle_program_id,
+        oracle_publisher,
+    };
+    validate_config(&config)?;
+    ProgramConfig::pack(config, &mut config_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn process_update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    interest_rate: u32,
+    duration: u32,
+    processing_fee: u32,
+    application_fee: u32,
+    guarantor_share: u32,
+    lender_share: u32,
+    collateral_ratio: u32,
+    maintenance_ratio: u32,
+    liquidation_bonus: u32,
+    oracle_program_id: Pubkey,
+    oracle_publisher: Pubkey,
+) -> ProgramResult {
+    let UpdateConfigAccounts {
+        admin: admin_info,
+        config: config_info,
+    } = UpdateConfigAccounts::from_account_infos(&mut accounts.iter())?;
+    let mut config = unpack_config(program_id, config_info)?;
+    // only the admin can change the config
+    if *admin_info.key != config.admin_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+
+    msg!("Updating program config...");
+    config.interest_rate = interest_rate;
+    config.duration = duration;
+    config.processing_fee = processing_fee;
+    config.application_fee = application_fee;
+    config.guarantor_share = guarantor_share;
+    config.lender_share = lender_share;
+    config.collateral_ratio = collateral_ratio;
+    config.maintenance_ratio = maintenance_ratio;
+    config.liquidation_bonus = liquidation_bonus;
+    config.oracle_program_id = oracle_program_id;
+    config.oracle_publisher = oracle_publisher;
+    validate_config(&config)?;
+    config.pack_into_account(&mut config_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_init_treasury(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let InitTreasuryAccounts {
+        admin: admin_info,
+        config: config_info,
+        treasury: treasury_info,
+        mint: mint_info,
+        rent: rent_info,
+        system_program,
+        token_program,
+    } = InitTreasuryAccounts::from_account_infos(&mut accounts.iter())?;
+    let config = unpack_config(program_id, config_info)?;
+    // only the admin can create treasuries
+    if *admin_info.key != config.admin_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    // get the treasury account, it is derived from the mint so there is one per mint
+    let (treasury_pubkey, bump_seed) = find_treasury_address(program_id, mint_info.key);
+    if *treasury_info.key != treasury_pubkey {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let rent = &Rent::from_account_info(rent_info)?;
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // create the treasury token account, owned by the program config
+    let create_treasury_ix = system_instruction::create_account(
+        admin_info.key,
+        &treasury_pubkey,
+        rent.minimum_balance(spl_token::state::Account::LEN),
+        spl_token::state::Account::LEN as u64,
         token_program.key,
-        payer_token_account_info.key,
-        lender_token_account_info.key,
-        payer_info.key,
-        &[&payer_info.key],
-        total_lender_share,
-    )?;
-    msg!("Calling the token program to transfer funds to the lender payment account...");
-    invoke(
-        &transfer_to_lender_ix,
+    );
+    msg!("Calling the system program to create the treasury...");
+    invoke_signed(
+        &create_treasury_ix,
         &[
-            payer_token_account_info.clone(),
-            lender_token_account_info.clone(),
-            payer_info.clone(),
-            token_program.clone(),
+            admin_info.clone(),
+            treasury_info.clone(),
+            system_program.clone(),
         ],
+        &[&[TREASURY_SEED, mint_info.key.as_ref(), &[bump_seed]]],
     )?;
-    // get pda and nonce
-    let (pda, nonce) = Pubkey::find_program_address(&[b"loan"], program_id);
-    // change the owner of the collateral account to be the original guarantor
-    let return_collateral_ix = spl_token::instruction::set_authority(
+    let init_treasury_ix = spl_token::instruction::initialize_account(
         token_program.key,
-        collateral_token_account_info.key,
-        Some(guarantor_account_info.key),
-        spl_token::instruction::AuthorityType::AccountOwner,
-        &pda,
-        &[&pda],
+        &treasury_pubkey,
+        mint_info.key,
+        config_info.key,
     )?;
-    msg!("Calling the token program to return collateral account to the guarantor...");
-    invoke_signed(
-        &return_collateral_ix,
+    msg!("Calling the token program to initialize the treasury...");
+    invoke(
+        &init_treasury_ix,
         &[
-            collateral_token_account_info.clone(),
-            guarantor_account_info.clone(),
-            pda_account_info.clone(),
+            treasury_info.clone(),
+            mint_info.clone(),
+            config_info.clone(),
+            rent_info.clone(),
             token_program.clone(),
         ],
-        &[&[&b"loan"[..], &[nonce]]],
     )?;
-    // change the owner of the guarantor payment account to be the original guarantor
-    let pay_guarantor_ix = spl_token::instruction::set_authority(
+
+    Ok(())
+}
+
+pub fn process_withdraw_treasury(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let WithdrawTreasuryAccounts {
+        admin: admin_info,
+        config: config_info,
+        treasury: treasury_info,
+        destination: destination_info,
+        token_program,
+    } = WithdrawTreasuryAccounts::from_account_infos(&mut accounts.iter())?;
+    let config = unpack_config(program_id, config_info)?;
+    // only the admin can withdraw from the treasuries
+    if *admin_info.key != config.admin_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // the program config owns the treasuries, so it signs for the transfer
+    let transfer_ix = spl_token::instruction::transfer(
         token_program.key,
-        guarantor_token_account_info.key,
-        Some(guarantor_account_info.key),
-        spl_token::instruction::AuthorityType::AccountOwner,
-        &pda,
-        &[&pda],
+        treasury_info.key,
+        destination_info.key,
+        config_info.key,
+        &[],
+        amount,
     )?;
-    msg!("Calling the token program to return guarantor payment account to the guarantor...");
+    msg!("Calling the token program to withdraw from the treasury...");
     invoke_signed(
-        &pay_guarantor_ix,
+        &transfer_ix,
         &[
-            guarantor_token_account_info.clone(),
-            guarantor_account_info.clone(),
-            pda_account_info.clone(),
+            treasury_info.clone(),
+            destination_info.clone(),
+            config_info.clone(),
             token_program.clone(),
         ],
-        &[&[&b"loan"[..], &[nonce]]],
+        &[&[CONFIG_SEED, &[config.bump_seed]]],
     )?;
-    // change the owner of the lender payment account to be the original lender
-    let pay_lender_ix = spl_token::instruction::set_authority(
-        token_program.key,
-        lender_token_account_info.key,
-        Some(lender_account_info.key),
-        spl_token::instruction::AuthorityType::AccountOwner,
-        &pda,
-        &[&pda],
+
+    Ok(())
+}
+
+pub fn process_migrate_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let MigrateLoanAccounts {
+        payer: payer_info,
+        initializer: initializer_info,
+        old_loan: old_loan_account_info,
+        new_loan: new_loan_account_info,
+        loan_mint: loan_mint_info,
+        rent: rent_info,
+        clock: clock_info,
+    } = MigrateLoanAccounts::from_account_infos(account_info_iter)?;
+    // get the old and new loan accounts and assert that they are owned by the program
+    if *old_loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if old_loan_account_info.data_len() != LOAN_V1_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if *new_loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the rent sysvar and check if the new loan account is rent exempt
+    let rent = &Rent::from_account_info(rent_info)?;
+    if !rent.is_exempt(new_loan_account_info.lamports(), new_loan_account_info.data_len()) {
+        return Err(LoanError::NotRentExempt.into());
+    }
+    let clock = &Clock::from_account_info(clock_info)?;
+    // get the loan data
+    let mut loan_data = Loan::unpack(&old_loan_account_info.data.borrow())?;
+    // the borrower gets the rent of the old account back
+    if *initializer_info.key != loan_data.initializer_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    // any party of the loan can migrate it, so the guarantor and lender can get at the
+    // tokens the version 1 loan authority holds without the borrower
+    if *payer_info.key != loan_data.initializer_pubkey
+        && COption::Some(*payer_info.key) != loan_data.guarantor_pubkey
+        && COption::Some(*payer_info.key) != loan_data.lender_pubkey
+    {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    let status = loan_data.status.transition(LoanAction::Migrate)?;
+    if *loan_mint_info.key != loan_data.loan_mint_pubkey {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    let loan_mint = unpack_mint(loan_mint_info)?;
+    // fail if the new loan account is already in use
+    if Loan::unpack_unchecked(&new_loan_account_info.data.borrow())?.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    // the loan authority is derived from the new loan account
+    let (loan_authority, bump_seed) = find_loan_authority_address(program_id, new_loan_account_info.key);
+    msg!("Saving loan information in the current layout...");
+    loan_data.bump_seed = bump_seed;
+    loan_data.loan_mint_decimals = loan_mint.decimals;
+    // version 1 did not record when the loan was made, so it counts from now
+    loan_data.created_at = clock.unix_timestamp;
+    // a guaranteed or accepted loan holds its tokens under the version 1 loan authority,
+    // which can only move them into the new loan's vault or back to their owners
+    if status == LoanStatus::Guaranteed || status == LoanStatus::Accepted {
+        let MigrateCollateralAccounts {
+            legacy_authority: legacy_authority_info,
+            legacy_collateral: legacy_collateral_info,
+            guarantor_repayment: guarantor_repayment_info,
+            collateral_vault: collateral_vault_info,
+            collateral_mint: collateral_mint_info,
+            loan_authority: loan_authority_info,
+            system_program,
+            token_program,
+        } = MigrateCollateralAccounts::from_account_infos(account_info_iter)?;
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let (legacy_authority, legacy_bump_seed) = find_legacy_loan_authority_address(program_id);
+        if *legacy_authority_info.key != legacy_authority {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if loan_data.collateral_account_pubkey != COption::Some(*legacy_collateral_info.key) {
+            return Err(LoanError::AccountMismatch.into());
+        }
+        if loan_data.guarantor_repayment_pubkey != COption::Some(*guarantor_repayment_info.key) {
+            return Err(LoanError::AccountMismatch.into());
+        }
+        let guarantor_pubkey = loan_data.guarantor_pubkey.ok_or(LoanError::AccountMismatch)?;
+        // version 1 did not record the collateral mint, it is the mint of the account the
+        // collateral is in.  The account is emptied, so a delegate or close authority the
+        // guarantor set on it before handing it over does not matter
+        if *legacy_collateral_info.owner != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let legacy_collateral = spl_token::state::Account::unpack(&legacy_collateral_info.data.borrow())?;
+        if legacy_collateral.mint != *collateral_mint_info.key {
+            return Err(LoanError::TokenMintMismatch.into());
+        }
+        if legacy_collateral.owner != legacy_authority {
+            return Err(LoanError::TokenOwnerMismatch.into());
+        }
+        unpack_mint(collateral_mint_info)?;
+        let (collateral_vault, vault_bump_seed) = find_collateral_vault_address(program_id, new_loan_account_info.key);
+        if *collateral_vault_info.key != collateral_vault {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if *loan_authority_info.key != loan_authority {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        loan_data.collateral_account_pubkey = COption::Some(collateral_vault);
+        loan_data.collateral_mint_pubkey = COption::Some(*collateral_mint_info.key);
+        loan_data.guaranteed_at = clock.unix_timestamp;
+        // nor when the loan was accepted, so there is no telling whether its duration has
+        // run out.  Rather than give the borrower a new term, the loan is due right away
+        if status == LoanStatus::Accepted {
+            loan_data.due_at = clock.unix_timestamp;
+        }
+
+        create_loan_vault(
+            COLLATERAL_VAULT_SEED,
+            new_loan_account_info.key,
+            vault_bump_seed,
+            payer_info,
+            collateral_vault_info,
+            collateral_mint_info,
+            loan_authority_info,
+            rent,
+            rent_info,
+            system_program,
+            token_program,
+        )?;
+        // move the collateral into the vault
+        let transfer_collateral_ix = spl_token::instruction::transfer(
+            token_program.key,
+            legacy_collateral_info.key,
+            &collateral_vault,
+            &legacy_authority,
+            &[],
+            legacy_collateral.amount,
+        )?;
+        msg!("Calling the token program to transfer the collateral into the vault...");
+        invoke_signed(
+            &transfer_collateral_ix,
+            &[
+                legacy_collateral_info.clone(),
+                collateral_vault_info.clone(),
+                legacy_authority_info.clone(),
+                token_program.clone(),
+            ],
+            &[&[LOAN_AUTHORITY_SEED, &[legacy_bump_seed]]],
+        )?;
+        // hand the emptied collateral account and the repayment accounts back
+        msg!("Calling the token program to return the version 1 token accounts...");
+        return_legacy_account(legacy_collateral_info, &guarantor_pubkey, legacy_authority_info, legacy_bump_seed, token_program)?;
+        return_legacy_account(guarantor_repayment_info, &guarantor_pubkey, legacy_authority_info, legacy_bump_seed, token_program)?;
+        if status == LoanStatus::Accepted {
+            let MigrateRepaymentAccounts {
+                lender_repayment: lender_repayment_info,
+            } = MigrateRepaymentAccounts::from_account_infos(account_info_iter)?;
+            if loan_data.lender_repayment_pubkey != COption::Some(*lender_repayment_info.key) {
+                return Err(LoanError::AccountMismatch.into());
+            }
+            let lender_pubkey = loan_data.lender_pubkey.ok_or(LoanError::AccountMismatch)?;
+            return_legacy_account(lender_repayment_info, &lender_pubkey, legacy_authority_info, legacy_bump_seed, token_program)?;
+        }
+    }
+    let due_at = loan_data.due_at;
+    Loan::pack(loan_data, &mut new_loan_account_info.data.borrow_mut())?;
+    LoanEvent::LoanMigrated {
+        loan: *new_loan_account_info.key,
+        old_loan: *old_loan_account_info.key,
+        due_at,
+        status,
+    }.emit();
+
+    // close the old loan account, returning its rent to the borrower
+    msg!("Closing the version 1 loan account...");
+    close_program_account(old_loan_account_info, initializer_info)
+}
+
+pub fn process_offer_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    interest_rate: u32,
+    duration: u32,
+) -> ProgramResult {
+    let OfferLoanAccounts {
+        lender: lender_info,
+        lender_loan_transfer: lender_loan_transfer_info,
+        lender_repayment: lender_repayment_account_info,
+        loan: loan_account_info,
+        offer: offer_account_info,
+        offer_vault: offer_vault_info,
+        loan_authority: loan_authority_info,
+        loan_mint: loan_mint_info,
+        config: config_info,
+        rent: rent_info,
+        system_program,
+        token_program,
+        clock: clock_info,
+    } = OfferLoanAccounts::from_account_infos(&mut accounts.iter())?;
+    // get the loan and offer accounts and assert that they are owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *offer_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the rent sysvar and check if the offer account is rent exempt
+    let rent = &Rent::from_account_info(rent_info)?;
+    if !rent.is_exempt(offer_account_info.lamports(), offer_account_info.data_len()) {
+        return Err(LoanError::NotRentExempt.into());
+    }
+    // get the loan data
+    let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan can not take offers in its current status
+    loan_data.status.transition(LoanAction::Offer)?;
+    // the offered terms have to be within the program limits and what the borrower
+    // accepts.  Loans requested before borrowers stated a maximum rate have none
+    let config = unpack_config(program_id, config_info)?;
+    let max_duration = get_max_duration(&config, &loan_data.initializer_pubkey, loan_data.expected_amount);
+    if duration < MIN_DURATION || duration > max_duration {
+        return Err(LoanError::InvalidDuration.into());
+    }
+    if loan_data.max_interest_rate != 0 && interest_rate > loan_data.max_interest_rate {
+        return Err(LoanError::InterestRateTooHigh.into());
+    }
+    // the offered funds and the repayments are in the loan mint
+    if *loan_mint_info.key != loan_data.loan_mint_pubkey {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    let lender_loan_transfer_account = unpack_token_account(
+        lender_loan_transfer_info,
+        &loan_data.loan_mint_pubkey,
+        Some(lender_info.key),
+    )?;
+    unpack_token_account(lender_repayment_account_info, &loan_data.loan_mint_pubkey, Some(lender_info.key))?;
+    // fail if loan transfer account balance is not sufficient
+    assert_token_balance(&lender_loan_transfer_account, loan_data.expected_amount)?;
+    // fail if the offer account is already in use
+    let offer_data = LoanOffer::unpack_unchecked(&offer_account_info.data.borrow())?;
+    if offer_data.is_initialized() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    // the offer vault is derived from the offer account, and owned by the loan authority
+    // so that the funds can only move to this loan's borrower or back to the lender
+    let (offer_vault, vault_bump_seed) = find_offer_vault_address(program_id, offer_account_info.key);
+    if *offer_vault_info.key != offer_vault {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let loan_authority = loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)?;
+    if *loan_authority_info.key != loan_authority {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let clock = &Clock::from_account_info(clock_info)?;
+    let amount = loan_data.expected_amount;
+    msg!("Saving the loan offer...");
+    LoanOffer::pack(
+        LoanOffer {
+            is_initialized: true,
+            loan_pubkey: *loan_account_info.key,
+            lender_pubkey: *lender_info.key,
+            lender_repayment_pubkey: *lender_repayment_account_info.key,
+            vault_pubkey: offer_vault,
+            interest_rate,
+            duration,
+            amount,
+            created_at: clock.unix_timestamp,
+        },
+        &mut offer_account_info.data.borrow_mut(),
+    )?;
+    LoanEvent::LoanOffered {
+        loan: *loan_account_info.key,
+        offer: *offer_account_info.key,
+        lender: *lender_info.key,
+        interest_rate,
+        duration,
+        amount,
+    }.emit();
+
+    // create the offer vault
+    let create_vault_ix = system_instruction::create_account(
+        lender_info.key,
+        &offer_vault,
+        rent.minimum_balance(spl_token::state::Account::LEN),
+        spl_token::state::Account::LEN as u64,
+        token_program.key,
+    );
+    msg!("Calling the system program to create the offer vault...");
+    invoke_signed(
+        &create_vault_ix,
+        &[
+            lender_info.clone(),
+            offer_vault_info.clone(),
+            system_program.clone(),
+        ],
+        &[&[OFFER_VAULT_SEED, offer_account_info.key.as_ref(), &[vault_bump_seed]]],
+    )?;
+    let init_vault_ix = spl_token::instruction::initialize_account(
+        token_program.key,
+        &offer_vault,
+        loan_mint_info.key,
+        &loan_authority,
+    )?;
+    msg!("Calling the token program to initialize the offer vault...");
+    invoke(
+        &init_vault_ix,
+        &[
+            offer_vault_info.clone(),
+            loan_mint_info.clone(),
+            loan_authority_info.clone(),
+            rent_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+    // move the offered funds into the vault
+    let transfer_to_vault_ix = spl_token::instruction::transfer(
+        token_program.key,
+        lender_loan_transfer_info.key,
+        &offer_vault,
+        lender_info.key,
+        &[&lender_info.key],
+        amount,
+    )?;
+    msg!("Calling the token program to transfer the offered funds into the vault...");
+    invoke(
+        &transfer_to_vault_ix,
+        &[
+            lender_loan_transfer_info.clone(),
+            offer_vault_info.clone(),
+            lender_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn process_take_offer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let TakeOfferAccounts {
+        borrower: borrower_info,
+        borrower_loan_receive: borrower_loan_receive_account_info,
+        loan: loan_account_info,
+        offer: offer_account_info,
+        offer_vault: offer_vault_info,
+        loan_authority: loan_authority_info,
+        lender: lender_info,
+        config: config_info,
+        treasury: treasury_info,
+        token_program,
+        clock: clock_info,
+        collateral_vault: collateral_vault_info,
+        collateral_mint: collateral_mint_info,
+    } = TakeOfferAccounts::from_account_infos(account_info_iter)?;
+    // get the loan and offer accounts and assert that they are owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *offer_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // only the borrower can take an offer
+    if *borrower_info.key != loan_data.initializer_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    // fail if the loan can not be accepted in its current status
+    let status = loan_data.status.transition(LoanAction::Accept)?;
+    // get the offer, and make sure it is for this loan
+    let offer_data = LoanOffer::unpack(&offer_account_info.data.borrow())?;
+    if offer_data.loan_pubkey != *loan_account_info.key
+        || offer_data.vault_pubkey != *offer_vault_info.key
+        || offer_data.lender_pubkey != *lender_info.key
+    {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    // Ensure we have the right account to send borrowed funds to
+    if *borrower_loan_receive_account_info.key != loan_data.borrower_loan_receive_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    unpack_token_account(borrower_loan_receive_account_info, &loan_data.loan_mint_pubkey, None)?;
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // get the program config and the treasury that collects the application fee
+    let config = unpack_config(program_id, config_info)?;
+    if *treasury_info.key != find_treasury_address(program_id, &loan_data.loan_mint_pubkey).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // the application fee is taken out of the amount sent to the borrower
+    let application_fee = apply_basis_points(offer_data.amount, get_application_fee(
+        &config,
+        &loan_data.initializer_pubkey,
+        offer_data.amount,
+    ))?;
+    let amount: u64 = offer_data.amount
+        .checked_sub(application_fee)
+        .ok_or(LoanError::MathOverflow)?;
+    // the loan duration starts running from the time the offer is taken
+    let clock = &Clock::from_account_info(clock_info)?;
+    // update loan info, the amount owed follows from the offered terms
+    msg!("Updating loan information with the offered terms...");
+    loan_data.status = status;
+    loan_data.interest_rate = offer_data.interest_rate;
+    loan_data.duration = offer_data.duration;
+    loan_data.amount = get_borrowed_amount(
+        &config,
+        &loan_data.initializer_pubkey,
+        loan_data.expected_amount,
+        offer_data.duration,
+        offer_data.interest_rate,
+    )?;
+    // the collateral was put up for the amount the borrower asked for, it has to still
+    // cover the amount the offered terms make owed
+    if loan_data.collateral_account_pubkey != COption::Some(*collateral_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if loan_data.collateral_mint_pubkey != COption::Some(*collateral_mint_info.key) {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    let collateral_mint = unpack_mint(collateral_mint_info)?;
+    let collateral_vault = unpack_token_account(collateral_vault_info, collateral_mint_info.key, None)?;
+    let (loan_mint_decimals, price, exponent) = get_collateral_price(
+        program_id,
+        Some(&config),
+        &loan_data,
+        collateral_mint_info.key,
+        &collateral_mint,
+        account_info_iter,
+        clock.unix_timestamp,
+    )?;
+    let collateral_amount = get_collateral_amount(
+        loan_data.amount,
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+        loan_data.required_collateral_ratio(),
+    )?;
+    if collateral_vault.amount < collateral_amount {
+        return Err(LoanError::InsufficientCollateral.into());
+    }
+    loan_data.lender_pubkey = COption::Some(offer_data.lender_pubkey);
+    loan_data.lender_repayment_pubkey = COption::Some(offer_data.lender_repayment_pubkey);
+    // the offer vault is empty once the loan is paid out, it stays open to hold the
+    // repayments until the lender withdraws them
+    loan_data.repayment_vault_pubkey = COption::Some(offer_data.vault_pubkey);
+    loan_data.accepted_at = clock.unix_timestamp;
+    loan_data.due_at = get_due_at(clock.unix_timestamp, loan_data.duration)?;
+    let due_at = loan_data.due_at;
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    LoanEvent::LoanAccepted {
+        loan: *loan_account_info.key,
+        lender: *lender_info.key,
+        amount,
+        application_fee,
+        due_at,
+        status,
+    }.emit();
+
+    msg!("Calling the token program to transfer tokens to the borrower...");
+    transfer_from_vault(
+        loan_account_info.key,
+        bump_seed,
+        offer_vault_info,
+        borrower_loan_receive_account_info,
+        loan_authority_info,
+        token_program,
+        amount,
+    )?;
+    msg!("Calling the token program to transfer the application fee to the treasury...");
+    transfer_from_vault(
+        loan_account_info.key,
+        bump_seed,
+        offer_vault_info,
+        treasury_info,
+        loan_authority_info,
+        token_program,
+        application_fee,
+    )?;
+    msg!("Closing the offer account...");
+    close_program_account(offer_account_info, lender_info)
+}
+
+pub fn process_cancel_offer(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let CancelOfferAccounts {
+        lender: lender_info,
+        lender_refund: lender_refund_account_info,
+        loan: loan_account_info,
+        offer: offer_account_info,
+        offer_vault: offer_vault_info,
+        loan_authority: loan_authority_info,
+        token_program,
+    } = CancelOfferAccounts::from_account_infos(&mut accounts.iter())?;
+    // get the offer account and assert that it is owned by the program
+    if *offer_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // only the lender can withdraw their offer
+    let offer_data = LoanOffer::unpack(&offer_account_info.data.borrow())?;
+    if offer_data.lender_pubkey != *lender_info.key {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    if offer_data.loan_pubkey != *loan_account_info.key || offer_data.vault_pubkey != *offer_vault_info.key {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    // the loan holds the bump seed of the authority that owns the offer vault.  Once the
+    // borrower has closed the loan, the bump seed is derived again and the offer vault
+    // tells the mint
+    let (bump_seed, loan_mint_pubkey) = if *loan_account_info.owner == *program_id {
+        let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+        (loan_data.bump_seed, loan_data.loan_mint_pubkey)
+    } else if loan_account_info.data_is_empty() {
+        let offer_vault = spl_token::state::Account::unpack(&offer_vault_info.data.borrow())?;
+        (find_loan_authority_address(program_id, loan_account_info.key).1, offer_vault.mint)
+    } else {
+        return Err(ProgramError::IncorrectProgramId);
+    };
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    unpack_token_account(lender_refund_account_info, &loan_mint_pubkey, Some(lender_info.key))?;
+    LoanEvent::LoanOfferCancelled {
+        loan: *loan_account_info.key,
+        offer: *offer_account_info.key,
+        lender: *lender_info.key,
+    }.emit();
+
+    msg!("Calling the token program to return the offered funds to the lender...");
+    transfer_from_vault(
+        loan_account_info.key,
+        bump_seed,
+        offer_vault_info,
+        lender_refund_account_info,
+        loan_authority_info,
+        token_program,
+        offer_data.amount,
+    )?;
+    msg!("Calling the token program to close the offer vault...");
+    close_vault(
+        loan_account_info.key,
+        bump_seed,
+        offer_vault_info,
+        lender_info,
+        loan_authority_info,
+        token_program,
+    )?;
+    msg!("Closing the offer account...");
+    close_program_account(offer_account_info, lender_info)
+}
+
+pub fn process_check_health(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let CheckHealthAccounts {
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        collateral_mint: collateral_mint_info,
+        clock: clock_info,
+    } = CheckHealthAccounts::from_account_infos(account_info_iter)?;
+    // get the loan account and assert that it is owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan holds no collateral in its current status
+    loan_data.status.transition(LoanAction::CheckHealth)?;
+    if loan_data.collateral_account_pubkey != COption::Some(*collateral_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if loan_data.collateral_mint_pubkey != COption::Some(*collateral_mint_info.key) {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    let collateral_mint = unpack_mint(collateral_mint_info)?;
+    let collateral_vault = unpack_token_account(collateral_vault_info, collateral_mint_info.key, None)?;
+    let clock = &Clock::from_account_info(clock_info)?;
+    // value what is in the vault now, against what is left to repay
+    let (loan_mint_decimals, price, exponent) = get_collateral_price(
+        program_id,
+        None,
+        &loan_data,
+        collateral_mint_info.key,
+        &collateral_mint,
+        account_info_iter,
+        clock.unix_timestamp,
+    )?;
+    let collateral_value = get_collateral_value(
+        collateral_vault.amount,
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+    )?;
+    let outstanding_amount = loan_data.outstanding_amount();
+    let required_collateral_ratio = loan_data.required_collateral_ratio();
+    let collateral_ratio = get_basis_points(collateral_value, outstanding_amount);
+    let health_factor = get_basis_points(collateral_ratio, required_collateral_ratio as u64);
+    msg!("Collateral value: {}, outstanding amount: {}", collateral_value, outstanding_amount);
+    msg!("Collateral ratio: {} basis points, required: {} basis points", collateral_ratio, required_collateral_ratio);
+    msg!("Health factor: {} basis points", health_factor);
+
+    Ok(())
+}
+
+pub fn process_liquidate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let LiquidateAccounts {
+        liquidator: liquidator_info,
+        liquidator_token: liquidator_token_account_info,
+        liquidator_collateral: liquidator_collateral_account_info,
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        loan_authority: loan_authority_info,
+        collateral_mint: collateral_mint_info,
+        guarantor_repayment: guarantor_token_account_info,
+        lender_repayment: lender_token_account_info,
+        config: config_info,
+        treasury: treasury_info,
+        token_program,
+        clock: clock_info,
+    } = LiquidateAccounts::from_account_infos(account_info_iter)?;
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the program config, it holds the liquidation terms and repayment shares
+    let config = unpack_config(program_id, config_info)?;
+
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan can not be liquidated in its current status
+    let outstanding_amount = loan_data.outstanding_amount();
+    loan_data.status.transition(LoanAction::Liquidate)?;
+    // fail if nothing is being repaid, or more than what is outstanding
+    if amount == 0 || amount > outstanding_amount {
+        return Err(LoanError::InvalidAmount.into());
+    }
+    if loan_data.collateral_account_pubkey != COption::Some(*collateral_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if loan_data.collateral_mint_pubkey != COption::Some(*collateral_mint_info.key) {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let collateral_mint = unpack_mint(collateral_mint_info)?;
+    let collateral_vault = unpack_token_account(collateral_vault_info, collateral_mint_info.key, None)?;
+    // fail if the liquidator can not cover the repayment, or can not receive the collateral
+    let liquidator_token_account = unpack_token_account(
+        liquidator_token_account_info,
+        &loan_data.loan_mint_pubkey,
+        Some(liquidator_info.key),
+    )?;
+    assert_token_balance(&liquidator_token_account, amount)?;
+    unpack_token_account(liquidator_collateral_account_info, collateral_mint_info.key, Some(liquidator_info.key))?;
+    // Ensure we have the right accounts to send the repayment to
+    check_repayment_accounts(
+        program_id,
+        &loan_data,
+        guarantor_token_account_info,
+        lender_token_account_info,
+        treasury_info,
+    )?;
+
+    // fail unless the collateral has fallen below the maintenance ratio.  A maintenance
+    // ratio of 0 means loans can not be liquidated at all
+    let clock = &Clock::from_account_info(clock_info)?;
+    let (loan_mint_decimals, price, exponent) = get_collateral_price(
+        program_id,
+        Some(&config),
+        &loan_data,
+        collateral_mint_info.key,
+        &collateral_mint,
+        account_info_iter,
+        clock.unix_timestamp,
+    )?;
+    let collateral_value = get_collateral_value(
+        collateral_vault.amount,
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+    )?;
+    if get_basis_points(collateral_value, outstanding_amount) >= config.maintenance_ratio as u64 {
+        return Err(LoanError::NotLiquidatable.into());
+    }
+    // the liquidator gets the value they repay plus the bonus, as far as the vault covers it
+    let bonus_ratio = BASIS_POINTS
+        .checked_add(config.liquidation_bonus)
+        .ok_or(LoanError::MathOverflow)?;
+    let collateral_amount = get_collateral_amount(
+        amount,
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+        bonus_ratio,
+    )?
+    .min(collateral_vault.amount);
+    if collateral_amount == 0 {
+        return Err(LoanError::InsufficientCollateral.into());
+    }
+    // the liquidation closes the loan once it is repaid in full, or once the vault is
+    // empty and nothing is left to secure the rest of the loan
+    let status = if amount == outstanding_amount || collateral_amount == collateral_vault.amount {
+        loan_data.status.transition(LoanAction::LiquidateInFull)?
+    } else {
+        loan_data.status.transition(LoanAction::Liquidate)?
+    };
+    // the payment is split the same way as a repayment
+    let split = get_repayment_split(&config, &loan_data, amount)?;
+
+    transfer_repayment(
+        liquidator_info,
+        liquidator_token_account_info,
+        guarantor_token_account_info,
+        lender_token_account_info,
+        treasury_info,
+        token_program,
+        &split,
+    )?;
+    msg!("Calling the token program to transfer the collateral to the liquidator...");
+    transfer_from_vault(
+        loan_account_info.key,
+        loan_data.bump_seed,
+        collateral_vault_info,
+        liquidator_collateral_account_info,
+        loan_authority_info,
+        token_program,
+        collateral_amount,
+    )?;
+
+    // update loan info
+    loan_data.amount_repaid = loan_data.amount_repaid
+        .checked_add(amount)
+        .ok_or(LoanError::MathOverflow)?;
+    loan_data.liquidated_amount = loan_data.liquidated_amount
+        .checked_add(amount)
+        .ok_or(LoanError::MathOverflow)?;
+    loan_data.liquidated_collateral = loan_data.liquidated_collateral
+        .checked_add(collateral_amount)
+        .ok_or(LoanError::MathOverflow)?;
+    loan_data.status = status;
+    let event = LoanEvent::LoanLiquidated {
+        loan: *loan_account_info.key,
+        liquidator: *liquidator_info.key,
+        amount,
+        collateral_amount,
+        outstanding_amount: loan_data.outstanding_amount(),
+        status,
+    };
+    if status != LoanStatus::Liquidated {
+        msg!("Updating loan information with the liquidated amount...");
+        Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+        event.emit();
+        return Ok(());
+    }
+    msg!("Updating loan information, setting status to liquidated...");
+    loan_data.closed_at = clock.unix_timestamp;
+    let guarantor_pubkey = loan_data.guarantor_pubkey;
+    let collateral_account_pubkey = loan_data.collateral_account_pubkey;
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey;
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    event.emit();
+
+    // the loan is closed, hand what is left of the collateral back to the guarantor
+    let collateral_accounts = ReturnCollateralAccounts::from_account_infos(account_info_iter)?;
+    return_collateral(
+        program_id,
+        loan_account_info.key,
+        bump_seed,
+        &guarantor_pubkey,
+        &collateral_account_pubkey,
+        &collateral_mint_pubkey,
+        &collateral_accounts,
+        token_program,
+    )
+}
+
+pub fn process_add_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let AddCollateralAccounts {
+        depositor: depositor_info,
+        collateral_source: collateral_source_info,
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        token_program,
+    } = AddCollateralAccounts::from_account_infos(&mut accounts.iter())?;
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan holds no collateral in its current status
+    loan_data.status.transition(LoanAction::AddCollateral)?;
+    if amount == 0 {
+        return Err(LoanError::InvalidAmount.into());
+    }
+    if loan_data.collateral_account_pubkey != COption::Some(*collateral_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey.ok_or(LoanError::TokenMintMismatch)?;
+    let collateral_vault = unpack_token_account(collateral_vault_info, &collateral_mint_pubkey, None)?;
+    // fail if the depositor does not have the collateral to add
+    let collateral_source = unpack_token_account(
+        collateral_source_info,
+        &collateral_mint_pubkey,
+        Some(depositor_info.key),
+    )?;
+    assert_token_balance(&collateral_source, amount)?;
+
+    let transfer_ix = spl_token::instruction::transfer(
+        token_program.key,
+        collateral_source_info.key,
+        collateral_vault_info.key,
+        depositor_info.key,
+        &[&depositor_info.key],
+        amount,
+    )?;
+    msg!("Calling the token program to transfer the collateral to the vault...");
+    invoke(
+        &transfer_ix,
+        &[
+            collateral_source_info.clone(),
+            collateral_vault_info.clone(),
+            depositor_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    LoanEvent::CollateralAdded {
+        loan: *loan_account_info.key,
+        depositor: *depositor_info.key,
+        amount,
+        collateral_amount: collateral_vault.amount
+            .checked_add(amount)
+            .ok_or(LoanError::MathOverflow)?,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_release_collateral(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let ReleaseCollateralAccounts {
+        guarantor: guarantor_info,
+        collateral_return: collateral_return_account_info,
+        loan: loan_account_info,
+        collateral_vault: collateral_vault_info,
+        loan_authority: loan_authority_info,
+        collateral_mint: collateral_mint_info,
+        token_program,
+        clock: clock_info,
+    } = ReleaseCollateralAccounts::from_account_infos(account_info_iter)?;
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan holds no collateral in its current status
+    loan_data.status.transition(LoanAction::ReleaseCollateral)?;
+    // once an accepted loan is due, the lender may claim all of the collateral
+    let clock = &Clock::from_account_info(clock_info)?;
+    if loan_data.status == LoanStatus::Accepted && clock.unix_timestamp >= loan_data.due_at {
+        return Err(LoanError::LoanExpired.into());
+    }
+    // only the guarantor gets the collateral back
+    if loan_data.guarantor_pubkey != COption::Some(*guarantor_info.key) {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    if loan_data.collateral_account_pubkey != COption::Some(*collateral_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if loan_data.collateral_mint_pubkey != COption::Some(*collateral_mint_info.key) {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let collateral_mint = unpack_mint(collateral_mint_info)?;
+    let collateral_vault = unpack_token_account(collateral_vault_info, collateral_mint_info.key, None)?;
+    unpack_token_account(collateral_return_account_info, collateral_mint_info.key, Some(guarantor_info.key))?;
+
+    // the collateral the outstanding amount still requires stays in the vault
+    let (loan_mint_decimals, price, exponent) = get_collateral_price(
+        program_id,
+        None,
+        &loan_data,
+        collateral_mint_info.key,
+        &collateral_mint,
+        account_info_iter,
+        clock.unix_timestamp,
+    )?;
+    let required_amount = get_collateral_amount(
+        loan_data.outstanding_amount(),
+        loan_mint_decimals,
+        collateral_mint.decimals,
+        price,
+        exponent,
+        loan_data.required_collateral_ratio(),
+    )?;
+    let amount = collateral_vault.amount.saturating_sub(required_amount);
+    if amount == 0 {
+        return Err(LoanError::InsufficientCollateral.into());
+    }
+
+    msg!("Calling the token program to release the collateral to the guarantor...");
+    transfer_from_vault(
+        loan_account_info.key,
+        loan_data.bump_seed,
+        collateral_vault_info,
+        collateral_return_account_info,
+        loan_authority_info,
+        token_program,
+        amount,
+    )?;
+
+    LoanEvent::CollateralReleased {
+        loan: *loan_account_info.key,
+        guarantor: *guarantor_info.key,
+        amount,
+        collateral_amount: collateral_vault.amount - amount,
+    }.emit();
+
+    Ok(())
+}
+
+pub fn process_expire_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let ExpireLoanAccounts {
+        loan: loan_account_info,
+        clock: clock_info,
+        token_program,
+    } = ExpireLoanAccounts::from_account_infos(account_info_iter)?;
+    // get the loan account and assert that it is owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let clock = &Clock::from_account_info(clock_info)?;
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail if the loan has already been accepted or closed
+    let is_guaranteed = loan_data.status == LoanStatus::Guaranteed;
+    let status = loan_data.status.transition(LoanAction::Expire)?;
+    // fail if the request is still open
+    if clock.unix_timestamp < get_due_at(loan_data.created_at, REQUEST_DURATION)? {
+        return Err(LoanError::RequestNotExpired.into());
+    }
+    // update loan info
+    msg!("Updating loan information, setting status to expired...");
+    loan_data.status = status;
+    loan_data.closed_at = clock.unix_timestamp;
+    let guarantor_pubkey = loan_data.guarantor_pubkey;
+    let collateral_account_pubkey = loan_data.collateral_account_pubkey;
+    let collateral_mint_pubkey = loan_data.collateral_mint_pubkey;
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    LoanEvent::LoanExpired {
+        loan: *loan_account_info.key,
+        status,
+    }.emit();
+
+    if !is_guaranteed {
+        return Ok(());
+    }
+    // the loan was guaranteed, hand the collateral back to the guarantor
+    let collateral_accounts = ReturnCollateralAccounts::from_account_infos(account_info_iter)?;
+    return_collateral(
+        program_id,
+        loan_account_info.key,
+        bump_seed,
+        &guarantor_pubkey,
+        &collateral_account_pubkey,
+        &collateral_mint_pubkey,
+        &collateral_accounts,
+        token_program,
+    )
+}
+
+pub fn process_close_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let CloseLoanAccounts {
+        initializer: initializer_info,
+        loan: loan_account_info,
+    } = CloseLoanAccounts::from_account_infos(&mut accounts.iter())?;
+    // get the loan account and assert that it is owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // only the borrower paid for the loan account
+    if *initializer_info.key != loan_data.initializer_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    // fail unless the loan is settled, by then its vaults have been closed.  The closed
+    // status is only recorded in the event, the account itself is wiped
+    let status = loan_data.status.transition(LoanAction::Close)?;
+    // the lender's repayments would be lost with the loan's record of its vault
+    if loan_data.repayment_vault_pubkey.is_some() {
+        return Err(LoanError::RepaymentNotWithdrawn.into());
+    }
+    LoanEvent::LoanClosed {
+        loan: *loan_account_info.key,
+        initializer: *initializer_info.key,
+        status,
+    }.emit();
+
+    msg!("Closing the loan account...");
+    close_program_account(loan_account_info, initializer_info)
+}
+
+pub fn process_withdraw_repayment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let WithdrawRepaymentAccounts {
+        lender: lender_info,
+        destination: destination_info,
+        loan: loan_account_info,
+        repayment_vault: repayment_vault_info,
+        loan_authority: loan_authority_info,
+        token_program,
+    } = WithdrawRepaymentAccounts::from_account_infos(&mut accounts.iter())?;
+    // get the loan account and assert that it is owned by the program
+    if *loan_account_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // get the loan data
+    let mut loan_data = Loan::unpack(&loan_account_info.data.borrow())?;
+    // fail is loan is not initialized
+    if !loan_data.is_initialized() {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    // fail unless the loan has been accepted
+    let status = loan_data.status.transition(LoanAction::WithdrawRepayment)?;
+    // only the lender can withdraw the repayments
+    if loan_data.lender_pubkey != COption::Some(*lender_info.key) {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    if loan_data.repayment_vault_pubkey != COption::Some(*repayment_vault_info.key) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_account_info.key, loan_data.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let amount = spl_token::state::Account::unpack(&repayment_vault_info.data.borrow())?.amount;
+    // nothing more is paid into the vault once the loan is settled, so it is closed
+    let is_settled = status != LoanStatus::Accepted;
+    if is_settled {
+        msg!("Updating loan information, removing the repayment vault...");
+        loan_data.repayment_vault_pubkey = COption::None;
+    }
+    let bump_seed = loan_data.bump_seed;
+    Loan::pack(loan_data, &mut loan_account_info.data.borrow_mut())?;
+    LoanEvent::RepaymentWithdrawn {
+        loan: *loan_account_info.key,
+        lender: *lender_info.key,
+        amount,
+        status,
+    }.emit();
+
+    msg!("Calling the token program to transfer the repayments to the lender...");
+    transfer_from_vault(
+        loan_account_info.key,
+        bump_seed,
+        repayment_vault_info,
+        destination_info,
+        loan_authority_info,
+        token_program,
+        amount,
     )?;
-    msg!("Calling the token program to return lender payment account to the lender...");
-    invoke_signed(
-        &pay_lender_ix,
+    if !is_settled {
+        return Ok(());
+    }
+    msg!("Calling the token program to close the repayment vault...");
+    close_vault(
+        loan_account_info.key,
+        bump_seed,
+        repayment_vault_info,
+        lender_info,
+        loan_authority_info,
+        token_program,
+    )
+}
+
+pub fn process_migrate_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let MigrateConfigAccounts {
+        admin: admin_info,
+        config: config_info,
+    } = MigrateConfigAccounts::from_account_infos(&mut accounts.iter())?;
+    let config = unpack_config(program_id, config_info)?;
+    // only the admin can close the config
+    if *admin_info.key != config.admin_pubkey {
+        return Err(LoanError::NotAuthorized.into());
+    }
+    // a config of the current size already has room for every field
+    if config_info.data_len() == ProgramConfig::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // close the config account, returning its rent to the admin
+    msg!("Closing the program config...");
+    close_program_account(config_info, admin_info)
+}
+
+/// Load the program config, making sure it is the one derived from the program id
+fn unpack_config(
+    program_id: &Pubkey,
+    config_info: &AccountInfo,
+) -> Result<ProgramConfig, ProgramError> {
+    if *config_info.owner != *program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let config = ProgramConfig::unpack(&config_info.data.borrow())?;
+    if *config_info.key != config_id(program_id, config.bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(config)
+}
+
+/// The size of the start of a program data account that holds the upgrade authority.  It
+/// is the bincode encoded `UpgradeableLoaderState::ProgramData`: a u32 variant index, the
+/// u64 slot of the last deployment and the optional upgrade authority
+const PROGRAM_DATA_METADATA_LEN: usize = 4 + 8 + 1 + 32;
+/// The variant index of `UpgradeableLoaderState::ProgramData`
+const PROGRAM_DATA_VARIANT: u32 = 3;
+
+/// Read the upgrade authority of the program from its program data account, `None` once
+/// the program has been made immutable
+fn get_upgrade_authority(
+    program_id: &Pubkey,
+    program_data_info: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    if *program_data_info.owner != bpf_loader_upgradeable::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *program_data_info.key != find_program_data_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let data = program_data_info.data.borrow();
+    if data.len() < PROGRAM_DATA_METADATA_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let metadata = array_ref![data, 0, PROGRAM_DATA_METADATA_LEN];
+    let (variant, _slot, authority_option, authority) = array_refs![metadata, 4, 8, 1, 32];
+    if u32::from_le_bytes(*variant) != PROGRAM_DATA_VARIANT {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    match authority_option {
+        [0] => Ok(None),
+        [1] => Ok(Some(Pubkey::new_from_array(*authority))),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Fail if the config pricing does not add up
+fn validate_config(config: &ProgramConfig) -> ProgramResult {
+    if config.duration < MIN_DURATION
+        || config.processing_fee > BASIS_POINTS
+        || config.application_fee > BASIS_POINTS
+        || config.guarantor_share.checked_add(config.lender_share) != Some(BASIS_POINTS)
+        || config.collateral_ratio < BASIS_POINTS
+        || config.maintenance_ratio > config.collateral_ratio
+        || config.liquidation_bonus > BASIS_POINTS
+    {
+        return Err(LoanError::InvalidConfig.into());
+    }
+    Ok(())
+}
+
+/// Fail unless the accounts are the ones the loan sends repayments to
+fn check_repayment_accounts(
+    program_id: &Pubkey,
+    loan_data: &Loan,
+    guarantor_token_account_info: &AccountInfo,
+    lender_token_account_info: &AccountInfo,
+    treasury_info: &AccountInfo,
+) -> ProgramResult {
+    // Ensure we have the right account to send the program's share to
+    if *treasury_info.key != find_treasury_address(program_id, &loan_data.loan_mint_pubkey).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // Ensure we have the right account to send guarantor funds to
+    let guarantor_token_account_option = Some(*guarantor_token_account_info.key);
+    let guarantor_token_account_c_option: COption<Pubkey> = guarantor_token_account_option.into();
+    if guarantor_token_account_c_option != loan_data.guarantor_repayment_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    unpack_token_account(guarantor_token_account_info, &loan_data.loan_mint_pubkey, None)?;
+    // Ensure we have the right account to send repaid funds to.  Loans accepted before
+    // they had a repayment vault pay the lender's token account directly
+    let lender_token_option = Some(*lender_token_account_info.key);
+    let lender_token_c_option: COption<Pubkey> = lender_token_option.into();
+    if lender_token_c_option != loan_data.repayment_vault_pubkey.or(loan_data.lender_repayment_pubkey) {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    unpack_token_account(lender_token_account_info, &loan_data.loan_mint_pubkey, None)?;
+    Ok(())
+}
+
+/// Pay each share of a repayment out of the payer's token account
+fn transfer_repayment<'a>(
+    payer_info: &AccountInfo<'a>,
+    payer_token_account_info: &AccountInfo<'a>,
+    guarantor_token_account_info: &AccountInfo<'a>,
+    lender_token_account_info: &AccountInfo<'a>,
+    treasury_info: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    split: &RepaymentSplit,
+) -> ProgramResult {
+    // transfer the funds to the guarantor repayment account
+    let transfer_to_guarantor_ix = spl_token::instruction::transfer(
+        token_program.key,
+        payer_token_account_info.key,
+        guarantor_token_account_info.key,
+        payer_info.key,
+        &[&payer_info.key],
+        split.guarantor_share,
+    )?;
+    msg!("Calling the token program to transfer funds to the guarantor payment account...");
+    invoke(
+        &transfer_to_guarantor_ix,
+        &[
+            payer_token_account_info.clone(),
+            guarantor_token_account_info.clone(),
+            payer_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // transfer the funds to the lender repayment account
+    let transfer_to_lender_ix = spl_token::instruction::transfer(
+        token_program.key,
+        payer_token_account_info.key,
+        lender_token_account_info.key,
+        payer_info.key,
+        &[&payer_info.key],
+        split.lender_share,
+    )?;
+    msg!("Calling the token program to transfer funds to the lender payment account...");
+    invoke(
+        &transfer_to_lender_ix,
         &[
+            payer_token_account_info.clone(),
             lender_token_account_info.clone(),
-            lender_account_info.clone(),
-            pda_account_info.clone(),
+            payer_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    // transfer the program's share to the treasury
+    let transfer_to_treasury_ix = spl_token::instruction::transfer(
+        token_program.key,
+        payer_token_account_info.key,
+        treasury_info.key,
+        payer_info.key,
+        &[&payer_info.key],
+        split.program_share,
+    )?;
+    msg!("Calling the token program to transfer the program share to the treasury...");
+    invoke(
+        &transfer_to_treasury_ix,
+        &[
+            payer_token_account_info.clone(),
+            treasury_info.clone(),
+            payer_info.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Send the whole collateral vault balance back to the guarantor and close the vault
+fn return_collateral<'a>(
+    program_id: &Pubkey,
+    loan_pubkey: &Pubkey,
+    bump_seed: u8,
+    guarantor_pubkey: &COption<Pubkey>,
+    collateral_account_pubkey: &COption<Pubkey>,
+    collateral_mint_pubkey: &COption<Pubkey>,
+    collateral_accounts: &ReturnCollateralAccounts<&AccountInfo<'a>>,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let ReturnCollateralAccounts {
+        guarantor: guarantor_account_info,
+        collateral_return: collateral_return_account_info,
+        collateral_vault: collateral_vault_info,
+        loan_authority: loan_authority_info,
+    } = *collateral_accounts;
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // Ensure we are returning the collateral to the guarantor
+    let guarantor_account_option = Some(*guarantor_account_info.key);
+    let guarantor_account_c_option: COption<Pubkey> = guarantor_account_option.into();
+    if guarantor_account_c_option != *guarantor_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    let collateral_mint_pubkey = collateral_mint_pubkey.ok_or(LoanError::TokenMintMismatch)?;
+    unpack_token_account(collateral_return_account_info, &collateral_mint_pubkey, Some(guarantor_account_info.key))?;
+    let collateral_vault_option = Some(*collateral_vault_info.key);
+    let collateral_vault_c_option: COption<Pubkey> = collateral_vault_option.into();
+    if collateral_vault_c_option != *collateral_account_pubkey {
+        return Err(LoanError::AccountMismatch.into());
+    }
+    if *loan_authority_info.key != loan_authority_id(program_id, loan_pubkey, bump_seed)? {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let collateral_amount = spl_token::state::Account::unpack(&collateral_vault_info.data.borrow())?.amount;
+    msg!("Calling the token program to return the collateral to the guarantor...");
+    transfer_from_vault(
+        loan_pubkey,
+        bump_seed,
+        collateral_vault_info,
+        collateral_return_account_info,
+        loan_authority_info,
+        token_program,
+        collateral_amount,
+    )?;
+    msg!("Calling the token program to close the collateral vault...");
+    close_vault(
+        loan_pubkey,
+        bump_seed,
+        collateral_vault_info,
+        guarantor_account_info,
+        loan_authority_info,
+        token_program,
+    )
+}
+
+/// Load a token mint, making sure it belongs to the token program
+fn unpack_mint(
+    account_info: &AccountInfo,
+) -> Result<spl_token::state::Mint, ProgramError> {
+    if *account_info.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    spl_token::state::Mint::unpack(&account_info.data.borrow())
+}
+
+/// The decimals of the loan mint, and the price of the collateral in the loan mint as
+/// `(price, exponent)`.  Collateral in the loan mint is worth the same amount of the loan
+/// mint, any other collateral is valued at the price of the feed that comes next in
+/// `account_info_iter`, after the program config unless the instruction already took it
+fn get_collateral_price<'a, 'b: 'a>(
+    program_id: &Pubkey,
+    config: Option<&ProgramConfig>,
+    loan_data: &Loan,
+    collateral_mint_pubkey: &Pubkey,
+    collateral_mint: &spl_token::state::Mint,
+    account_info_iter: &mut Iter<'a, AccountInfo<'b>>,
+    now: UnixTimestamp,
+) -> Result<(u8, u64, i32), ProgramError> {
+    if *collateral_mint_pubkey == loan_data.loan_mint_pubkey {
+        return Ok((collateral_mint.decimals, 1, 0));
+    }
+    let loaded_config;
+    let (config, price_feed_info) = match config {
+        Some(config) => {
+            let PriceFeedAccounts {
+                price_feed: price_feed_info,
+            } = PriceFeedAccounts::from_account_infos(account_info_iter)?;
+            (config, price_feed_info)
+        }
+        None => {
+            let CollateralPriceAccounts {
+                config: config_info,
+                price_feed: price_feed_info,
+            } = CollateralPriceAccounts::from_account_infos(account_info_iter)?;
+            loaded_config = unpack_config(program_id, config_info)?;
+            (&loaded_config, price_feed_info)
+        }
+    };
+    let price_feed = unpack_price_feed(
+        price_feed_info,
+        config,
+        collateral_mint_pubkey,
+        &loan_data.loan_mint_pubkey,
+    )?;
+    Ok((loan_data.loan_mint_decimals, price_feed.get_price(now)?, price_feed.exponent))
+}
+
+/// Load a price feed, making sure it is the one the publisher the config trusts keeps
+/// with the oracle program the config trusts, and that it prices `base_mint` in
+/// `quote_mint`
+fn unpack_price_feed(
+    account_info: &AccountInfo,
+    config: &ProgramConfig,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> Result<PriceFeed, ProgramError> {
+    // a config that names no publisher trusts no price feed
+    if config.oracle_publisher == Pubkey::default() {
+        return Err(LoanError::InvalidConfig.into());
+    }
+    if *account_info.owner != config.oracle_program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let price_feed = PriceFeed::unpack(&account_info.data.borrow())?;
+    if price_feed.base_mint != *base_mint || price_feed.quote_mint != *quote_mint {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    // the oracle program keeps a feed for every publisher, so any other feed of the
+    // same mints may be priced by whoever created it
+    let (price_feed_pubkey, _) = find_price_feed_address(
+        &config.oracle_program_id,
+        &config.oracle_publisher,
+        base_mint,
+        quote_mint,
+    );
+    if *account_info.key != price_feed_pubkey {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(price_feed)
+}
+
+/// Load a token account, making sure the loan can use it.  It must be owned by the
+/// token program, hold tokens of `mint`, not be frozen, have no delegate or close
+/// authority, and be owned by `owner` when one is given
+fn unpack_token_account(
+    account_info: &AccountInfo,
+    mint: &Pubkey,
+    owner: Option<&Pubkey>,
+) -> Result<spl_token::state::Account, ProgramError> {
+    if *account_info.owner != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let token_account = spl_token::state::Account::unpack(&account_info.data.borrow())?;
+    if token_account.mint != *mint {
+        return Err(LoanError::TokenMintMismatch.into());
+    }
+    if token_account.is_frozen() {
+        return Err(LoanError::TokenAccountFrozen.into());
+    }
+    if let Some(owner) = owner {
+        if token_account.owner != *owner {
+            return Err(LoanError::TokenOwnerMismatch.into());
+        }
+    }
+    if token_account.delegate.is_some() {
+        return Err(LoanError::TokenAccountDelegated.into());
+    }
+    if token_account.close_authority.is_some() {
+        return Err(LoanError::TokenAccountCloseAuthority.into());
+    }
+    Ok(token_account)
+}
+
+/// Fail if a token account holds less than `amount`
+fn assert_token_balance(
+    token_account: &spl_token::state::Account,
+    amount: u64,
+) -> ProgramResult {
+    if token_account.amount < amount {
+        return Err(LoanError::InsufficientTokenBalance.into());
+    }
+    Ok(())
+}
+
+/// Move tokens out of one of the loan's vaults, signing as the loan authority
+fn transfer_from_vault<'a>(
+    loan_pubkey: &Pubkey,
+    bump_seed: u8,
+    vault_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    loan_authority_info: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    let transfer_ix = spl_token::instruction::transfer(
+        token_program.key,
+        vault_info.key,
+        destination_info.key,
+        loan_authority_info.key,
+        &[],
+        amount,
+    )?;
+    invoke_signed(
+        &transfer_ix,
+        &[
+            vault_info.clone(),
+            destination_info.clone(),
+            loan_authority_info.clone(),
             token_program.clone(),
         ],
-        &[&[&b"loan"[..], &[nonce]]],
+        &[&[LOAN_AUTHORITY_SEED, loan_pubkey.as_ref(), &[bump_seed]]],
+    )
+}
+
+/// Create and initialize one of the vaults of a loan at the address derived from
+/// `vault_seed` and the loan account, owned by its loan authority
+#[allow(clippy::too_many_arguments)]
+fn create_loan_vault<'a>(
+    vault_seed: &[u8],
+    loan_pubkey: &Pubkey,
+    vault_bump_seed: u8,
+    payer_info: &AccountInfo<'a>,
+    vault_info: &AccountInfo<'a>,
+    mint_info: &AccountInfo<'a>,
+    loan_authority_info: &AccountInfo<'a>,
+    rent: &Rent,
+    rent_info: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let create_vault_ix = system_instruction::create_account(
+        payer_info.key,
+        vault_info.key,
+        rent.minimum_balance(spl_token::state::Account::LEN),
+        spl_token::state::Account::LEN as u64,
+        token_program.key,
+    );
+    msg!("Calling the system program to create the vault...");
+    invoke_signed(
+        &create_vault_ix,
+        &[
+            payer_info.clone(),
+            vault_info.clone(),
+            system_program.clone(),
+        ],
+        &[&[vault_seed, loan_pubkey.as_ref(), &[vault_bump_seed]]],
+    )?;
+    let init_vault_ix = spl_token::instruction::initialize_account(
+        token_program.key,
+        vault_info.key,
+        mint_info.key,
+        loan_authority_info.key,
     )?;
+    msg!("Calling the token program to initialize the vault...");
+    invoke(
+        &init_vault_ix,
+        &[
+            vault_info.clone(),
+            mint_info.clone(),
+            loan_authority_info.clone(),
+            rent_info.clone(),
+            token_program.clone(),
+        ],
+    )
+}
 
-    // TODO: transfer application fee + program share to program owner address
+/// Hand a token account that a version 1 loan gave to the version 1 loan authority back
+/// to `owner`
+fn return_legacy_account<'a>(
+    account_info: &AccountInfo<'a>,
+    owner: &Pubkey,
+    legacy_authority_info: &AccountInfo<'a>,
+    legacy_bump_seed: u8,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let set_owner_ix = spl_token::instruction::set_authority(
+        token_program.key,
+        account_info.key,
+        Some(owner),
+        spl_token::instruction::AuthorityType::AccountOwner,
+        legacy_authority_info.key,
+        &[],
+    )?;
+    invoke_signed(
+        &set_owner_ix,
+        &[
+            account_info.clone(),
+            legacy_authority_info.clone(),
+            token_program.clone(),
+        ],
+        &[&[LOAN_AUTHORITY_SEED, &[legacy_bump_seed]]],
+    )
+}
 
+/// Close an account owned by the program, sending its rent lamports to the destination
+fn close_program_account(account_info: &AccountInfo, destination_info: &AccountInfo) -> ProgramResult {
+    let lamports = account_info.lamports();
+    **destination_info.lamports.borrow_mut() = destination_info
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(LoanError::MathOverflow)?;
+    **account_info.lamports.borrow_mut() = 0;
+    for byte in account_info.data.borrow_mut().iter_mut() {
+        *byte = 0;
+    }
     Ok(())
 }
+
+/// Close one of the loan's empty vaults, sending its rent lamports to the destination
+fn close_vault<'a>(
+    loan_pubkey: &Pubkey,
+    bump_seed: u8,
+    vault_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    loan_authority_info: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    let close_ix = spl_token::instruction::close_account(
+        token_program.key,
+        vault_info.key,
+        destination_info.key,
+        loan_authority_info.key,
+        &[],
+    )?;
+    invoke_signed(
+        &close_ix,
+        &[
+            vault_info.clone(),
+            destination_info.clone(),
+            loan_authority_info.clone(),
+            token_program.clone(),
+        ],
+        &[&[LOAN_AUTHORITY_SEED, loan_pubkey.as_ref(), &[bump_seed]]],
+    )
+}
@@ -1,24 +1,158 @@
+use std::convert::TryFrom;
 use solana_program::{
+    clock::UnixTimestamp,
+    msg,
     program_error::ProgramError,
     program_option::COption,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
-use crate::utils::{pack_coption_key, unpack_coption_key};
+use borsh::{BorshDeserialize, BorshSerialize};
+use crate::{error::LoanError, oracle::oracle_program, utils::{pack_coption_key, unpack_coption_key, BASIS_POINTS}};
 
+/// Marks an account as a loan account, stored in its first byte
+pub const LOAN_DISCRIMINATOR: u8 = 0x4c;
+/// The current version of the loan account layout
+pub const LOAN_VERSION: u8 = 2;
+/// The size of a version 1 loan account.  Version 1 accounts have no discriminator or
+/// version byte and have to be moved into the current layout with `MigrateLoan`
+pub const LOAN_V1_LEN: usize = 302;
+/// Bytes kept free at the end of the loan account so fields can be added without
+/// changing its size
+const LOAN_RESERVED_LEN: usize = 4;
+/// The sizes of program configs created before fields were appended to it.  Older
+/// configs keep working, with the fields they have no room for at their defaults:
+/// collateral covers the loan one for one, loans cannot be liquidated, price feeds
+/// come from `oracle_program::id()` and no publisher's price feeds are trusted
+pub const CONFIG_V1_LEN: usize = 58;  // before the collateral ratio
+pub const CONFIG_V2_LEN: usize = 62;  // before the liquidation terms
+pub const CONFIG_V3_LEN: usize = 70;  // before the oracle program
+pub const CONFIG_V4_LEN: usize = 102;  // before the oracle publisher
+
+#[derive(Clone, Copy, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub enum LoanStatus {
-    Pending = 0,
-    Initialized = 1,
-    Guaranteed = 2,
-    Accepted = 3,
-    Repaid = 4,
-    Cancelled = 7,
+    Uninitialized = 0,  // the loan account has not been initialized yet
+    Initialized = 1,  // the borrower has requested the loan
+    Guaranteed = 2,  // the guarantor has put up the collateral
+    Accepted = 3,  // the lender has sent the loan amount to the borrower
+    Repaid = 4,  // the whole loan amount has been repaid
+    Defaulted = 5,  // the loan was not repaid in time and the lender claimed the collateral
+    Liquidated = 6,  // the collateral was sold off to cover the loan
+    Cancelled = 7,  // the borrower withdrew the loan request
+    Expired = 8,  // the loan request was not accepted in time
+    Closed = 9,  // the loan is settled and its accounts have been closed
+}
+
+impl TryFrom<u8> for LoanStatus {
+    type Error = ProgramError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => LoanStatus::Uninitialized,
+            1 => LoanStatus::Initialized,
+            2 => LoanStatus::Guaranteed,
+            3 => LoanStatus::Accepted,
+            4 => LoanStatus::Repaid,
+            5 => LoanStatus::Defaulted,
+            6 => LoanStatus::Liquidated,
+            7 => LoanStatus::Cancelled,
+            8 => LoanStatus::Expired,
+            9 => LoanStatus::Closed,
+            _ => return Err(ProgramError::InvalidAccountData),
+        })
+    }
+}
+
+/// The instructions that change the status of a loan
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoanAction {
+    Init,
+    Guarantee,
+    Accept,
+    Repay,  // a payment that leaves part of the loan outstanding
+    RepayInFull,  // the payment that completes the loan
+    Cancel,
+    ClaimDefault,
+    Migrate,
+    Offer,  // a lender offers to fund the loan on their own terms
+    CheckHealth,  // read how well the collateral covers the loan
+    Liquidate,  // a keeper repays part of an under-collateralized loan for collateral
+    LiquidateInFull,  // the liquidation that completes the loan, or leaves no collateral to secure the rest of it
+    AddCollateral,  // top up the collateral vault
+    ReleaseCollateral,  // hand the guarantor the collateral beyond what the loan requires
+    Expire,  // nobody accepted the loan request in time
+    Close,  // the borrower closes a settled loan and takes back its rent
+    WithdrawRepayment,  // pay the lender what the repayment vault holds
+}
+
+impl LoanStatus {
+    /// Every status, in the order of their values
+    pub const ALL: [LoanStatus; 10] = [
+        LoanStatus::Uninitialized,
+        LoanStatus::Initialized,
+        LoanStatus::Guaranteed,
+        LoanStatus::Accepted,
+        LoanStatus::Repaid,
+        LoanStatus::Defaulted,
+        LoanStatus::Liquidated,
+        LoanStatus::Cancelled,
+        LoanStatus::Expired,
+        LoanStatus::Closed,
+    ];
+
+    /// The status a loan moves to when `action` is applied to it.  This is the only
+    /// place that decides which instruction is allowed in which status
+    pub fn transition(self, action: LoanAction) -> Result<LoanStatus, ProgramError> {
+        self.next(action).ok_or_else(|| {
+            msg!(
+                "Loan status {:?} does not allow {:?}, expected one of {:?}",
+                self,
+                action,
+                LoanStatus::allowing(action),
+            );
+            LoanError::InvalidLoanStatus.into()
+        })
+    }
+
+    /// The statuses in which `action` is allowed
+    pub fn allowing(action: LoanAction) -> Vec<LoanStatus> {
+        LoanStatus::ALL.iter().copied().filter(|status| status.next(action).is_some()).collect()
+    }
+
+    fn next(self, action: LoanAction) -> Option<LoanStatus> {
+        use LoanAction::*;
+        use LoanStatus::*;
+        Some(match (self, action) {
+            (Uninitialized, Init) => Initialized,
+            (Initialized, Guarantee) => Guaranteed,
+            (Guaranteed, Accept) => Accepted,
+            (Accepted, Repay) => Accepted,
+            (Accepted, RepayInFull) => Repaid,
+            (Initialized, Cancel) | (Guaranteed, Cancel) => Cancelled,
+            (Accepted, ClaimDefault) => Defaulted,
+            (status @ Guaranteed, Offer) => status,
+            (status @ Guaranteed, CheckHealth) | (status @ Accepted, CheckHealth) => status,
+            (status @ Guaranteed, AddCollateral) | (status @ Accepted, AddCollateral) => status,
+            (status @ Guaranteed, ReleaseCollateral) | (status @ Accepted, ReleaseCollateral) => status,
+            (Accepted, Liquidate) => Accepted,
+            (Accepted, LiquidateInFull) => Liquidated,
+            (Initialized, Expire) | (Guaranteed, Expire) => Expired,
+            (Repaid, Close) | (Defaulted, Close) | (Liquidated, Close) | (Cancelled, Close) | (Expired, Close) => Closed,
+            (status @ Accepted, WithdrawRepayment) | (status @ Repaid, WithdrawRepayment) => status,
+            (status @ Defaulted, WithdrawRepayment) | (status @ Liquidated, WithdrawRepayment) => status,
+            // the tokens of guaranteed and accepted loans are moved out of the version 1
+            // authority as they are migrated
+            (status @ Initialized, Migrate) | (status @ Repaid, Migrate) | (status @ Cancelled, Migrate) => status,
+            (status @ Guaranteed, Migrate) | (status @ Accepted, Migrate) => status,
+            _ => return None,
+        })
+    }
 }
 
 pub struct Loan {
     pub is_initialized: bool,
-    pub status: u8,  // the loan status
+    pub status: LoanStatus,  // the loan status
     pub initializer_pubkey: Pubkey,  // the account that wants to borrow
     pub loan_mint_pubkey: Pubkey,  // this account holds loan processing fee
     pub borrower_loan_receive_pubkey: Pubkey, // loan amount will be sent here if successful
@@ -26,11 +160,25 @@ pub struct Loan {
     pub guarantor_repayment_pubkey: COption<Pubkey>, // account to repay the guarantor
     pub collateral_account_pubkey: COption<Pubkey>, // the token account that holds the collateral
     pub lender_pubkey: COption<Pubkey>, // the person providing the loans
-    pub lender_repayment_pubkey: COption<Pubkey>, // account to repay the lender
+    pub lender_repayment_pubkey: COption<Pubkey>, // account to repay the lender, loans with a repayment vault pay into that instead
     pub expected_amount: u64,  // the expected loan amount
     pub amount: u64,  // the loan amount including interest
-    pub interest_rate: u32,  // the loan interest rate annualized.  Note that this is an unsigned int so something like 9 would actually represent 9/100 interest rate
+    pub amount_repaid: u64,  // how much of the loan amount has been repaid so far
+    pub interest_rate: u32,  // the loan interest rate annualized, in basis points.  Note that this is an unsigned int so something like 900 would actually represent 9/100 interest rate
     pub duration: u32,  // the loan duration in hours
+    pub created_at: UnixTimestamp,  // when the borrower initialized the loan
+    pub guaranteed_at: UnixTimestamp,  // when the guarantor provided the collateral
+    pub accepted_at: UnixTimestamp,  // when the lender accepted the loan, the duration runs from here
+    pub due_at: UnixTimestamp,  // when the loan has to be repaid by, set once the loan is accepted
+    pub closed_at: UnixTimestamp,  // when the loan was repaid, cancelled or defaulted
+    pub bump_seed: u8,  // bump seed of the program derived address that owns the loan's vaults
+    pub collateral_mint_pubkey: COption<Pubkey>,  // the mint of the collateral, may differ from the loan mint
+    pub loan_mint_decimals: u8,  // the number of decimals of the loan mint
+    pub max_interest_rate: u32,  // the highest interest rate the borrower accepts in basis points, 0 for loans requested before borrowers stated one
+    pub collateral_ratio: u32,  // the value of collateral required as a share of the loan amount in basis points, 0 for loans requested before it was stored
+    pub liquidated_amount: u64,  // how much of the amount repaid was repaid by liquidations
+    pub liquidated_collateral: u64,  // how much collateral liquidations have taken from the vault
+    pub repayment_vault_pubkey: COption<Pubkey>,  // the vault the lender's share of repayments waits in until it is withdrawn, none for loans accepted before repayments were held
 }
 
 impl Sealed for Loan {}
@@ -41,11 +189,100 @@ impl IsInitialized for Loan {
     }
 }
 
+impl Loan {
+    /// The part of the loan amount that is yet to be repaid
+    pub fn outstanding_amount(&self) -> u64 {
+        self.amount.saturating_sub(self.amount_repaid)
+    }
+
+    /// The value of collateral the loan requires as a share of the loan amount, in
+    /// basis points.  Loans requested before the ratio was stored on them require the
+    /// collateral to cover the loan one for one
+    pub fn required_collateral_ratio(&self) -> u32 {
+        if self.collateral_ratio == 0 {
+            return BASIS_POINTS;
+        }
+        self.collateral_ratio
+    }
+
+    /// Unpack a version 1 loan account.  Fields that did not exist in version 1 are
+    /// zeroed, and the interest rate is converted from a percentage to basis points
+    fn unpack_v1(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, LOAN_V1_LEN];
+        let (
+            is_initialized,
+            status,
+            initializer_pubkey,
+            loan_mint_pubkey,
+            borrower_loan_receive_pubkey,
+            guarantor_pubkey,
+            guarantor_repayment_pubkey,
+            collateral_account_pubkey,
+            lender_pubkey,
+            lender_repayment_pubkey,
+            expected_amount,
+            amount,
+            interest_rate,
+            duration,
+        ) = array_refs![src, 1, 1, 32, 32, 32, 36, 36, 36, 36, 36, 8, 8, 4, 4];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(Loan {
+            is_initialized,
+            status: LoanStatus::try_from(status[0])?,
+            initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
+            loan_mint_pubkey: Pubkey::new_from_array(*loan_mint_pubkey),
+            borrower_loan_receive_pubkey: Pubkey::new_from_array(*borrower_loan_receive_pubkey),
+            guarantor_pubkey: unpack_coption_key(guarantor_pubkey)?,
+            guarantor_repayment_pubkey: unpack_coption_key(guarantor_repayment_pubkey)?,
+            collateral_account_pubkey: unpack_coption_key(collateral_account_pubkey)?,
+            lender_pubkey: unpack_coption_key(lender_pubkey)?,
+            lender_repayment_pubkey: unpack_coption_key(lender_repayment_pubkey)?,
+            expected_amount: u64::from_le_bytes(*expected_amount),
+            amount: u64::from_le_bytes(*amount),
+            amount_repaid: 0,
+            interest_rate: u32::from_le_bytes(*interest_rate)
+                .checked_mul(100)
+                .ok_or(ProgramError::InvalidAccountData)?,
+            duration: u32::from_le_bytes(*duration),
+            created_at: 0,
+            guaranteed_at: 0,
+            accepted_at: 0,
+            due_at: 0,
+            closed_at: 0,
+            bump_seed: 0,
+            collateral_mint_pubkey: COption::None,
+            loan_mint_decimals: 0,
+            max_interest_rate: 0,
+            collateral_ratio: 0,
+            liquidated_amount: 0,
+            liquidated_collateral: 0,
+            repayment_vault_pubkey: COption::None,
+        })
+    }
+}
+
 impl Pack for Loan {
-    const LEN: usize = 302;
+    const LEN: usize = 454;
+
+    /// Unpack a loan account of any supported version
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.len() {
+            LOAN_V1_LEN => Self::unpack_v1(input),
+            Self::LEN => Self::unpack_from_slice(input),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
         let src = array_ref![src, 0, Loan::LEN];
         let (
+            discriminator,
+            version,
             is_initialized,
             status,
             initializer_pubkey,
@@ -58,9 +295,29 @@ impl Pack for Loan {
             lender_repayment_pubkey,
             expected_amount,
             amount,
+            amount_repaid,
             interest_rate,
             duration,
-        ) = array_refs![src, 1, 1, 32, 32, 32, 36, 36, 36, 36, 36, 8, 8, 4, 4];
+            created_at,
+            guaranteed_at,
+            accepted_at,
+            due_at,
+            closed_at,
+            bump_seed,
+            collateral_mint_pubkey,
+            loan_mint_decimals,
+            max_interest_rate,
+            collateral_ratio,
+            liquidated_amount,
+            liquidated_collateral,
+            repayment_vault_pubkey,
+            _reserved,
+        ) = array_refs![src, 1, 1, 1, 1, 32, 32, 32, 36, 36, 36, 36, 36, 8, 8, 8, 4, 4, 8, 8, 8, 8, 8, 1, 36, 1, 4, 4, 8, 8, 36, LOAN_RESERVED_LEN];
+        // a zeroed account has not been written to yet
+        match (discriminator, version) {
+            ([0], [0]) | ([LOAN_DISCRIMINATOR], [LOAN_VERSION]) => {}
+            _ => return Err(ProgramError::InvalidAccountData),
+        }
         let is_initialized = match is_initialized {
             [0] => false,
             [1] => true,
@@ -69,7 +326,7 @@ impl Pack for Loan {
 
         Ok(Loan {
             is_initialized,
-            status: u8::from_le_bytes(*status),
+            status: LoanStatus::try_from(status[0])?,
             initializer_pubkey: Pubkey::new_from_array(*initializer_pubkey),
             loan_mint_pubkey: Pubkey::new_from_array(*loan_mint_pubkey),
             borrower_loan_receive_pubkey: Pubkey::new_from_array(*borrower_loan_receive_pubkey),
@@ -80,14 +337,30 @@ impl Pack for Loan {
             lender_repayment_pubkey: unpack_coption_key(lender_repayment_pubkey)?,
             expected_amount: u64::from_le_bytes(*expected_amount),
             amount: u64::from_le_bytes(*amount),
+            amount_repaid: u64::from_le_bytes(*amount_repaid),
             interest_rate: u32::from_le_bytes(*interest_rate),
             duration: u32::from_le_bytes(*duration),
+            created_at: i64::from_le_bytes(*created_at),
+            guaranteed_at: i64::from_le_bytes(*guaranteed_at),
+            accepted_at: i64::from_le_bytes(*accepted_at),
+            due_at: i64::from_le_bytes(*due_at),
+            closed_at: i64::from_le_bytes(*closed_at),
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            collateral_mint_pubkey: unpack_coption_key(collateral_mint_pubkey)?,
+            loan_mint_decimals: u8::from_le_bytes(*loan_mint_decimals),
+            max_interest_rate: u32::from_le_bytes(*max_interest_rate),
+            collateral_ratio: u32::from_le_bytes(*collateral_ratio),
+            liquidated_amount: u64::from_le_bytes(*liquidated_amount),
+            liquidated_collateral: u64::from_le_bytes(*liquidated_collateral),
+            repayment_vault_pubkey: unpack_coption_key(repayment_vault_pubkey)?,
         })
     }
 
      fn pack_into_slice(&self, dst: &mut [u8]) {
         let dst = array_mut_ref![dst, 0, Loan::LEN];
         let (
+            discriminator_dst,
+            version_dst,
             is_initialized_dst,
             status_dst,
             initializer_pubkey_dst,
@@ -100,9 +373,24 @@ impl Pack for Loan {
             lender_repayment_pubkey_dst,
             expected_amount_dst,
             amount_dst,
+            amount_repaid_dst,
             interest_rate_dst,
             duration_dst,
-        ) = mut_array_refs![dst, 1, 1, 32, 32, 32, 36, 36, 36, 36, 36, 8, 8, 4, 4];
+            created_at_dst,
+            guaranteed_at_dst,
+            accepted_at_dst,
+            due_at_dst,
+            closed_at_dst,
+            bump_seed_dst,
+            collateral_mint_pubkey_dst,
+            loan_mint_decimals_dst,
+            max_interest_rate_dst,
+            collateral_ratio_dst,
+            liquidated_amount_dst,
+            liquidated_collateral_dst,
+            repayment_vault_pubkey_dst,
+            reserved_dst,
+        ) = mut_array_refs![dst, 1, 1, 1, 1, 32, 32, 32, 36, 36, 36, 36, 36, 8, 8, 8, 4, 4, 8, 8, 8, 8, 8, 1, 36, 1, 4, 4, 8, 8, 36, LOAN_RESERVED_LEN];
 
         let Loan {
             is_initialized,
@@ -117,12 +405,28 @@ impl Pack for Loan {
             lender_repayment_pubkey,
             expected_amount,
             amount,
+            amount_repaid,
             interest_rate,
             duration,
+            created_at,
+            guaranteed_at,
+            accepted_at,
+            due_at,
+            closed_at,
+            bump_seed,
+            collateral_mint_pubkey,
+            loan_mint_decimals,
+            max_interest_rate,
+            collateral_ratio,
+            liquidated_amount,
+            liquidated_collateral,
+            repayment_vault_pubkey,
         } = self;
 
+        discriminator_dst[0] = LOAN_DISCRIMINATOR;
+        version_dst[0] = LOAN_VERSION;
         is_initialized_dst[0] = *is_initialized as u8;
-        *status_dst = status.to_le_bytes();
+        status_dst[0] = *status as u8;
         initializer_pubkey_dst.copy_from_slice(initializer_pubkey.as_ref());
         loan_mint_pubkey_dst.copy_from_slice(loan_mint_pubkey.as_ref());
         borrower_loan_receive_pubkey_dst.copy_from_slice(borrower_loan_receive_pubkey.as_ref());
@@ -133,7 +437,282 @@ impl Pack for Loan {
         pack_coption_key(lender_repayment_pubkey, lender_repayment_pubkey_dst);
         *expected_amount_dst = expected_amount.to_le_bytes();
         *amount_dst = amount.to_le_bytes();
+        *amount_repaid_dst = amount_repaid.to_le_bytes();
+        *interest_rate_dst = interest_rate.to_le_bytes();
+        *duration_dst = duration.to_le_bytes();
+        *created_at_dst = created_at.to_le_bytes();
+        *guaranteed_at_dst = guaranteed_at.to_le_bytes();
+        *accepted_at_dst = accepted_at.to_le_bytes();
+        *due_at_dst = due_at.to_le_bytes();
+        *closed_at_dst = closed_at.to_le_bytes();
+        *bump_seed_dst = bump_seed.to_le_bytes();
+        pack_coption_key(collateral_mint_pubkey, collateral_mint_pubkey_dst);
+        *loan_mint_decimals_dst = loan_mint_decimals.to_le_bytes();
+        *max_interest_rate_dst = max_interest_rate.to_le_bytes();
+        *collateral_ratio_dst = collateral_ratio.to_le_bytes();
+        *liquidated_amount_dst = liquidated_amount.to_le_bytes();
+        *liquidated_collateral_dst = liquidated_collateral.to_le_bytes();
+        pack_coption_key(repayment_vault_pubkey, repayment_vault_pubkey_dst);
+        *reserved_dst = [0u8; LOAN_RESERVED_LEN];
+    }
+}
+
+/// The program config.  Its address is derived from the program id, and it is also
+/// the owner of the per-mint treasury token accounts that collect the program's fees
+pub struct ProgramConfig {
+    pub is_initialized: bool,
+    pub admin_pubkey: Pubkey,  // the account allowed to update the config
+    pub interest_rate: u32,  // the loan interest rate annualized in basis points, e.g. 900 represents 9/100
+    pub duration: u32,  // the longest loan duration a borrower can request, in hours
+    pub processing_fee: u32,  // the share of the loan interest kept by the program in basis points, e.g. 100 represents 1/100
+    pub application_fee: u32,  // the loan application fee in basis points, e.g. 10 represents 10/10000
+    pub guarantor_share: u32,  // the guarantor's share of the interest in basis points, e.g. 5000 represents 50/100
+    pub lender_share: u32,  // the lender's share of the interest in basis points, e.g. 5000 represents 50/100
+    pub bump_seed: u8,  // bump seed of the program derived address of this account
+    pub collateral_ratio: u32,  // the value of collateral required as a share of the loan amount in basis points, e.g. 15000 represents 150/100
+    pub maintenance_ratio: u32,  // the collateral ratio below which a loan can be liquidated in basis points, 0 turns liquidations off
+    pub liquidation_bonus: u32,  // the extra collateral a liquidator receives on top of the value they repay in basis points, e.g. 500 represents 5/100
+    pub oracle_program_id: Pubkey,  // the program whose price feeds value collateral in a different mint from the loan
+    pub oracle_publisher: Pubkey,  // the publisher whose price feeds are trusted, none when it is the default pubkey
+}
+
+impl Sealed for ProgramConfig {}
+
+impl IsInitialized for ProgramConfig {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl ProgramConfig {
+    /// The packed config with every field that older configs have no room for at its
+    /// default
+    fn defaults() -> [u8; ProgramConfig::LEN] {
+        let mut data = [0u8; ProgramConfig::LEN];
+        data[CONFIG_V1_LEN..CONFIG_V2_LEN].copy_from_slice(&BASIS_POINTS.to_le_bytes());
+        data[CONFIG_V3_LEN..CONFIG_V4_LEN].copy_from_slice(oracle_program::id().as_ref());
+        data
+    }
+
+    /// Pack the config into a config account of any size.  An older config has no room
+    /// for the fields appended since it was created, so they keep their defaults until
+    /// `MigrateConfig` closes it and it is created again
+    pub fn pack_into_account(self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        match dst.len() {
+            CONFIG_V1_LEN | CONFIG_V2_LEN | CONFIG_V3_LEN | CONFIG_V4_LEN | ProgramConfig::LEN => {}
+            _ => return Err(ProgramError::InvalidAccountData),
+        }
+        let mut data = [0u8; ProgramConfig::LEN];
+        self.pack_into_slice(&mut data);
+        if data[dst.len()..] != Self::defaults()[dst.len()..] {
+            return Err(LoanError::InvalidConfig.into());
+        }
+        dst.copy_from_slice(&data[..dst.len()]);
+        Ok(())
+    }
+}
+
+impl Pack for ProgramConfig {
+    const LEN: usize = 134;
+
+    /// Unpack a config account of any size
+    fn unpack_unchecked(input: &[u8]) -> Result<Self, ProgramError> {
+        match input.len() {
+            CONFIG_V1_LEN | CONFIG_V2_LEN | CONFIG_V3_LEN | CONFIG_V4_LEN | Self::LEN => {
+                let mut data = Self::defaults();
+                data[..input.len()].copy_from_slice(input);
+                Self::unpack_from_slice(&data)
+            }
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, ProgramConfig::LEN];
+        let (
+            is_initialized,
+            admin_pubkey,
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            bump_seed,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        ) = array_refs![src, 1, 32, 4, 4, 4, 4, 4, 4, 1, 4, 4, 4, 32, 32];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(ProgramConfig {
+            is_initialized,
+            admin_pubkey: Pubkey::new_from_array(*admin_pubkey),
+            interest_rate: u32::from_le_bytes(*interest_rate),
+            duration: u32::from_le_bytes(*duration),
+            processing_fee: u32::from_le_bytes(*processing_fee),
+            application_fee: u32::from_le_bytes(*application_fee),
+            guarantor_share: u32::from_le_bytes(*guarantor_share),
+            lender_share: u32::from_le_bytes(*lender_share),
+            bump_seed: u8::from_le_bytes(*bump_seed),
+            collateral_ratio: u32::from_le_bytes(*collateral_ratio),
+            maintenance_ratio: u32::from_le_bytes(*maintenance_ratio),
+            liquidation_bonus: u32::from_le_bytes(*liquidation_bonus),
+            oracle_program_id: Pubkey::new_from_array(*oracle_program_id),
+            oracle_publisher: Pubkey::new_from_array(*oracle_publisher),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, ProgramConfig::LEN];
+        let (
+            is_initialized_dst,
+            admin_pubkey_dst,
+            interest_rate_dst,
+            duration_dst,
+            processing_fee_dst,
+            application_fee_dst,
+            guarantor_share_dst,
+            lender_share_dst,
+            bump_seed_dst,
+            collateral_ratio_dst,
+            maintenance_ratio_dst,
+            liquidation_bonus_dst,
+            oracle_program_id_dst,
+            oracle_publisher_dst,
+        ) = mut_array_refs![dst, 1, 32, 4, 4, 4, 4, 4, 4, 1, 4, 4, 4, 32, 32];
+
+        let ProgramConfig {
+            is_initialized,
+            admin_pubkey,
+            interest_rate,
+            duration,
+            processing_fee,
+            application_fee,
+            guarantor_share,
+            lender_share,
+            bump_seed,
+            collateral_ratio,
+            maintenance_ratio,
+            liquidation_bonus,
+            oracle_program_id,
+            oracle_publisher,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        admin_pubkey_dst.copy_from_slice(admin_pubkey.as_ref());
+        *interest_rate_dst = interest_rate.to_le_bytes();
+        *duration_dst = duration.to_le_bytes();
+        *processing_fee_dst = processing_fee.to_le_bytes();
+        *application_fee_dst = application_fee.to_le_bytes();
+        *guarantor_share_dst = guarantor_share.to_le_bytes();
+        *lender_share_dst = lender_share.to_le_bytes();
+        *bump_seed_dst = bump_seed.to_le_bytes();
+        *collateral_ratio_dst = collateral_ratio.to_le_bytes();
+        *maintenance_ratio_dst = maintenance_ratio.to_le_bytes();
+        *liquidation_bonus_dst = liquidation_bonus.to_le_bytes();
+        oracle_program_id_dst.copy_from_slice(oracle_program_id.as_ref());
+        oracle_publisher_dst.copy_from_slice(oracle_publisher.as_ref());
+    }
+}
+
+/// A lender's offer to fund a guaranteed loan on their own terms.  The offered funds
+/// wait in a vault owned by the loan authority until the borrower takes the offer or
+/// the lender cancels it
+pub struct LoanOffer {
+    pub is_initialized: bool,
+    pub loan_pubkey: Pubkey,  // the loan the offer is for
+    pub lender_pubkey: Pubkey,  // the person making the offer
+    pub lender_repayment_pubkey: Pubkey,  // account to repay the lender if the offer is taken
+    pub vault_pubkey: Pubkey,  // the token account that holds the offered funds
+    pub interest_rate: u32,  // the offered interest rate annualized, in basis points
+    pub duration: u32,  // the offered loan duration in hours
+    pub amount: u64,  // the amount held in the vault
+    pub created_at: UnixTimestamp,  // when the lender made the offer
+}
+
+impl Sealed for LoanOffer {}
+
+impl IsInitialized for LoanOffer {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl Pack for LoanOffer {
+    const LEN: usize = 153;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, LoanOffer::LEN];
+        let (
+            is_initialized,
+            loan_pubkey,
+            lender_pubkey,
+            lender_repayment_pubkey,
+            vault_pubkey,
+            interest_rate,
+            duration,
+            amount,
+            created_at,
+        ) = array_refs![src, 1, 32, 32, 32, 32, 4, 4, 8, 8];
+        let is_initialized = match is_initialized {
+            [0] => false,
+            [1] => true,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+
+        Ok(LoanOffer {
+            is_initialized,
+            loan_pubkey: Pubkey::new_from_array(*loan_pubkey),
+            lender_pubkey: Pubkey::new_from_array(*lender_pubkey),
+            lender_repayment_pubkey: Pubkey::new_from_array(*lender_repayment_pubkey),
+            vault_pubkey: Pubkey::new_from_array(*vault_pubkey),
+            interest_rate: u32::from_le_bytes(*interest_rate),
+            duration: u32::from_le_bytes(*duration),
+            amount: u64::from_le_bytes(*amount),
+            created_at: i64::from_le_bytes(*created_at),
+        })
+    }
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, LoanOffer::LEN];
+        let (
+            is_initialized_dst,
+            loan_pubkey_dst,
+            lender_pubkey_dst,
+            lender_repayment_pubkey_dst,
+            vault_pubkey_dst,
+            interest_rate_dst,
+            duration_dst,
+            amount_dst,
+            created_at_dst,
+        ) = mut_array_refs![dst, 1, 32, 32, 32, 32, 4, 4, 8, 8];
+
+        let LoanOffer {
+            is_initialized,
+            loan_pubkey,
+            lender_pubkey,
+            lender_repayment_pubkey,
+            vault_pubkey,
+            interest_rate,
+            duration,
+            amount,
+            created_at,
+        } = self;
+
+        is_initialized_dst[0] = *is_initialized as u8;
+        loan_pubkey_dst.copy_from_slice(loan_pubkey.as_ref());
+        lender_pubkey_dst.copy_from_slice(lender_pubkey.as_ref());
+        lender_repayment_pubkey_dst.copy_from_slice(lender_repayment_pubkey.as_ref());
+        vault_pubkey_dst.copy_from_slice(vault_pubkey.as_ref());
         *interest_rate_dst = interest_rate.to_le_bytes();
         *duration_dst = duration.to_le_bytes();
+        *amount_dst = amount.to_le_bytes();
+        *created_at_dst = created_at.to_le_bytes();
     }
-}
\ No newline at end of file
+}
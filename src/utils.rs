@@ -1,73 +1,405 @@
 use solana_program::{
+    bpf_loader_upgradeable,
+    clock::UnixTimestamp,
     program_option::COption,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
+use std::convert::TryFrom;
 use arrayref::{array_refs, mut_array_refs};
+use crate::{error::LoanError, state::{Loan, ProgramConfig}};
+
+// All rates, fees and shares are expressed in basis points and all money math is
+// done with checked u128 integer arithmetic.  Rounding policy: every computed amount
+// is rounded down, and whatever is left over from a repayment goes to the lender, so
+// no dust is ever left unaccounted for.
+
+/// The number of basis points in a whole
+pub const BASIS_POINTS: u32 = 10_000;
+/// The number of hours in a year, used to pro-rate annualized interest rates
+pub const HOURS_PER_YEAR: u32 = 24 * 365;
+/// The number of seconds in an hour, loan durations are stored in hours
+pub const SECONDS_PER_HOUR: i64 = 60 * 60;
+/// The shortest loan duration a borrower can request, in hours
+pub const MIN_DURATION: u32 = 24;
+/// How long a loan request stays open for a guarantor and a lender, in hours.  After
+/// that anyone can expire it and hand the collateral back to the guarantor
+pub const REQUEST_DURATION: u32 = 24 * 7;
 
 /// get the loan interest rate
 pub fn get_interest_rate(
+    config: &ProgramConfig,
     _borrower: &Pubkey,
     _loan_amount: u64,
 ) -> u32 {
-    return 9;  // 9%
+    return config.interest_rate;
 }
 
 /// get the share paid out to the guarantor
 pub fn get_guarantor_share(
+    config: &ProgramConfig,
     _guarantor: &Pubkey,
     _loan_amount: u64,
 ) -> u32 {
-    return 50;  // 50%
+    return config.guarantor_share;
 }
 
 /// get the share paid our to the lender
 pub fn get_lender_share(
+    config: &ProgramConfig,
     _lender: &Pubkey,
     _loan_amount: u64,
 ) -> u32 {
-    return 50;  // 50%
+    return config.lender_share;
+}
+
+/// get the longest loan duration a borrower can request
+pub fn get_max_duration(
+    config: &ProgramConfig,
+    _borrower: &Pubkey,
+    _loan_amount: u64,
+) -> u32 {
+    return config.duration;
 }
 
-/// get the loan duration
-pub fn get_duration(
+/// get the value of collateral required as a share of the loan amount
+pub fn get_collateral_ratio(
+    config: &ProgramConfig,
     _borrower: &Pubkey,
     _loan_amount: u64,
 ) -> u32 {
-    return 24 * 30;  // 30 days
+    return config.collateral_ratio;
 }
 
 /// get the loan processing fee
 pub fn get_processing_fee(
+    config: &ProgramConfig,
     _borrower: &Pubkey,
     _expected_amount: u64,
     _loan_duration: u32,
     _loan_interest: u32,
 ) -> u32 {
-    return 1;  // 1%
+    return config.processing_fee;
 }
 
 /// get the loan application fee
 pub fn get_application_fee(
+    config: &ProgramConfig,
     _borrower: &Pubkey,
     _expected_amount: u64,
-) -> f64 {
-    return 0.001;
+) -> u32 {
+    return config.application_fee;
 }
 
-/// get the loan duration
+/// get the loan amount, i.e. the expected amount plus interest and processing fee
+/// pro-rated over the loan duration
 pub fn get_borrowed_amount(
+    config: &ProgramConfig,
     borrower: &Pubkey,
     expected_amount: u64,
     loan_duration: u32,
     loan_interest: u32,
+) -> Result<u64, ProgramError> {
+    let processing_fee: u32 = get_processing_fee(config, borrower, expected_amount, loan_duration, loan_interest);
+    let total_charge = (loan_interest as u128)
+        .checked_add(processing_fee as u128)
+        .ok_or(LoanError::MathOverflow)?;
+    let charge = (expected_amount as u128)
+        .checked_mul(total_charge)
+        .and_then(|value| value.checked_mul(loan_duration as u128))
+        .and_then(|value| value.checked_div(BASIS_POINTS as u128 * HOURS_PER_YEAR as u128))
+        .ok_or(LoanError::MathOverflow)?;
+    let amount = (expected_amount as u128)
+        .checked_add(charge)
+        .ok_or(LoanError::MathOverflow)?;
+    return to_u64(amount);
+}
+
+/// get the part of an amount given by a rate in basis points, rounded down
+pub fn apply_basis_points(
+    amount: u64,
+    basis_points: u32,
+) -> Result<u64, ProgramError> {
+    let value = (amount as u128)
+        .checked_mul(basis_points as u128)
+        .and_then(|value| value.checked_div(BASIS_POINTS as u128))
+        .ok_or(LoanError::MathOverflow)?;
+    return to_u64(value);
+}
+
+/// get the amount of collateral worth `collateral_ratio` basis points of `loan_amount`,
+/// at `price * 10^exponent` whole loan tokens per whole collateral token.  Unlike every
+/// other amount this is rounded up, so the collateral always covers the loan.
+pub fn get_collateral_amount(
+    loan_amount: u64,
+    loan_mint_decimals: u8,
+    collateral_mint_decimals: u8,
+    price: u64,
+    exponent: i32,
+    collateral_ratio: u32,
+) -> Result<u64, ProgramError> {
+    // collateral = loan_amount * ratio * 10^(collateral_decimals - loan_decimals - exponent) / price
+    let scale = collateral_mint_decimals as i64 - loan_mint_decimals as i64 - exponent as i64;
+    let (numerator, denominator) = if scale >= 0 {
+        let numerator = (loan_amount as u128)
+            .checked_mul(collateral_ratio as u128)
+            .and_then(|value| value.checked_mul(power_of_ten(scale)?))
+            .ok_or(LoanError::MathOverflow)?;
+        (numerator, (price as u128) * BASIS_POINTS as u128)
+    } else {
+        let numerator = (loan_amount as u128)
+            .checked_mul(collateral_ratio as u128)
+            .ok_or(LoanError::MathOverflow)?;
+        let denominator = (price as u128)
+            .checked_mul(BASIS_POINTS as u128)
+            .and_then(|value| value.checked_mul(power_of_ten(-scale)?))
+            .ok_or(LoanError::MathOverflow)?;
+        (numerator, denominator)
+    };
+    if denominator == 0 {
+        return Err(LoanError::InvalidPrice.into());
+    }
+    let value = numerator
+        .checked_add(denominator - 1)
+        .map(|value| value / denominator)
+        .ok_or(LoanError::MathOverflow)?;
+    return to_u64(value);
+}
+
+/// get the value of `collateral_amount` in the loan mint, at `price * 10^exponent` whole
+/// loan tokens per whole collateral token, rounded down
+pub fn get_collateral_value(
+    collateral_amount: u64,
+    loan_mint_decimals: u8,
+    collateral_mint_decimals: u8,
+    price: u64,
+    exponent: i32,
+) -> Result<u64, ProgramError> {
+    // value = collateral_amount * price * 10^(loan_decimals + exponent - collateral_decimals)
+    let scale = loan_mint_decimals as i64 + exponent as i64 - collateral_mint_decimals as i64;
+    let value = (collateral_amount as u128)
+        .checked_mul(price as u128)
+        .ok_or(LoanError::MathOverflow)?;
+    let value = if scale >= 0 {
+        power_of_ten(scale)
+            .and_then(|multiplier| value.checked_mul(multiplier))
+            .ok_or(LoanError::MathOverflow)?
+    } else {
+        // a divisor too large to represent leaves nothing
+        power_of_ten(-scale).map_or(0, |divisor| value / divisor)
+    };
+    return to_u64(value);
+}
+
+/// get `part` as a share of `whole` in basis points, rounded down.  A `whole` of 0 is
+/// covered any number of times over, so gives `u64::MAX`
+pub fn get_basis_points(
+    part: u64,
+    whole: u64,
 ) -> u64 {
-    let processing_fee: u32 = get_processing_fee(borrower, expected_amount, loan_duration, loan_interest);
-    let total_charge = loan_interest + processing_fee;
-    let rate = (total_charge as f64 / 100 as f64) as f64;
-    let pro_rated_duration = loan_duration as f64 / (24 * 365) as f64;
-    let pro_rated_rate = rate * pro_rated_duration + 1 as f64;
-    return (expected_amount as f64 * pro_rated_rate) as u64;
+    if whole == 0 {
+        return u64::MAX;
+    }
+    let value = part as u128 * BASIS_POINTS as u128 / whole as u128;
+    return u64::try_from(value).unwrap_or(u64::MAX);
+}
+
+/// 10 to the power of `scale`, if it fits
+fn power_of_ten(scale: i64) -> Option<u128> {
+    u32::try_from(scale).ok().and_then(|scale| 10u128.checked_pow(scale))
+}
+
+/// How a single repayment is divided up
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RepaymentSplit {
+    /// sent to the treasury
+    pub program_share: u64,
+    /// sent to the guarantor's repayment account
+    pub guarantor_share: u64,
+    /// sent to the lender's repayment account, includes the principal
+    pub lender_share: u64,
+}
+
+/// get how a repayment of `amount` is divided between the program, guarantor and lender
+///
+/// The interest covered by the payment is worked out from the total repaid so far, so
+/// that rounding never accumulates across installments.  The split always adds up to
+/// exactly `amount`.
+pub fn get_repayment_split(
+    config: &ProgramConfig,
+    loan: &Loan,
+    amount: u64,
+) -> Result<RepaymentSplit, ProgramError> {
+    let total_interest = loan.amount
+        .checked_sub(loan.expected_amount)
+        .ok_or(LoanError::MathOverflow)?;
+    let repaid_after = loan.amount_repaid
+        .checked_add(amount)
+        .ok_or(LoanError::MathOverflow)?;
+    // the interest covered by everything repaid up to and including this payment,
+    // minus the interest covered by everything repaid before it
+    let interest_repaid = |repaid: u64| -> Result<u64, ProgramError> {
+        if loan.amount == 0 {
+            return Ok(0);
+        }
+        let value = (total_interest as u128)
+            .checked_mul(repaid as u128)
+            .and_then(|value| value.checked_div(loan.amount as u128))
+            .ok_or(LoanError::MathOverflow)?;
+        to_u64(value)
+    };
+    let loan_interest = interest_repaid(repaid_after)?
+        .checked_sub(interest_repaid(loan.amount_repaid)?)
+        .ok_or(LoanError::MathOverflow)?;
+    let loan_principal = amount
+        .checked_sub(loan_interest)
+        .ok_or(LoanError::MathOverflow)?;
+
+    let program_share = apply_basis_points(loan_interest, get_processing_fee(
+        config,
+        &loan.initializer_pubkey,
+        loan.expected_amount,
+        loan.duration,
+        loan.interest_rate,
+    ))?;
+    let shared_interest = loan_interest
+        .checked_sub(program_share)
+        .ok_or(LoanError::MathOverflow)?;
+    let guarantor_share = apply_basis_points(shared_interest, get_guarantor_share(
+        config,
+        &loan.guarantor_pubkey.unwrap_or_default(),
+        loan.amount,
+    ))?;
+    let lender_interest = apply_basis_points(shared_interest, get_lender_share(
+        config,
+        &loan.lender_pubkey.unwrap_or_default(),
+        loan.amount,
+    ))?;
+    // any rounding remainder goes to the lender
+    let remainder = shared_interest
+        .checked_sub(guarantor_share)
+        .and_then(|value| value.checked_sub(lender_interest))
+        .ok_or(LoanError::MathOverflow)?;
+    let lender_share = loan_principal
+        .checked_add(lender_interest)
+        .and_then(|value| value.checked_add(remainder))
+        .ok_or(LoanError::MathOverflow)?;
+
+    Ok(RepaymentSplit {
+        program_share,
+        guarantor_share,
+        lender_share,
+    })
+}
+
+/// get the time at which a loan accepted at `accepted_at` is due
+pub fn get_due_at(accepted_at: UnixTimestamp, duration: u32) -> Result<UnixTimestamp, ProgramError> {
+    (duration as i64)
+        .checked_mul(SECONDS_PER_HOUR)
+        .and_then(|duration| accepted_at.checked_add(duration))
+        .ok_or_else(|| LoanError::MathOverflow.into())
+}
+
+fn to_u64(value: u128) -> Result<u64, ProgramError> {
+    u64::try_from(value).map_err(|_| LoanError::MathOverflow.into())
+}
+
+/// Seed of the program derived address that owns a loan's vaults
+pub const LOAN_AUTHORITY_SEED: &[u8] = b"loan";
+/// Seed of the program derived address of a loan's collateral vault
+pub const COLLATERAL_VAULT_SEED: &[u8] = b"collateral";
+
+/// find the program derived address that owns the vaults of a loan
+pub fn find_loan_authority_address(
+    program_id: &Pubkey,
+    loan_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LOAN_AUTHORITY_SEED, loan_pubkey.as_ref()], program_id)
+}
+
+/// get the address that owns the vaults of a loan from the bump seed stored on the loan
+pub fn loan_authority_id(
+    program_id: &Pubkey,
+    loan_pubkey: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[LOAN_AUTHORITY_SEED, loan_pubkey.as_ref(), &[bump_seed]], program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+/// find the program wide address that version 1 loans handed their token accounts to.
+/// It shares its seed with the loan authority, but without a loan key
+pub fn find_legacy_loan_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LOAN_AUTHORITY_SEED], program_id)
+}
+
+/// find the address of the token account that holds the collateral of a loan
+pub fn find_collateral_vault_address(
+    program_id: &Pubkey,
+    loan_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[COLLATERAL_VAULT_SEED, loan_pubkey.as_ref()], program_id)
+}
+
+/// Seed of the program derived address of a loan's repayment vault
+pub const REPAYMENT_VAULT_SEED: &[u8] = b"repayment";
+
+/// find the address of the token account that holds the lender's share of the
+/// repayments of a loan until it is withdrawn
+pub fn find_repayment_vault_address(
+    program_id: &Pubkey,
+    loan_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REPAYMENT_VAULT_SEED, loan_pubkey.as_ref()], program_id)
+}
+
+/// Seed of the program derived address of the vault that holds the funds of a loan offer
+pub const OFFER_VAULT_SEED: &[u8] = b"offer";
+
+/// find the address of the vault that holds the funds of a loan offer
+pub fn find_offer_vault_address(
+    program_id: &Pubkey,
+    offer_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OFFER_VAULT_SEED, offer_pubkey.as_ref()], program_id)
+}
+
+/// Seed of the program derived address of the program config
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// find the address of the program config
+pub fn find_config_address(
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// find the address of the program data account the upgradeable loader keeps for the
+/// program, it holds the program's upgrade authority
+pub fn find_program_data_address(
+    program_id: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id())
+}
+
+/// get the address of the program config from the bump seed stored on it
+pub fn config_id(
+    program_id: &Pubkey,
+    bump_seed: u8,
+) -> Result<Pubkey, ProgramError> {
+    Pubkey::create_program_address(&[CONFIG_SEED, &[bump_seed]], program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)
+}
+
+/// Seed of the program derived address of a treasury token account
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// find the address of the token account that collects the program's fees in a mint
+pub fn find_treasury_address(
+    program_id: &Pubkey,
+    mint_pubkey: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_SEED, mint_pubkey.as_ref()], program_id)
 }
 
 // Helpers
@@ -0,0 +1,66 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption, pubkey::Pubkey};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::instruction::accept_loan;
+use loans254::state::{Loan, LoanStatus};
+use loans254::utils::SECONDS_PER_HOUR;
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_accept_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = accept_loan(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.lender_repayment,
+        fixture.borrower_token,
+        fixture.loan,
+        fixture.mint,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Accepted, loan.status);
+    assert_eq!(loan.accepted_at + 24 * 30 * SECONDS_PER_HOUR, loan.due_at);
+    assert_eq!(TOKEN_BALANCE - EXPECTED_AMOUNT, token_balance(&mut banks_client, fixture.lender_token).await);
+    assert_eq!(TOKEN_BALANCE + EXPECTED_AMOUNT - 1000, token_balance(&mut banks_client, fixture.borrower_token).await);
+    assert_eq!(1000, token_balance(&mut banks_client, fixture.treasury).await);
+    // the lender paid for the vault the repayments are held in
+    assert_eq!(COption::Some(fixture.repayment_vault), loan.repayment_vault_pubkey);
+    assert_eq!(0, token_balance(&mut banks_client, fixture.repayment_vault).await);
+    assert!(banks_client.get_balance(fixture.lender.pubkey()).await.unwrap() < LAMPORTS);
+}
+
+#[tokio::test]
+async fn test_accept_loan_rejects_other_token_program() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut instruction = accept_loan(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.lender_repayment,
+        fixture.borrower_token,
+        fixture.loan,
+        fixture.mint,
+    );
+    instruction.accounts[6].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Guaranteed, loan.status);
+}
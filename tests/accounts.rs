@@ -0,0 +1,63 @@
+use solana_program::{program_option::COption, pubkey::Pubkey, sysvar};
+
+use loans254::accounts::{AcceptLoanAccounts, RepayLoanAccounts};
+use loans254::instruction::{accept_loan, repay_loan};
+
+#[test]
+fn test_accept_loan_account_order() {
+    let program_id = Pubkey::new_unique();
+    let lender = Pubkey::new_unique();
+    let lender_transfer = Pubkey::new_unique();
+    let lender_repayment = Pubkey::new_unique();
+    let borrower_receive = Pubkey::new_unique();
+    let loan = Pubkey::new_unique();
+    let instruction = accept_loan(
+        program_id,
+        lender,
+        lender_transfer,
+        lender_repayment,
+        borrower_receive,
+        loan,
+        Pubkey::new_unique(),
+    );
+
+    let keys: Vec<Pubkey> = instruction.accounts.iter().map(|meta| meta.pubkey).collect();
+    assert_eq!(vec![lender, lender_transfer, lender_repayment, borrower_receive, loan], keys[..5].to_vec());
+    assert_eq!(sysvar::clock::id(), keys[7]);
+    // the lender signs, and every account that tokens move out of or into is writable
+    let meta = |index: usize| &instruction.accounts[index];
+    assert!(meta(0).is_signer);
+    assert!(meta(1).is_writable);
+    assert!(!meta(2).is_writable);
+    assert!(meta(3).is_writable);
+    assert!(meta(4).is_writable);
+    assert!(meta(9).is_writable);
+    assert!(meta(10).is_writable);
+    assert_eq!(AcceptLoanAccounts::<Pubkey>::LEN, instruction.accounts.len());
+}
+
+#[test]
+fn test_repay_loan_collateral_accounts() {
+    let program_id = Pubkey::new_unique();
+    let repay = |guarantor: COption<Pubkey>, guarantor_collateral: COption<Pubkey>| {
+        repay_loan(
+            program_id,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            guarantor,
+            guarantor_collateral,
+            100,
+        )
+    };
+    let repay_accounts = RepayLoanAccounts::<Pubkey>::LEN;
+
+    assert_eq!(repay_accounts, repay(COption::None, COption::None).accounts.len());
+    let guarantor = Pubkey::new_unique();
+    let instruction = repay(COption::Some(guarantor), COption::Some(Pubkey::new_unique()));
+    assert_eq!(repay_accounts + 4, instruction.accounts.len());
+    assert_eq!(guarantor, instruction.accounts[repay_accounts].pubkey);
+}
@@ -0,0 +1,57 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::instruction::InstructionError;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::add_collateral;
+use loans254::state::LoanStatus;
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_add_collateral() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // anyone can top up the vault, here the borrower does
+    let instruction = add_collateral(fixture.program_id, fixture.borrower.pubkey(), fixture.borrower_token, fixture.loan, 100_000);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    assert_eq!(COLLATERAL_AMOUNT + 100_000, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(TOKEN_BALANCE - 100_000, token_balance(&mut banks_client, fixture.borrower_token).await);
+}
+
+#[tokio::test]
+async fn test_add_collateral_rejects_other_vault() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the collateral can only go into the loan's own vault
+    let mut instruction = add_collateral(fixture.program_id, fixture.borrower.pubkey(), fixture.borrower_token, fixture.loan, 100_000);
+    instruction.accounts[3].pubkey = fixture.guarantor_collateral;
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::AccountMismatch as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, fixture.borrower_token).await);
+}
+
+#[tokio::test]
+async fn test_add_collateral_without_vault() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Initialized), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // a loan that has not been guaranteed has no vault to add to
+    let instruction = add_collateral(fixture.program_id, fixture.borrower.pubkey(), fixture.borrower_token, fixture.loan, 100_000);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InvalidLoanStatus as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
@@ -0,0 +1,114 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::cancel_loan;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_cancel_initialized_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Initialized), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = cancel_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.loan,
+        COption::None,
+        COption::None,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Cancelled, loan.status);
+    assert_ne!(0, loan.closed_at);
+}
+
+#[tokio::test]
+async fn test_cancel_guaranteed_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = cancel_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Cancelled, loan.status);
+    assert_eq!(TOKEN_BALANCE + COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_cancel_loan_rejects_other_initializer() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the guarantor can not cancel the loan to get their collateral back
+    let instruction = cancel_loan(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_cancel_loan_rejects_other_collateral_account() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the collateral can only go back to a token account of the guarantor
+    let instruction = cancel_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.borrower_token),
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::TokenOwnerMismatch as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_accepted_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = cancel_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InvalidLoanStatus as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
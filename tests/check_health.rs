@@ -0,0 +1,83 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::{Instruction, InstructionError}, program_option::COption, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+use solana_sdk::transaction::TransactionError;
+
+use loans254::error::LoanError;
+use loans254::instruction::check_health;
+use loans254::state::LoanStatus;
+
+use fixtures::*;
+
+/// An accepted loan collateralized in the mint of `priced_collateral`
+fn check_health_program_test(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> ProgramTest {
+    let loan = priced_collateral.loan(fixture, LoanStatus::Accepted);
+    let mut program_test = fixture.program_test(loan, COLLATERAL_AMOUNT);
+    priced_collateral.add_accounts(fixture, &mut program_test);
+    program_test
+}
+
+fn check_health_instruction(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> Instruction {
+    check_health(
+        fixture.program_id,
+        fixture.loan,
+        priced_collateral.mint,
+        COption::Some(priced_collateral.price_feed),
+    )
+}
+
+#[tokio::test]
+async fn test_check_health_reads_feed_of_config_oracle() {
+    let fixture = LoanFixture::new();
+    // published long ago, so a trusted feed is stale
+    let priced_collateral = PricedCollateral {
+        publish_time: 0,
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = check_health_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the config trusts the feed, so it gets as far as its price
+    let instruction = check_health_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::StalePrice as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_check_health_rejects_feed_of_other_oracle() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral {
+        price_feed_owner: Pubkey::new_unique(),
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = check_health_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // anyone can deploy a program that writes price feeds, only the config's oracle counts
+    let instruction = check_health_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_check_health_rejects_feed_of_other_publisher() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral::published_by(&fixture, &Pubkey::new_unique());
+    let program_test = check_health_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // anyone can publish a feed with the config's oracle, only the config's publisher counts
+    let instruction = check_health_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await,
+    );
+}
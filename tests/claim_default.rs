@@ -0,0 +1,104 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::instruction::InstructionError;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::claim_default;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+/// An accepted loan that was due at the start of the epoch
+fn overdue_loan(fixture: &LoanFixture) -> Loan {
+    Loan {
+        due_at: 1,
+        ..fixture.loan(LoanStatus::Accepted)
+    }
+}
+
+#[tokio::test]
+async fn test_claim_default() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(overdue_loan(&fixture), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = claim_default(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.guarantor.pubkey(),
+        fixture.loan,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Defaulted, loan.status);
+    assert_ne!(0, loan.closed_at);
+    assert_eq!(TOKEN_BALANCE + COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.lender_token).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_claim_default_before_due() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = claim_default(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.guarantor.pubkey(),
+        fixture.loan,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::LoanNotDue as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_claim_default_rejects_other_lender() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(overdue_loan(&fixture), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // only the lender can claim the collateral
+    let instruction = claim_default(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor.pubkey(),
+        fixture.loan,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_claim_default_rejects_other_guarantor() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(overdue_loan(&fixture), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the vault rent goes back to the guarantor who paid it
+    let instruction = claim_default(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.lender.pubkey(),
+        fixture.loan,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::AccountMismatch as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+}
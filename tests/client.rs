@@ -0,0 +1,74 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(all(feature = "test-bpf", feature = "client"))]
+
+use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{account::Account, signature::{Keypair, Signer}};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+use loans254::client::LoanClient;
+use loans254::processor::process_instruction;
+use loans254::state::{LoanStatus, ProgramConfig};
+use loans254::utils::find_config_address;
+
+fn packed_account<T: Pack>(data: T, owner: &Pubkey) -> Account {
+    let mut account = Account::new(Rent::default().minimum_balance(T::LEN), T::LEN, owner);
+    T::pack(data, &mut account.data).unwrap();
+    account
+}
+
+#[tokio::test]
+async fn test_client_init_and_cancel_loan() {
+    let program_id = Pubkey::new_unique();
+    let borrower = Keypair::new();
+    let mint_pubkey = Pubkey::new_unique();
+    let receive_pubkey = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new("loans254", program_id, processor!(process_instruction));
+    let (config_pubkey, bump_seed) = find_config_address(&program_id);
+    program_test.add_account(config_pubkey, packed_account(ProgramConfig {
+        is_initialized: true,
+        admin_pubkey: Pubkey::new_unique(),
+        interest_rate: 900,
+        duration: 24 * 30,
+        processing_fee: 100,
+        application_fee: 10,
+        guarantor_share: 5000,
+        lender_share: 5000,
+        bump_seed,
+        collateral_ratio: 15000,
+        maintenance_ratio: 12000,
+        liquidation_bonus: 500,
+        oracle_program_id: Pubkey::new_unique(),
+        oracle_publisher: Pubkey::new_unique(),
+    }, &program_id));
+    program_test.add_account(mint_pubkey, packed_account(Mint {
+        is_initialized: true,
+        decimals: 6,
+        ..Mint::default()
+    }, &spl_token::id()));
+    program_test.add_account(receive_pubkey, packed_account(TokenAccount {
+        mint: mint_pubkey,
+        owner: borrower.pubkey(),
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    }, &spl_token::id()));
+    let (banks_client, payer, _recent_blockhash) = program_test.start().await;
+    let mut client = LoanClient::new(banks_client, program_id, payer);
+
+    let collateral_mint_pubkey = Pubkey::new_unique();
+    let loan_pubkey = client
+        .init_loan(&borrower, mint_pubkey, receive_pubkey, 13337, 24 * 7, 1000, collateral_mint_pubkey)
+        .await
+        .unwrap();
+    let loan = client.get_loan(loan_pubkey).await.unwrap();
+    assert_eq!(LoanStatus::Initialized, loan.status);
+    assert_eq!(borrower.pubkey(), loan.initializer_pubkey);
+    assert_eq!(13337, loan.expected_amount);
+    assert_eq!(24 * 7, loan.duration);
+    assert_eq!(COption::Some(collateral_mint_pubkey), loan.collateral_mint_pubkey);
+    assert_eq!(COption::None, loan.guarantor_pubkey);
+
+    client.cancel_loan(loan_pubkey, &borrower, None).await.unwrap();
+    assert_eq!(LoanStatus::Cancelled, client.get_loan(loan_pubkey).await.unwrap().status);
+}
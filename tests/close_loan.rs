@@ -0,0 +1,74 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::close_loan;
+use loans254::state::LoanStatus;
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_close_loan() {
+    let fixture = LoanFixture::new();
+    // the lender has withdrawn the repayments and closed the repayment vault
+    let mut loan = fixture.loan(LoanStatus::Repaid);
+    loan.repayment_vault_pubkey = COption::None;
+    let program_test = fixture.program_test(loan, 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let rent = banks_client.get_balance(fixture.loan).await.unwrap();
+
+    let instruction = close_loan(fixture.program_id, fixture.borrower.pubkey(), fixture.loan);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    assert!(!account_exists(&mut banks_client, fixture.loan).await);
+    assert_eq!(LAMPORTS + rent, banks_client.get_balance(fixture.borrower.pubkey()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_close_loan_rejects_other_initializer() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Repaid), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the rent goes back to the borrower who paid it
+    let instruction = close_loan(fixture.program_id, fixture.lender.pubkey(), fixture.loan);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+    assert!(account_exists(&mut banks_client, fixture.loan).await);
+}
+
+#[tokio::test]
+async fn test_close_accepted_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the loan still holds collateral
+    let instruction = close_loan(fixture.program_id, fixture.borrower.pubkey(), fixture.loan);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InvalidLoanStatus as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_close_loan_before_repayment_withdrawn() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Repaid), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the loan is the only record of the vault holding the lender's repayments
+    let instruction = close_loan(fixture.program_id, fixture.borrower.pubkey(), fixture.loan);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::RepaymentNotWithdrawn as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert!(account_exists(&mut banks_client, fixture.loan).await);
+}
@@ -0,0 +1,31 @@
+use solana_program::{decode_error::DecodeError, program_error::ProgramError};
+
+use loans254::error::LoanError;
+
+#[test]
+fn test_decode_custom_error() {
+    for error in &[LoanError::InvalidInstruction, LoanError::TokenMintMismatch, LoanError::LoanExpired] {
+        let code = match ProgramError::from(*error) {
+            ProgramError::Custom(code) => code,
+            other => panic!("Unexpected error: {:?}", other),
+        };
+        assert_eq!(Some(*error), LoanError::decode_custom_error_to_enum(code));
+    }
+    assert_eq!(None, <LoanError as DecodeError<LoanError>>::decode_custom_error_to_enum(1000));
+}
+
+#[test]
+fn test_error_messages() {
+    assert_eq!("Invalid Loan Status", LoanError::InvalidLoanStatus.to_string());
+    assert_eq!("Account Does Not Match The Loan", LoanError::AccountMismatch.to_string());
+}
+
+#[test]
+fn test_error_codes() {
+    // error codes are part of the program interface, new errors only go at the end
+    assert_eq!(7, LoanError::TokenOwnerMismatch as u32);
+    assert_eq!(8, LoanError::TokenMintMismatch as u32);
+    assert_eq!(9, LoanError::TokenAccountDelegated as u32);
+    assert_eq!(10, LoanError::TokenAccountCloseAuthority as u32);
+    assert_eq!(11, LoanError::InvalidLoanStatus as u32);
+}
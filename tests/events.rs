@@ -0,0 +1,55 @@
+use borsh::BorshSerialize;
+use solana_program::pubkey::Pubkey;
+
+use loans254::events::{parse_events, LoanEvent, EVENT_PREFIX};
+use loans254::state::LoanStatus;
+
+fn event_log(event: &LoanEvent) -> String {
+    format!("Program log: {}{}", EVENT_PREFIX, base64::encode(event.try_to_vec().unwrap()))
+}
+
+#[test]
+fn test_from_log_message() {
+    let event = LoanEvent::LoanCancelled {
+        loan: Pubkey::new_unique(),
+        initializer: Pubkey::new_unique(),
+        status: LoanStatus::Cancelled,
+    };
+    let log = event_log(&event);
+    let message = log.strip_prefix("Program log: ").unwrap();
+    assert_eq!(Some(event), LoanEvent::from_log_message(message));
+    assert_eq!(None, LoanEvent::from_log_message("Instruction: CancelLoan"));
+    assert_eq!(None, LoanEvent::from_log_message(&format!("{}not base64", EVENT_PREFIX)));
+}
+
+#[test]
+fn test_parse_events() {
+    let program_id = Pubkey::new_unique();
+    let other_program_id = Pubkey::new_unique();
+    let loan = Pubkey::new_unique();
+    let repaid = LoanEvent::LoanRepaid {
+        loan,
+        payer: Pubkey::new_unique(),
+        amount: 500,
+        amount_repaid: 500,
+        outstanding_amount: 700,
+        status: LoanStatus::Accepted,
+    };
+    let forged = LoanEvent::LoanCancelled {
+        loan,
+        initializer: Pubkey::new_unique(),
+        status: LoanStatus::Cancelled,
+    };
+    let logs = vec![
+        format!("Program {} invoke [1]", program_id),
+        "Program log: Instruction: RepayLoan".to_string(),
+        format!("Program {} invoke [2]", other_program_id),
+        event_log(&forged),
+        format!("Program {} success", other_program_id),
+        event_log(&repaid),
+        format!("Program {} consumed 21000 of 200000 compute units", program_id),
+        format!("Program {} success", program_id),
+        event_log(&forged),
+    ];
+    assert_eq!(vec![repaid], parse_events(&program_id, &logs));
+}
@@ -0,0 +1,89 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::expire_loan;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_expire_initialized_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Initialized), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // anyone can expire the request, here the payer does
+    let instruction = expire_loan(fixture.program_id, fixture.loan, COption::None, COption::None);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Expired, loan.status);
+    assert_ne!(0, loan.closed_at);
+}
+
+#[tokio::test]
+async fn test_expire_guaranteed_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = expire_loan(
+        fixture.program_id,
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Expired, loan.status);
+    assert_eq!(TOKEN_BALANCE + COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_expire_open_request() {
+    let fixture = LoanFixture::new();
+    let loan = Loan {
+        created_at: i64::MAX / 2,
+        ..fixture.loan(LoanStatus::Guaranteed)
+    };
+    let program_test = fixture.program_test(loan, COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = expire_loan(
+        fixture.program_id,
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::RequestNotExpired as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_expire_accepted_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = expire_loan(
+        fixture.program_id,
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InvalidLoanStatus as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await,
+    );
+}
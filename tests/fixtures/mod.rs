@@ -0,0 +1,332 @@
+//! Accounts for the processor tests.  Each test preloads a loan in the state the
+//! instruction under test expects, with its token accounts, vault and treasury, and
+//! runs the instruction through `ProgramTest`
+#![allow(dead_code)]
+
+use solana_program::{
+    bpf_loader_upgradeable,
+    clock::UnixTimestamp,
+    hash::Hash,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+
+use loans254::oracle::{find_price_feed_address, oracle_program, PriceFeed};
+use loans254::processor::process_instruction;
+use loans254::state::{Loan, LoanStatus, ProgramConfig};
+use loans254::utils::{
+    find_collateral_vault_address,
+    find_config_address,
+    find_loan_authority_address,
+    find_repayment_vault_address,
+    find_treasury_address,
+};
+
+/// The amount the borrower asked for
+pub const EXPECTED_AMOUNT: u64 = 1_000_000;
+/// The amount owed, including interest
+pub const AMOUNT: u64 = 1_010_000;
+/// The collateral the config requires for `AMOUNT`, at a ratio of 150%
+pub const COLLATERAL_AMOUNT: u64 = 1_515_000;
+/// The collateral the config requires for `AMOUNT` in the mint of `PricedCollateral`,
+/// at a ratio of 150% and the lowest price its feed vouches for
+pub const PRICED_COLLATERAL_AMOUNT: u64 = 613_360_324;
+/// What every token account starts with
+pub const TOKEN_BALANCE: u64 = 10_000_000;
+/// What every party starts with, to pay for the accounts they create
+pub const LAMPORTS: u64 = 1_000_000_000;
+
+pub fn packed_account<T: Pack>(data: T, owner: &Pubkey) -> Account {
+    let mut account = Account::new(Rent::default().minimum_balance(T::LEN), T::LEN, owner);
+    T::pack(data, &mut account.data).unwrap();
+    account
+}
+
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    packed_account(TokenAccount {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    }, &spl_token::id())
+}
+
+/// The program data account the upgradeable loader keeps for a program, holding its
+/// upgrade authority but no program
+pub fn program_data_account(upgrade_authority: Option<&Pubkey>) -> Account {
+    // the bincode encoded `UpgradeableLoaderState::ProgramData { slot, upgrade_authority_address }`
+    let mut data = vec![3, 0, 0, 0];
+    data.extend_from_slice(&0u64.to_le_bytes());
+    match upgrade_authority {
+        Some(upgrade_authority) => {
+            data.push(1);
+            data.extend_from_slice(upgrade_authority.as_ref());
+        }
+        None => data.extend_from_slice(&[0; 33]),
+    }
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        ..Account::default()
+    }
+}
+
+/// A loan in the loan mint, collateralized in the same mint, with its parties and
+/// their token accounts
+pub struct LoanFixture {
+    pub program_id: Pubkey,
+    pub admin: Keypair,
+    pub mint: Pubkey,
+    pub loan: Pubkey,
+    pub borrower: Keypair,
+    pub borrower_token: Pubkey,
+    pub guarantor: Keypair,
+    pub guarantor_collateral: Pubkey,
+    pub guarantor_repayment: Pubkey,
+    pub lender: Keypair,
+    pub lender_token: Pubkey,
+    pub lender_repayment: Pubkey,
+    pub config: Pubkey,
+    pub treasury: Pubkey,
+    pub collateral_vault: Pubkey,
+    pub repayment_vault: Pubkey,
+    pub loan_authority: Pubkey,
+    pub oracle_publisher: Pubkey,
+}
+
+impl LoanFixture {
+    pub fn new() -> Self {
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let loan = Pubkey::new_unique();
+        LoanFixture {
+            program_id,
+            admin: Keypair::new(),
+            mint,
+            loan,
+            borrower: Keypair::new(),
+            borrower_token: Pubkey::new_unique(),
+            guarantor: Keypair::new(),
+            guarantor_collateral: Pubkey::new_unique(),
+            guarantor_repayment: Pubkey::new_unique(),
+            lender: Keypair::new(),
+            lender_token: Pubkey::new_unique(),
+            lender_repayment: Pubkey::new_unique(),
+            config: find_config_address(&program_id).0,
+            treasury: find_treasury_address(&program_id, &mint).0,
+            collateral_vault: find_collateral_vault_address(&program_id, &loan).0,
+            repayment_vault: find_repayment_vault_address(&program_id, &loan).0,
+            loan_authority: find_loan_authority_address(&program_id, &loan).0,
+            oracle_publisher: Pubkey::new_unique(),
+        }
+    }
+
+    pub fn config(&self) -> ProgramConfig {
+        ProgramConfig {
+            is_initialized: true,
+            admin_pubkey: self.admin.pubkey(),
+            interest_rate: 900,
+            duration: 24 * 30,
+            processing_fee: 100,
+            application_fee: 10,
+            guarantor_share: 5000,
+            lender_share: 5000,
+            bump_seed: find_config_address(&self.program_id).1,
+            collateral_ratio: 15000,
+            maintenance_ratio: 12000,
+            liquidation_bonus: 500,
+            oracle_program_id: oracle_program::id(),
+            oracle_publisher: self.oracle_publisher,
+        }
+    }
+
+    /// The loan as it is once it reaches `status`.  Accepted loans are not due
+    pub fn loan(&self, status: LoanStatus) -> Loan {
+        let is_guaranteed = status != LoanStatus::Initialized;
+        let is_accepted = status != LoanStatus::Initialized && status != LoanStatus::Guaranteed;
+        let some_if = |condition: bool, pubkey: Pubkey| {
+            if condition {
+                COption::Some(pubkey)
+            } else {
+                COption::None
+            }
+        };
+        Loan {
+            is_initialized: true,
+            status,
+            initializer_pubkey: self.borrower.pubkey(),
+            loan_mint_pubkey: self.mint,
+            borrower_loan_receive_pubkey: self.borrower_token,
+            guarantor_pubkey: some_if(is_guaranteed, self.guarantor.pubkey()),
+            guarantor_repayment_pubkey: some_if(is_guaranteed, self.guarantor_repayment),
+            collateral_account_pubkey: some_if(is_guaranteed, self.collateral_vault),
+            lender_pubkey: some_if(is_accepted, self.lender.pubkey()),
+            lender_repayment_pubkey: some_if(is_accepted, self.lender_repayment),
+            expected_amount: EXPECTED_AMOUNT,
+            amount: AMOUNT,
+            amount_repaid: 0,
+            interest_rate: 900,
+            duration: 24 * 30,
+            created_at: 1,
+            guaranteed_at: if is_guaranteed { 1 } else { 0 },
+            accepted_at: if is_accepted { 1 } else { 0 },
+            due_at: if is_accepted { i64::MAX } else { 0 },
+            closed_at: 0,
+            bump_seed: find_loan_authority_address(&self.program_id, &self.loan).1,
+            collateral_mint_pubkey: COption::Some(self.mint),
+            loan_mint_decimals: 6,
+            max_interest_rate: 1000,
+            collateral_ratio: 15000,
+            liquidated_amount: 0,
+            liquidated_collateral: 0,
+            repayment_vault_pubkey: some_if(is_accepted, self.repayment_vault),
+        }
+    }
+
+    /// The program with the config, the loan mint and treasury, every party's token
+    /// accounts, the loan, its collateral vault holding `collateral_amount` of the
+    /// loan's collateral mint once the loan has been guaranteed, and its empty repayment
+    /// vault once it has been accepted
+    pub fn program_test(&self, loan: Loan, collateral_amount: u64) -> ProgramTest {
+        let mut program_test = ProgramTest::new("loans254", self.program_id, processor!(process_instruction));
+        program_test.add_account(self.config, packed_account(self.config(), &self.program_id));
+        program_test.add_account(self.mint, packed_account(Mint {
+            is_initialized: true,
+            decimals: 6,
+            supply: u64::MAX / 2,
+            ..Mint::default()
+        }, &spl_token::id()));
+        program_test.add_account(self.treasury, token_account(&self.mint, &self.config, 0));
+        for party in &[&self.admin, &self.borrower, &self.guarantor, &self.lender] {
+            program_test.add_account(party.pubkey(), Account::new(LAMPORTS, 0, &system_program::id()));
+        }
+        for (token, owner) in &[
+            (self.borrower_token, self.borrower.pubkey()),
+            (self.guarantor_collateral, self.guarantor.pubkey()),
+            (self.guarantor_repayment, self.guarantor.pubkey()),
+            (self.lender_token, self.lender.pubkey()),
+            (self.lender_repayment, self.lender.pubkey()),
+        ] {
+            program_test.add_account(*token, token_account(&self.mint, owner, TOKEN_BALANCE));
+        }
+        if loan.collateral_account_pubkey.is_some() {
+            program_test.add_account(
+                self.collateral_vault,
+                token_account(
+                    &loan.collateral_mint_pubkey.unwrap_or(self.mint),
+                    &self.loan_authority,
+                    collateral_amount,
+                ),
+            );
+        }
+        if loan.repayment_vault_pubkey.is_some() {
+            program_test.add_account(self.repayment_vault, token_account(&self.mint, &self.loan_authority, 0));
+        }
+        program_test.add_account(self.loan, packed_account(loan, &self.program_id));
+        program_test
+    }
+}
+
+/// Collateral in a mint of its own, with 9 decimals, and a price feed of it in the loan
+/// mint that prices a collateral token at 2.50 loan tokens, give or take 0.03
+pub struct PricedCollateral {
+    pub mint: Pubkey,
+    /// The guarantor's account in the collateral mint, holding a whole token
+    pub guarantor_collateral: Pubkey,
+    pub price_feed: Pubkey,
+    pub price_feed_owner: Pubkey,
+    pub publish_time: UnixTimestamp,
+}
+
+impl PricedCollateral {
+    /// Collateral valued with the price feed the config trusts
+    pub fn new(fixture: &LoanFixture) -> Self {
+        Self::published_by(fixture, &fixture.oracle_publisher)
+    }
+
+    /// Collateral valued with the price feed `publisher` keeps with the config's oracle
+    /// program.  The price is never stale, it is published after any time the test
+    /// clock can reach
+    pub fn published_by(fixture: &LoanFixture, publisher: &Pubkey) -> Self {
+        let mint = Pubkey::new_unique();
+        Self {
+            mint,
+            guarantor_collateral: Pubkey::new_unique(),
+            price_feed: find_price_feed_address(&oracle_program::id(), publisher, &mint, &fixture.mint).0,
+            price_feed_owner: oracle_program::id(),
+            publish_time: UnixTimestamp::MAX,
+        }
+    }
+
+    /// The loan as it is once it reaches `status`, collateralized in this mint
+    pub fn loan(&self, fixture: &LoanFixture, status: LoanStatus) -> Loan {
+        Loan {
+            collateral_mint_pubkey: COption::Some(self.mint),
+            ..fixture.loan(status)
+        }
+    }
+
+    /// Adds the collateral mint, the guarantor's collateral account and the price feed
+    pub fn add_accounts(&self, fixture: &LoanFixture, program_test: &mut ProgramTest) {
+        program_test.add_account(self.mint, packed_account(Mint {
+            is_initialized: true,
+            decimals: 9,
+            supply: u64::MAX / 2,
+            ..Mint::default()
+        }, &spl_token::id()));
+        program_test.add_account(
+            self.guarantor_collateral,
+            token_account(&self.mint, &fixture.guarantor.pubkey(), 1_000_000_000),
+        );
+        program_test.add_account(self.price_feed, packed_account(PriceFeed {
+            is_initialized: true,
+            base_mint: self.mint,
+            quote_mint: fixture.mint,
+            price: 250,
+            exponent: -2,
+            confidence: 3,
+            publish_time: self.publish_time,
+        }, &self.price_feed_owner));
+    }
+}
+
+/// Run the instruction in its own transaction, signed by the payer and `signers`
+pub async fn process(
+    banks_client: &mut BanksClient,
+    payer: &Keypair,
+    recent_blockhash: Hash,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+    let mut all_signers = vec![payer];
+    all_signers.extend_from_slice(signers);
+    transaction.sign(&all_signers, recent_blockhash);
+    banks_client.process_transaction(transaction).await.map_err(|error| error.unwrap())
+}
+
+pub async fn get_packed<T: Pack>(banks_client: &mut BanksClient, pubkey: Pubkey) -> T {
+    let account = banks_client.get_account(pubkey).await.unwrap().unwrap();
+    T::unpack_unchecked(&account.data).unwrap()
+}
+
+pub async fn token_balance(banks_client: &mut BanksClient, pubkey: Pubkey) -> u64 {
+    get_packed::<TokenAccount>(banks_client, pubkey).await.amount
+}
+
+pub async fn account_exists(banks_client: &mut BanksClient, pubkey: Pubkey) -> bool {
+    banks_client.get_account(pubkey).await.unwrap().is_some()
+}
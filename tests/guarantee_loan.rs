@@ -0,0 +1,107 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::{Instruction, InstructionError}, program_option::COption, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::guarantee_loan;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+/// A loan request that asks for collateral in the mint of `priced_collateral`
+fn guarantee_loan_program_test(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> ProgramTest {
+    let loan = priced_collateral.loan(fixture, LoanStatus::Initialized);
+    let mut program_test = fixture.program_test(loan, 0);
+    priced_collateral.add_accounts(fixture, &mut program_test);
+    program_test
+}
+
+fn guarantee_loan_instruction(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> Instruction {
+    guarantee_loan(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        priced_collateral.guarantor_collateral,
+        fixture.guarantor_repayment,
+        fixture.loan,
+        priced_collateral.mint,
+        COption::Some(priced_collateral.price_feed),
+    )
+}
+
+#[tokio::test]
+async fn test_guarantee_loan_with_priced_collateral() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral::new(&fixture);
+    let program_test = guarantee_loan_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = guarantee_loan_instruction(&fixture, &priced_collateral);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await.unwrap();
+
+    // the collateral is valued at the lowest price the feed vouches for
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Guaranteed, loan.status);
+    assert_eq!(COption::Some(priced_collateral.mint), loan.collateral_mint_pubkey);
+    assert_eq!(PRICED_COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(
+        1_000_000_000 - PRICED_COLLATERAL_AMOUNT,
+        token_balance(&mut banks_client, priced_collateral.guarantor_collateral).await,
+    );
+}
+
+#[tokio::test]
+async fn test_guarantee_loan_with_stale_price() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral {
+        publish_time: 0,
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = guarantee_loan_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = guarantee_loan_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::StalePrice as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_guarantee_loan_rejects_feed_of_other_oracle() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral {
+        price_feed_owner: Pubkey::new_unique(),
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = guarantee_loan_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = guarantee_loan_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_guarantee_loan_rejects_feed_of_other_publisher() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral::published_by(&fixture, &Pubkey::new_unique());
+    let program_test = guarantee_loan_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the other publisher could have priced the collateral as high as they liked
+    let instruction = guarantee_loan_instruction(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "idl")]
+
+use loans254::idl::generate_idl;
+use loans254::instruction::INSTRUCTION_VERSION;
+
+#[test]
+fn test_generate_idl() {
+    let idl = generate_idl();
+    assert_eq!(INSTRUCTION_VERSION, idl["instructionVersion"]);
+
+    let instructions = idl["instructions"].as_array().unwrap();
+    assert_eq!("InitLoan", instructions[0]["name"]);
+    assert_eq!("amount", instructions[0]["args"][0]["name"]);
+    assert_eq!("u64", instructions[0]["args"][0]["type"]);
+    assert_eq!("initializer", instructions[0]["accounts"][0]["name"]);
+    assert_eq!(true, instructions[0]["accounts"][0]["isSigner"]);
+    for instruction in instructions {
+        assert!(!instruction["accounts"].as_array().unwrap().is_empty());
+    }
+
+    // the collateral accounts are only passed when there is collateral to return
+    let repay_accounts = instructions[3]["accounts"].as_array().unwrap();
+    assert_eq!(false, repay_accounts[0]["isOptional"]);
+    assert_eq!(true, repay_accounts.last().unwrap()["isOptional"]);
+
+    let errors = idl["errors"].as_array().unwrap();
+    assert_eq!("InvalidInstruction", errors[0]["name"]);
+    assert_eq!("RepaymentNotWithdrawn", errors.last().unwrap()["name"]);
+}
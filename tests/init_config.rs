@@ -0,0 +1,112 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::{Instruction, InstructionError}, pubkey::Pubkey, system_program};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{account::Account, signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::init_config;
+use loans254::processor::process_instruction;
+use loans254::state::ProgramConfig;
+use loans254::utils::find_program_data_address;
+
+use fixtures::*;
+
+/// The program before its config is created, with the upgrade authority in its program
+/// data account
+fn init_config_program_test(fixture: &LoanFixture, upgrade_authority: Option<&Pubkey>) -> ProgramTest {
+    let mut program_test = ProgramTest::new("loans254", fixture.program_id, processor!(process_instruction));
+    program_test.add_account(
+        find_program_data_address(&fixture.program_id).0,
+        program_data_account(upgrade_authority),
+    );
+    for party in &[&fixture.admin, &fixture.borrower] {
+        program_test.add_account(party.pubkey(), Account::new(LAMPORTS, 0, &system_program::id()));
+    }
+    program_test
+}
+
+fn init_config_instruction(fixture: &LoanFixture, admin: &Pubkey) -> Instruction {
+    let config = fixture.config();
+    init_config(
+        fixture.program_id,
+        *admin,
+        config.interest_rate,
+        config.duration,
+        config.processing_fee,
+        config.application_fee,
+        config.guarantor_share,
+        config.lender_share,
+        config.collateral_ratio,
+        config.maintenance_ratio,
+        config.liquidation_bonus,
+        config.oracle_program_id,
+        config.oracle_publisher,
+    )
+}
+
+#[tokio::test]
+async fn test_init_config() {
+    let fixture = LoanFixture::new();
+    let program_test = init_config_program_test(&fixture, Some(&fixture.admin.pubkey()));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = init_config_instruction(&fixture, &fixture.admin.pubkey());
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await.unwrap();
+
+    let config: ProgramConfig = get_packed(&mut banks_client, fixture.config).await;
+    assert!(config.is_initialized);
+    assert_eq!(fixture.admin.pubkey(), config.admin_pubkey);
+    assert_eq!(fixture.config().bump_seed, config.bump_seed);
+    assert_eq!(12000, config.maintenance_ratio);
+    assert_eq!(fixture.config().oracle_program_id, config.oracle_program_id);
+    assert_eq!(fixture.oracle_publisher, config.oracle_publisher);
+}
+
+#[tokio::test]
+async fn test_init_config_rejects_other_admin() {
+    let fixture = LoanFixture::new();
+    let program_test = init_config_program_test(&fixture, Some(&fixture.admin.pubkey()));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // someone watching the deployment can not make themselves the admin
+    let instruction = init_config_instruction(&fixture, &fixture.borrower.pubkey());
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert!(!account_exists(&mut banks_client, fixture.config).await);
+}
+
+#[tokio::test]
+async fn test_init_config_of_immutable_program() {
+    let fixture = LoanFixture::new();
+    let program_test = init_config_program_test(&fixture, None);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = init_config_instruction(&fixture, &fixture.admin.pubkey());
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_init_config_rejects_other_program_data() {
+    let fixture = LoanFixture::new();
+    let program_data = Pubkey::new_unique();
+    let mut program_test = init_config_program_test(&fixture, Some(&fixture.admin.pubkey()));
+    program_test.add_account(program_data, program_data_account(Some(&fixture.admin.pubkey())));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the upgrade authority has to come from the program's own program data account
+    let mut instruction = init_config_instruction(&fixture, &fixture.admin.pubkey());
+    instruction.accounts[4].pubkey = program_data;
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await,
+    );
+}
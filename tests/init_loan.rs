@@ -3,7 +3,7 @@
 
 use solana_program::{
     account_info::AccountInfo,
-    clock::Epoch,
+    clock::{Clock, Epoch},
     entrypoint::ProgramResult,
     instruction::{Instruction},
     program_option::COption,
@@ -12,7 +12,6 @@ use solana_program::{
     rent::Rent,
 };
 use solana_sdk::account::{create_account, create_is_signer_account_infos, Account};
-use solana_sdk::{bpf_loader};
 use std::str::FromStr;
 use spl_token::state::{Account as TokenAccount, AccountState, Mint};
 
@@ -20,9 +19,11 @@ use safe_transmute::to_bytes::{transmute_to_bytes};
 use bumpalo::{vec as bump_vec, Bump};
 use rand::prelude::*;
 
+use loans254::error::LoanError;
 use loans254::instruction::{init_loan};
 use loans254::processor::{Processor};
-use loans254::state::{Loan, LoanStatus};
+use loans254::state::{Loan, LoanStatus, ProgramConfig};
+use loans254::utils::find_config_address;
 
 fn do_process_instruction(
     instruction: Instruction,
@@ -43,6 +44,10 @@ fn rent_sysvar() -> Account {
     create_account(&Rent::default(), 42)
 }
 
+fn clock_sysvar(unix_timestamp: i64) -> Account {
+    create_account(&Clock { unix_timestamp, ..Clock::default() }, 42)
+}
+
 fn random_pubkey<'bump, G: rand::Rng>(_rng: &mut G, bump: &'bump Bump) -> &'bump Pubkey {
     bump.alloc(Pubkey::new(transmute_to_bytes(&rand::random::<[u64; 4]>())))
 }
@@ -51,6 +56,7 @@ fn new_token_mint<'bump, Gen: Rng>(rng: &mut Gen, bump: &'bump Bump) -> AccountI
     let data = bump_vec![in bump; 0u8; Mint::LEN].into_bump_slice_mut();
     let mut mint = Mint::default();
     mint.is_initialized = true;
+    mint.decimals = 6;
     Mint::pack(mint, data).unwrap();
     AccountInfo::new(
         random_pubkey(rng, bump),
@@ -88,17 +94,31 @@ fn new_token_account<'bump, Gen: Rng>(
     )
 }
 
-fn new_spl_token_program<'bump>(bump: &'bump Bump) -> AccountInfo<'bump> {
-    AccountInfo::new(
-        &spl_token::ID,
-        true,
-        false,
-        bump.alloc(0),
-        &mut [],
-        &bpf_loader::ID,
-        false,
-        Epoch::default(),
-    )
+fn config_account(program_id: &Pubkey) -> Account {
+    let (_config_pubkey, bump_seed) = find_config_address(program_id);
+    let config = ProgramConfig {
+        is_initialized: true,
+        admin_pubkey: Pubkey::new_unique(),
+        interest_rate: 900,
+        duration: 24 * 30,
+        processing_fee: 100,
+        application_fee: 10,
+        guarantor_share: 5000,
+        lender_share: 5000,
+        bump_seed,
+        collateral_ratio: 15000,
+        maintenance_ratio: 12000,
+        liquidation_bonus: 500,
+        oracle_program_id: Pubkey::new_unique(),
+        oracle_publisher: Pubkey::new_unique(),
+    };
+    let mut account = Account::new(
+        Rent::default().minimum_balance(ProgramConfig::LEN),
+        ProgramConfig::LEN,
+        program_id,
+    );
+    ProgramConfig::pack(config, &mut account.data).unwrap();
+    account
 }
 
 #[tokio::test]
@@ -112,53 +132,84 @@ async fn test_process_init_loan() {
     let loan_acc_key = Pubkey::new_unique();
 
     let coin_mint = new_token_mint(&mut rng, &bump);
-    let temp_token_vault = new_token_account(&mut rng, &coin_mint.key, &account_key, &bump);
     let receiving_token_vault = new_token_account(&mut rng, &coin_mint.key, &account_key, &bump);
 
     let mut account_account = Account::new(2000000, Loan::LEN, &account_key);
-    let mut token_acc = Account::new(
-        Rent::default().minimum_balance(Loan::LEN),
-        Loan::LEN,
-        &account_key,
+    let mut mint_acc = Account::new(
+        Rent::default().minimum_balance(Mint::LEN),
+        Mint::LEN,
+        &spl_token::ID,
     );
+    mint_acc.data.copy_from_slice(&coin_mint.data.borrow());
     let mut receiving_account = Account::new(
-        Rent::default().minimum_balance(Loan::LEN),
-        Loan::LEN,
+        Rent::default().minimum_balance(TokenAccount::LEN),
+        TokenAccount::LEN,
         &spl_token::ID,
     );
+    receiving_account.data.copy_from_slice(&receiving_token_vault.data.borrow());
     let mut loan_acc = Account::new(
         Rent::default().minimum_balance(Loan::LEN),
         Loan::LEN,
         &program_id,
     );
     let mut rent_sysvar = rent_sysvar();
-    let spl_token_program = new_spl_token_program(&bump);
-    let mut token_program = Account::new(
-        Rent::default().minimum_balance(Loan::LEN),
-        Loan::LEN,
-        &spl_token_program.key,
-    );
-
-    println!("BEFORE >> {:?} ", token_acc.owner);
-
-    assert_eq!(token_acc.owner, account_key);
+    let mut config_acc = config_account(&program_id);
+    let mut clock_sysvar = clock_sysvar(1_612_137_600);
+    let collateral_mint_key = Pubkey::new_unique();
+
+    // the terms have to be within the program limits
+    for (duration, max_interest_rate, error) in vec![
+        (12, 1000, LoanError::InvalidDuration),
+        (24 * 365, 1000, LoanError::InvalidDuration),
+        (24 * 7, 800, LoanError::InterestRateTooHigh),
+    ] {
+        assert_eq!(
+            Err(error.into()),
+            do_process_instruction(
+                init_loan(
+                    program_id,
+                    account_key,
+                    *coin_mint.key,
+                    *receiving_token_vault.key,
+                    loan_acc_key,
+                    13337,
+                    duration,
+                    max_interest_rate,
+                    collateral_mint_key,
+                ),
+                vec![
+                    &mut account_account,
+                    &mut mint_acc,
+                    &mut receiving_account,
+                    &mut loan_acc,
+                    &mut rent_sysvar,
+                    &mut config_acc,
+                    &mut clock_sysvar,
+                ],
+            )
+        );
+    }
 
     do_process_instruction(
         init_loan(
             program_id,
             account_key,
-            *temp_token_vault.key,
+            *coin_mint.key,
             *receiving_token_vault.key,
             loan_acc_key,
             13337,
+            24 * 7,
+            1000,
+            collateral_mint_key,
         ),
         vec![
             &mut account_account,
-            &mut token_acc,
+            &mut mint_acc,
             &mut receiving_account,
             &mut loan_acc,
             &mut rent_sysvar,
-            &mut token_program,
+            &mut config_acc,
+            &mut clock_sysvar,
         ],
     )
     .unwrap();
@@ -174,13 +225,21 @@ async fn test_process_init_loan() {
     };
     assert_eq!(true, loan_data.is_initialized);
     assert_eq!(account_key, loan_data.initializer_pubkey);
-    assert_eq!(*temp_token_vault.key, loan_data.loan_mint_pubkey);
+    assert_eq!(*coin_mint.key, loan_data.loan_mint_pubkey);
+    assert_eq!(6, loan_data.loan_mint_decimals);
     assert_eq!(*receiving_token_vault.key, loan_data.borrower_loan_receive_pubkey);
     assert_eq!(13337, loan_data.expected_amount);
-    assert_eq!(9, loan_data.interest_rate);
-    assert_eq!(24 * 30, loan_data.duration);
-    assert_eq!(LoanStatus::Initialized as u8, loan_data.status);
-    assert_eq!(13446, loan_data.amount);
+    assert_eq!(900, loan_data.interest_rate);
+    assert_eq!(1000, loan_data.max_interest_rate);
+    assert_eq!(15000, loan_data.collateral_ratio);
+    assert_eq!(24 * 7, loan_data.duration);
+    assert_eq!(COption::Some(collateral_mint_key), loan_data.collateral_mint_pubkey);
+    assert_eq!(LoanStatus::Initialized, loan_data.status);
+    assert_eq!(13362, loan_data.amount);
+    assert_eq!(0, loan_data.amount_repaid);
+    assert_eq!(1_612_137_600, loan_data.created_at);
+    assert_eq!(0, loan_data.due_at);
+    assert_eq!(0, loan_data.closed_at);
 
     // let option = Some(account_key);
     // let c_option: COption<Pubkey> = option.into();
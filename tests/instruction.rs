@@ -0,0 +1,105 @@
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use loans254::error::LoanError;
+use loans254::instruction::{LoanInstruction, INSTRUCTION_VERSION};
+
+#[test]
+fn test_pack_unpack_instruction() {
+    let instruction = LoanInstruction::RepayLoan { amount: 13337 };
+    let data = instruction.pack_into_vec();
+    assert_eq!(vec![INSTRUCTION_VERSION, 3, 25, 52, 0, 0, 0, 0, 0, 0], data);
+    assert_eq!(Ok(instruction), LoanInstruction::unpack(&data));
+
+    let instruction = LoanInstruction::InitLoan {
+        amount: 13337,
+        duration: 24 * 90,
+        max_interest_rate: 1200,
+        collateral_mint: Pubkey::new_unique(),
+    };
+    assert_eq!(Ok(instruction.clone()), LoanInstruction::unpack(&instruction.pack_into_vec()));
+
+    // new instructions are appended, after `Liquidate`
+    let instruction = LoanInstruction::AddCollateral { amount: 13337 };
+    let data = instruction.pack_into_vec();
+    assert_eq!(vec![INSTRUCTION_VERSION, 16, 25, 52, 0, 0, 0, 0, 0, 0], data);
+    assert_eq!(Ok(instruction), LoanInstruction::unpack(&data));
+}
+
+#[test]
+fn test_unpack_invalid_instruction() {
+    let invalid = Err(ProgramError::from(LoanError::InvalidInstruction));
+    let mut data = LoanInstruction::RepayLoan { amount: 9 }.pack_into_vec();
+    assert_eq!(invalid, LoanInstruction::unpack(&[]));
+    assert_eq!(invalid, LoanInstruction::unpack(&data[..5]));
+
+    data.push(0);
+    assert_eq!(invalid, LoanInstruction::unpack(&data));
+
+    data.pop();
+    data[0] = INSTRUCTION_VERSION + 1;
+    assert_eq!(invalid, LoanInstruction::unpack(&data));
+}
+
+#[test]
+fn test_unpack_version_1_instruction() {
+    // only `InitLoan` changed in version 2
+    let repay = vec![1, 3, 9, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Ok(LoanInstruction::RepayLoan { amount: 9 }), LoanInstruction::unpack(&repay));
+
+    let init_loan = vec![1, 0, 25, 52, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&init_loan));
+}
+
+#[test]
+fn test_unpack_version_2_instruction() {
+    // only `InitConfig` and `UpdateConfig` changed in version 3
+    let repay = vec![2, 3, 9, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Ok(LoanInstruction::RepayLoan { amount: 9 }), LoanInstruction::unpack(&repay));
+
+    let mut update_config = vec![2, 7];
+    update_config.extend_from_slice(&[0; 24]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&update_config));
+    update_config[0] = 1;
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&update_config));
+}
+
+#[test]
+fn test_unpack_version_3_instruction() {
+    // only `InitConfig` and `UpdateConfig` changed in version 4
+    let repay = vec![3, 3, 9, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Ok(LoanInstruction::RepayLoan { amount: 9 }), LoanInstruction::unpack(&repay));
+
+    let mut init_config = vec![3, 6];
+    init_config.extend_from_slice(&[0; 28]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&init_config));
+}
+
+#[test]
+fn test_unpack_version_4_instruction() {
+    // only `InitConfig` and `UpdateConfig` changed in version 5
+    let repay = vec![4, 3, 9, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Ok(LoanInstruction::RepayLoan { amount: 9 }), LoanInstruction::unpack(&repay));
+
+    let mut init_config = vec![4, 6];
+    init_config.extend_from_slice(&[0; 36]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&init_config));
+
+    let mut update_config = vec![4, 7];
+    update_config.extend_from_slice(&[0; 36]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&update_config));
+}
+
+#[test]
+fn test_unpack_version_5_instruction() {
+    // only `InitConfig` and `UpdateConfig` changed in version 6
+    let repay = vec![5, 3, 9, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(Ok(LoanInstruction::RepayLoan { amount: 9 }), LoanInstruction::unpack(&repay));
+
+    let mut init_config = vec![5, 6];
+    init_config.extend_from_slice(&[0; 68]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&init_config));
+
+    let mut update_config = vec![5, 7];
+    update_config.extend_from_slice(&[0; 68]);
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidInstruction)), LoanInstruction::unpack(&update_config));
+}
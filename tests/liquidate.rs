@@ -0,0 +1,254 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::{Instruction, InstructionError}, program_option::COption, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+use solana_sdk::{signature::{Keypair, Signer}, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::liquidate;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+/// A vault that holds less than the 120% maintenance ratio of `AMOUNT`
+const UNHEALTHY_COLLATERAL_AMOUNT: u64 = 1_100_000;
+
+/// A liquidator with a token account to repay from, and one to receive the collateral
+struct Liquidator {
+    keypair: Keypair,
+    token: Pubkey,
+    collateral: Pubkey,
+}
+
+impl Liquidator {
+    fn new() -> Self {
+        Self {
+            keypair: Keypair::new(),
+            token: Pubkey::new_unique(),
+            collateral: Pubkey::new_unique(),
+        }
+    }
+}
+
+fn liquidate_program_test(fixture: &LoanFixture, liquidator: &Liquidator, collateral_amount: u64) -> ProgramTest {
+    let mut program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), collateral_amount);
+    program_test.add_account(liquidator.token, token_account(&fixture.mint, &liquidator.keypair.pubkey(), TOKEN_BALANCE));
+    program_test.add_account(liquidator.collateral, token_account(&fixture.mint, &liquidator.keypair.pubkey(), 0));
+    program_test
+}
+
+fn liquidate_instruction(fixture: &LoanFixture, liquidator: &Liquidator, closes_loan: bool, amount: u64) -> Instruction {
+    let (guarantor, guarantor_collateral) = if closes_loan {
+        (COption::Some(fixture.guarantor.pubkey()), COption::Some(fixture.guarantor_collateral))
+    } else {
+        (COption::None, COption::None)
+    };
+    liquidate(
+        fixture.program_id,
+        liquidator.keypair.pubkey(),
+        liquidator.token,
+        liquidator.collateral,
+        fixture.loan,
+        fixture.mint,
+        fixture.mint,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        COption::None,
+        guarantor,
+        guarantor_collateral,
+        amount,
+    )
+}
+
+/// An accepted loan collateralized in the mint of `priced_collateral`, with a vault whose
+/// value the price feed decides, and a liquidator who can receive that collateral
+fn priced_program_test(fixture: &LoanFixture, liquidator: &Liquidator, priced_collateral: &PricedCollateral) -> ProgramTest {
+    let loan = priced_collateral.loan(fixture, LoanStatus::Accepted);
+    let mut program_test = fixture.program_test(loan, PRICED_COLLATERAL_AMOUNT / 2);
+    priced_collateral.add_accounts(fixture, &mut program_test);
+    program_test.add_account(liquidator.token, token_account(&fixture.mint, &liquidator.keypair.pubkey(), TOKEN_BALANCE));
+    program_test.add_account(liquidator.collateral, token_account(&priced_collateral.mint, &liquidator.keypair.pubkey(), 0));
+    program_test
+}
+
+fn priced_liquidate_instruction(fixture: &LoanFixture, liquidator: &Liquidator, priced_collateral: &PricedCollateral) -> Instruction {
+    liquidate(
+        fixture.program_id,
+        liquidator.keypair.pubkey(),
+        liquidator.token,
+        liquidator.collateral,
+        fixture.loan,
+        fixture.mint,
+        priced_collateral.mint,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        COption::Some(priced_collateral.price_feed),
+        COption::None,
+        COption::None,
+        100_000,
+    )
+}
+
+#[tokio::test]
+async fn test_liquidate_in_part() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, UNHEALTHY_COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = liquidate_instruction(&fixture, &liquidator, false, 100_000);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await.unwrap();
+
+    // the liquidator gets what they repaid plus the 5% bonus
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Accepted, loan.status);
+    assert_eq!(100_000, loan.amount_repaid);
+    assert_eq!(105_000, loan.liquidated_collateral);
+    assert_eq!(TOKEN_BALANCE - 100_000, token_balance(&mut banks_client, liquidator.token).await);
+    assert_eq!(105_000, token_balance(&mut banks_client, liquidator.collateral).await);
+    assert_eq!(UNHEALTHY_COLLATERAL_AMOUNT - 105_000, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_in_full() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, UNHEALTHY_COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = liquidate_instruction(&fixture, &liquidator, true, AMOUNT);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await.unwrap();
+
+    // what is left after the bonus goes back to the guarantor
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Liquidated, loan.status);
+    assert_ne!(0, loan.closed_at);
+    assert_eq!(1_060_500, token_balance(&mut banks_client, liquidator.collateral).await);
+    assert_eq!(TOKEN_BALANCE + 39_500, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_empties_vault() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, 500_000);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the bonus on 600_000 is more than the vault holds, so the liquidator takes all of it
+    let instruction = liquidate_instruction(&fixture, &liquidator, true, 600_000);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await.unwrap();
+
+    // nothing secures the rest of the loan, so it is closed rather than left accepted
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Liquidated, loan.status);
+    assert_eq!(600_000, loan.amount_repaid);
+    assert_eq!(500_000, loan.liquidated_collateral);
+    assert_eq!(500_000, token_balance(&mut banks_client, liquidator.collateral).await);
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_empties_vault_without_guarantor_accounts() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, 500_000);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the vault has to be closed along with the loan
+    let instruction = liquidate_instruction(&fixture, &liquidator, false, 600_000);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+    assert_eq!(500_000, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_healthy_loan() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = liquidate_instruction(&fixture, &liquidator, false, 100_000);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotLiquidatable as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_rejects_other_token_program() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let program_test = liquidate_program_test(&fixture, &liquidator, UNHEALTHY_COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut instruction = liquidate_instruction(&fixture, &liquidator, false, 100_000);
+    instruction.accounts[11].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_liquidate_with_stale_price() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let priced_collateral = PricedCollateral {
+        publish_time: 0,
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = priced_program_test(&fixture, &liquidator, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = priced_liquidate_instruction(&fixture, &liquidator, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::StalePrice as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+    assert_eq!(PRICED_COLLATERAL_AMOUNT / 2, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_rejects_feed_of_other_oracle() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let priced_collateral = PricedCollateral {
+        price_feed_owner: Pubkey::new_unique(),
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = priced_program_test(&fixture, &liquidator, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = priced_liquidate_instruction(&fixture, &liquidator, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+    assert_eq!(PRICED_COLLATERAL_AMOUNT / 2, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_liquidate_rejects_feed_of_other_publisher() {
+    let fixture = LoanFixture::new();
+    let liquidator = Liquidator::new();
+    let priced_collateral = PricedCollateral::published_by(&fixture, &Pubkey::new_unique());
+    let program_test = priced_program_test(&fixture, &liquidator, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the other publisher could price the collateral low enough to liquidate any loan
+    let instruction = priced_liquidate_instruction(&fixture, &liquidator, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&liquidator.keypair]).await,
+    );
+    assert_eq!(PRICED_COLLATERAL_AMOUNT / 2, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
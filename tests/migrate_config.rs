@@ -0,0 +1,130 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, pubkey::Pubkey, rent::Rent, system_program};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{account::Account, signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::{init_config, migrate_config};
+use loans254::processor::process_instruction;
+use loans254::state::{ProgramConfig, CONFIG_V2_LEN};
+use loans254::utils::find_program_data_address;
+
+use fixtures::*;
+
+/// A config account of `config_len` bytes, without liquidation terms or an oracle
+/// publisher so that it fits into the size of a config created before them
+fn config_account(fixture: &LoanFixture, config_len: usize) -> Account {
+    let mut account = Account::new(Rent::default().minimum_balance(config_len), config_len, &fixture.program_id);
+    ProgramConfig {
+        maintenance_ratio: 0,
+        liquidation_bonus: 0,
+        oracle_publisher: Pubkey::default(),
+        ..fixture.config()
+    }.pack_into_account(&mut account.data).unwrap();
+    account
+}
+
+/// The program with a config of `config_len` bytes, created before the liquidation terms
+/// when it is `CONFIG_V2_LEN`
+fn migrate_config_program_test(fixture: &LoanFixture, config_len: usize) -> ProgramTest {
+    let mut program_test = ProgramTest::new("loans254", fixture.program_id, processor!(process_instruction));
+    program_test.add_account(
+        find_program_data_address(&fixture.program_id).0,
+        program_data_account(Some(&fixture.admin.pubkey())),
+    );
+    program_test.add_account(fixture.config, config_account(fixture, config_len));
+    for party in &[&fixture.admin, &fixture.borrower] {
+        program_test.add_account(party.pubkey(), Account::new(LAMPORTS, 0, &system_program::id()));
+    }
+    program_test
+}
+
+#[tokio::test]
+async fn test_migrate_config() {
+    let fixture = LoanFixture::new();
+    let program_test = migrate_config_program_test(&fixture, CONFIG_V2_LEN);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = migrate_config(fixture.program_id, fixture.admin.pubkey());
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await.unwrap();
+
+    // the admin gets the rent back, and the config is gone until it is created again
+    assert!(!account_exists(&mut banks_client, fixture.config).await);
+    let admin = banks_client.get_account(fixture.admin.pubkey()).await.unwrap().unwrap();
+    assert_eq!(LAMPORTS + Rent::default().minimum_balance(CONFIG_V2_LEN), admin.lamports);
+
+    let config = fixture.config();
+    let instruction = init_config(
+        fixture.program_id,
+        fixture.admin.pubkey(),
+        config.interest_rate,
+        config.duration,
+        config.processing_fee,
+        config.application_fee,
+        config.guarantor_share,
+        config.lender_share,
+        config.collateral_ratio,
+        config.maintenance_ratio,
+        config.liquidation_bonus,
+        config.oracle_program_id,
+        config.oracle_publisher,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await.unwrap();
+
+    // at the current size the config has room for the liquidation terms
+    let account = banks_client.get_account(fixture.config).await.unwrap().unwrap();
+    assert_eq!(ProgramConfig::LEN, account.data.len());
+    let config: ProgramConfig = get_packed(&mut banks_client, fixture.config).await;
+    assert_eq!(fixture.admin.pubkey(), config.admin_pubkey);
+    assert_eq!(12000, config.maintenance_ratio);
+    assert_eq!(500, config.liquidation_bonus);
+}
+
+#[tokio::test]
+async fn test_migrate_config_rejects_other_admin() {
+    let fixture = LoanFixture::new();
+    let program_test = migrate_config_program_test(&fixture, CONFIG_V2_LEN);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = migrate_config(fixture.program_id, fixture.borrower.pubkey());
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert!(account_exists(&mut banks_client, fixture.config).await);
+}
+
+#[tokio::test]
+async fn test_migrate_current_config() {
+    let fixture = LoanFixture::new();
+    let program_test = migrate_config_program_test(&fixture, ProgramConfig::LEN);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // a config of the current size has nothing to migrate
+    let instruction = migrate_config(fixture.program_id, fixture.admin.pubkey());
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidAccountData)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await,
+    );
+    assert!(account_exists(&mut banks_client, fixture.config).await);
+}
+
+#[tokio::test]
+async fn test_migrate_config_rejects_other_config() {
+    let fixture = LoanFixture::new();
+    let mut program_test = migrate_config_program_test(&fixture, CONFIG_V2_LEN);
+    let other_config = Pubkey::new_unique();
+    program_test.add_account(other_config, config_account(&fixture, CONFIG_V2_LEN));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let mut instruction = migrate_config(fixture.program_id, fixture.admin.pubkey());
+    instruction.accounts[1].pubkey = other_config;
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await,
+    );
+}
@@ -0,0 +1,276 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{account::Account, signature::{Keypair, Signer}, transaction::TransactionError};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use loans254::error::LoanError;
+use loans254::instruction::migrate_loan;
+use loans254::processor::process_instruction;
+use loans254::state::{Loan, LoanStatus, LOAN_V1_LEN};
+use loans254::utils::find_legacy_loan_authority_address;
+
+use fixtures::*;
+
+/// A version 1 loan of the fixture's parties, and the token accounts it handed to the
+/// version 1 loan authority once it was guaranteed and accepted
+struct V1Loan {
+    loan: Pubkey,
+    legacy_authority: Pubkey,
+    collateral: Pubkey,
+}
+
+impl V1Loan {
+    fn new(fixture: &LoanFixture) -> Self {
+        Self {
+            loan: Pubkey::new_unique(),
+            legacy_authority: find_legacy_loan_authority_address(&fixture.program_id).0,
+            collateral: Pubkey::new_unique(),
+        }
+    }
+
+    /// The version 1 layout: the status, the parties and their token accounts, the
+    /// amounts, and the interest rate as a percentage
+    fn data(&self, fixture: &LoanFixture, status: LoanStatus) -> Vec<u8> {
+        let coption = |pubkey: Pubkey| {
+            let mut data = vec![1, 0, 0, 0];
+            data.extend_from_slice(pubkey.as_ref());
+            data
+        };
+        let mut data = vec![1, status as u8];
+        data.extend_from_slice(fixture.borrower.pubkey().as_ref());
+        data.extend_from_slice(fixture.mint.as_ref());
+        data.extend_from_slice(fixture.borrower_token.as_ref());
+        if status == LoanStatus::Initialized {
+            data.extend_from_slice(&[0; 36 * 5]);
+        } else {
+            data.extend(coption(fixture.guarantor.pubkey()));
+            data.extend(coption(fixture.guarantor_repayment));
+            data.extend(coption(self.collateral));
+            if status == LoanStatus::Accepted {
+                data.extend(coption(fixture.lender.pubkey()));
+                data.extend(coption(fixture.lender_repayment));
+            } else {
+                data.extend_from_slice(&[0; 36 * 2]);
+            }
+        }
+        data.extend_from_slice(&EXPECTED_AMOUNT.to_le_bytes());
+        data.extend_from_slice(&AMOUNT.to_le_bytes());
+        data.extend_from_slice(&9u32.to_le_bytes());
+        data.extend_from_slice(&(24u32 * 30).to_le_bytes());
+        assert_eq!(LOAN_V1_LEN, data.len());
+        data
+    }
+
+    /// The program with the version 1 loan in `status`, and an empty account for the
+    /// current layout at the fixture's loan address
+    fn program_test(&self, fixture: &LoanFixture, status: LoanStatus) -> ProgramTest {
+        let mut program_test = ProgramTest::new("loans254", fixture.program_id, processor!(process_instruction));
+        for party in &[&fixture.borrower, &fixture.guarantor] {
+            program_test.add_account(party.pubkey(), Account::new(LAMPORTS, 0, &system_program::id()));
+        }
+        program_test.add_account(fixture.mint, packed_account(Mint {
+            is_initialized: true,
+            decimals: 6,
+            supply: u64::MAX / 2,
+            ..Mint::default()
+        }, &spl_token::id()));
+        let data = self.data(fixture, status);
+        program_test.add_account(self.loan, Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: fixture.program_id,
+            ..Account::default()
+        });
+        program_test.add_account(fixture.loan, Account::new(
+            Rent::default().minimum_balance(Loan::LEN),
+            Loan::LEN,
+            &fixture.program_id,
+        ));
+        for (token, amount) in &[
+            (self.collateral, COLLATERAL_AMOUNT),
+            (fixture.guarantor_repayment, 0),
+            (fixture.lender_repayment, 0),
+        ] {
+            program_test.add_account(*token, token_account(&fixture.mint, &self.legacy_authority, *amount));
+        }
+        program_test
+    }
+
+    fn migrate_loan_instruction(&self, fixture: &LoanFixture, payer: Pubkey, status: LoanStatus) -> Instruction {
+        let is_guaranteed = status == LoanStatus::Guaranteed || status == LoanStatus::Accepted;
+        let some_if = |condition: bool, pubkey: Pubkey| {
+            if condition {
+                COption::Some(pubkey)
+            } else {
+                COption::None
+            }
+        };
+        migrate_loan(
+            fixture.program_id,
+            payer,
+            fixture.borrower.pubkey(),
+            self.loan,
+            fixture.loan,
+            fixture.mint,
+            some_if(is_guaranteed, self.collateral),
+            some_if(is_guaranteed, fixture.guarantor_repayment),
+            some_if(is_guaranteed, fixture.mint),
+            some_if(status == LoanStatus::Accepted, fixture.lender_repayment),
+        )
+    }
+}
+
+async fn token_owner(banks_client: &mut BanksClient, pubkey: Pubkey) -> Pubkey {
+    get_packed::<TokenAccount>(banks_client, pubkey).await.owner
+}
+
+#[tokio::test]
+async fn test_migrate_initialized_loan() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let program_test = v1_loan.program_test(&fixture, LoanStatus::Initialized);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.borrower.pubkey(), LoanStatus::Initialized);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    // the request runs from the migration, rather than from the start of time
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Initialized, loan.status);
+    assert_ne!(0, loan.created_at);
+    assert!(!account_exists(&mut banks_client, v1_loan.loan).await);
+}
+
+#[tokio::test]
+async fn test_migrate_accepted_loan() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let program_test = v1_loan.program_test(&fixture, LoanStatus::Accepted);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.borrower.pubkey(), LoanStatus::Accepted);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    // the collateral moves into the vault of the new loan account.  When the loan was
+    // accepted is not known, so it is due from the migration rather than given a new term
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Accepted, loan.status);
+    assert_eq!(COption::Some(fixture.collateral_vault), loan.collateral_account_pubkey);
+    assert_eq!(COption::Some(fixture.mint), loan.collateral_mint_pubkey);
+    assert_eq!(0, loan.accepted_at);
+    assert_ne!(0, loan.due_at);
+    assert_eq!(loan.guaranteed_at, loan.due_at);
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(fixture.loan_authority, token_owner(&mut banks_client, fixture.collateral_vault).await);
+
+    // and the version 1 authority hands every token account back
+    assert_eq!(0, token_balance(&mut banks_client, v1_loan.collateral).await);
+    assert_eq!(fixture.guarantor.pubkey(), token_owner(&mut banks_client, v1_loan.collateral).await);
+    assert_eq!(fixture.guarantor.pubkey(), token_owner(&mut banks_client, fixture.guarantor_repayment).await);
+    assert_eq!(fixture.lender.pubkey(), token_owner(&mut banks_client, fixture.lender_repayment).await);
+    assert!(!account_exists(&mut banks_client, v1_loan.loan).await);
+}
+
+#[tokio::test]
+async fn test_migrate_guaranteed_loan() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let program_test = v1_loan.program_test(&fixture, LoanStatus::Guaranteed);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.borrower.pubkey(), LoanStatus::Guaranteed);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Guaranteed, loan.status);
+    assert_eq!(0, loan.due_at);
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(fixture.guarantor.pubkey(), token_owner(&mut banks_client, fixture.guarantor_repayment).await);
+    // the lender's account was never handed over
+    assert_eq!(v1_loan.legacy_authority, token_owner(&mut banks_client, fixture.lender_repayment).await);
+}
+
+#[tokio::test]
+async fn test_migrate_accepted_loan_without_collateral_accounts() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let program_test = v1_loan.program_test(&fixture, LoanStatus::Accepted);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the collateral cannot be left behind under the version 1 authority
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.borrower.pubkey(), LoanStatus::Initialized);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert!(account_exists(&mut banks_client, v1_loan.loan).await);
+}
+
+#[tokio::test]
+async fn test_migrate_loan_rejects_collateral_of_other_loan() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let other_collateral = Pubkey::new_unique();
+    let mut program_test = v1_loan.program_test(&fixture, LoanStatus::Accepted);
+    program_test.add_account(other_collateral, token_account(&fixture.mint, &v1_loan.legacy_authority, TOKEN_BALANCE));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the version 1 authority holds the collateral of every version 1 loan, only this
+    // loan's can be moved
+    let mut instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.borrower.pubkey(), LoanStatus::Accepted);
+    instruction.accounts[8].pubkey = other_collateral;
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::AccountMismatch as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, other_collateral).await);
+}
+
+#[tokio::test]
+async fn test_guarantor_migrates_accepted_loan() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let program_test = v1_loan.program_test(&fixture, LoanStatus::Accepted);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the guarantor does not need the borrower to get the collateral out of the version 1
+    // authority
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, fixture.guarantor.pubkey(), LoanStatus::Accepted);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await.unwrap();
+
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(fixture.guarantor.pubkey(), token_owner(&mut banks_client, v1_loan.collateral).await);
+    assert_eq!(fixture.lender.pubkey(), token_owner(&mut banks_client, fixture.lender_repayment).await);
+    // the borrower still gets the rent of the old account back
+    let borrower = banks_client.get_account(fixture.borrower.pubkey()).await.unwrap().unwrap();
+    assert_eq!(LAMPORTS + Rent::default().minimum_balance(LOAN_V1_LEN), borrower.lamports);
+}
+
+#[tokio::test]
+async fn test_migrate_loan_rejects_other_payer() {
+    let fixture = LoanFixture::new();
+    let v1_loan = V1Loan::new(&fixture);
+    let stranger = Keypair::new();
+    let mut program_test = v1_loan.program_test(&fixture, LoanStatus::Accepted);
+    program_test.add_account(stranger.pubkey(), Account::new(LAMPORTS, 0, &system_program::id()));
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = v1_loan.migrate_loan_instruction(&fixture, stranger.pubkey(), LoanStatus::Accepted);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&stranger]).await,
+    );
+    assert!(account_exists(&mut banks_client, v1_loan.loan).await);
+}
@@ -0,0 +1,258 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+};
+use solana_program_test::ProgramTest;
+use solana_sdk::{account::Account, signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::{cancel_offer, close_loan, expire_loan, offer_loan, take_offer};
+use loans254::state::{Loan, LoanOffer, LoanStatus};
+use loans254::utils::find_offer_vault_address;
+
+use fixtures::*;
+
+/// Collateral that covers the amount the borrower asked for at the 150% ratio, but not
+/// the interest an offer adds to it
+const THIN_COLLATERAL_AMOUNT: u64 = 1_500_000;
+
+/// A guaranteed loan with `collateral_amount` in its vault, and an empty offer account
+/// for the lender to fill in
+fn offer_program_test(fixture: &LoanFixture, offer: &Pubkey, collateral_amount: u64) -> ProgramTest {
+    let mut program_test = fixture.program_test(fixture.loan(LoanStatus::Guaranteed), collateral_amount);
+    program_test.add_account(*offer, Account::new(
+        Rent::default().minimum_balance(LoanOffer::LEN),
+        LoanOffer::LEN,
+        &fixture.program_id,
+    ));
+    program_test
+}
+
+fn offer_instruction(fixture: &LoanFixture, offer: &Pubkey) -> Instruction {
+    offer_loan(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.lender_repayment,
+        fixture.loan,
+        *offer,
+        fixture.mint,
+        800,
+        24 * 10,
+    )
+}
+
+#[tokio::test]
+async fn test_offer_and_take_loan() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let offer_vault = find_offer_vault_address(&fixture.program_id, &offer).0;
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    let offer_data: LoanOffer = get_packed(&mut banks_client, offer).await;
+    assert_eq!(fixture.loan, offer_data.loan_pubkey);
+    assert_eq!(fixture.lender.pubkey(), offer_data.lender_pubkey);
+    assert_eq!(offer_vault, offer_data.vault_pubkey);
+    assert_eq!(800, offer_data.interest_rate);
+    assert_eq!(24 * 10, offer_data.duration);
+    assert_eq!(EXPECTED_AMOUNT, offer_data.amount);
+    assert_eq!(EXPECTED_AMOUNT, token_balance(&mut banks_client, offer_vault).await);
+    assert_eq!(TOKEN_BALANCE - EXPECTED_AMOUNT, token_balance(&mut banks_client, fixture.lender_token).await);
+
+    let instruction = take_offer(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        offer,
+        fixture.lender.pubkey(),
+        fixture.mint,
+        fixture.mint,
+        COption::None,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Accepted, loan.status);
+    assert_eq!(800, loan.interest_rate);
+    assert_eq!(24 * 10, loan.duration);
+    assert_eq!(1_002_465, loan.amount);
+    assert_eq!(COption::Some(fixture.lender.pubkey()), loan.lender_pubkey);
+    assert_eq!(COption::Some(fixture.lender_repayment), loan.lender_repayment_pubkey);
+    assert_eq!(TOKEN_BALANCE + EXPECTED_AMOUNT - 1000, token_balance(&mut banks_client, fixture.borrower_token).await);
+    assert_eq!(1000, token_balance(&mut banks_client, fixture.treasury).await);
+    // the emptied offer vault holds the repayments from now on
+    assert_eq!(COption::Some(offer_vault), loan.repayment_vault_pubkey);
+    assert_eq!(0, token_balance(&mut banks_client, offer_vault).await);
+    assert!(!account_exists(&mut banks_client, offer).await);
+}
+
+#[tokio::test]
+async fn test_take_offer_without_enough_collateral() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let offer_vault = find_offer_vault_address(&fixture.program_id, &offer).0;
+    let program_test = offer_program_test(&fixture, &offer, THIN_COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // the offered terms make 1_002_465 owed, which needs more collateral than the vault holds
+    let instruction = take_offer(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        offer,
+        fixture.lender.pubkey(),
+        fixture.mint,
+        fixture.mint,
+        COption::None,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InsufficientCollateral as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Guaranteed, loan.status);
+    assert_eq!(EXPECTED_AMOUNT, token_balance(&mut banks_client, offer_vault).await);
+}
+
+#[tokio::test]
+async fn test_cancel_offer() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let offer_vault = find_offer_vault_address(&fixture.program_id, &offer).0;
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    let instruction = cancel_offer(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.loan,
+        offer,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, fixture.lender_token).await);
+    assert!(!account_exists(&mut banks_client, offer_vault).await);
+    assert!(!account_exists(&mut banks_client, offer).await);
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Guaranteed, loan.status);
+}
+
+#[tokio::test]
+async fn test_cancel_offer_of_closed_loan() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let offer_vault = find_offer_vault_address(&fixture.program_id, &offer).0;
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // nobody took the offer before the request expired and the borrower closed the loan
+    let instruction = expire_loan(
+        fixture.program_id,
+        fixture.loan,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[]).await.unwrap();
+    let instruction = close_loan(fixture.program_id, fixture.borrower.pubkey(), fixture.loan);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+    assert!(!account_exists(&mut banks_client, fixture.loan).await);
+
+    let instruction = cancel_offer(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.loan,
+        offer,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, fixture.lender_token).await);
+    assert!(!account_exists(&mut banks_client, offer_vault).await);
+    assert!(!account_exists(&mut banks_client, offer).await);
+}
+
+#[tokio::test]
+async fn test_offer_loan_rejects_other_token_program() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let mut instruction = offer_instruction(&fixture, &offer);
+    instruction.accounts[11].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+    assert_eq!(TOKEN_BALANCE, token_balance(&mut banks_client, fixture.lender_token).await);
+}
+
+#[tokio::test]
+async fn test_take_offer_rejects_other_borrower() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // only the borrower can take an offer for their loan
+    let instruction = take_offer(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        offer,
+        fixture.lender.pubkey(),
+        fixture.mint,
+        fixture.mint,
+        COption::None,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_offer_rejects_other_lender() {
+    let fixture = LoanFixture::new();
+    let offer = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = offer_program_test(&fixture, &offer, COLLATERAL_AMOUNT).start().await;
+
+    let instruction = offer_instruction(&fixture, &offer);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // only the lender can withdraw their offer
+    let instruction = cancel_offer(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        offer,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
@@ -0,0 +1,117 @@
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+use loans254::error::LoanError;
+use loans254::oracle::{PriceFeed, MAX_PRICE_AGE};
+
+fn price_feed(price: i64, confidence: u64, publish_time: i64) -> PriceFeed {
+    PriceFeed {
+        is_initialized: true,
+        base_mint: Pubkey::new_unique(),
+        quote_mint: Pubkey::new_unique(),
+        price,
+        exponent: -2,
+        confidence,
+        publish_time,
+    }
+}
+
+#[test]
+fn test_pack_unpack_price_feed() {
+    let feed = price_feed(-250, 3, 1_612_137_600);
+    let mut data = vec![0u8; PriceFeed::LEN];
+    PriceFeed::pack(feed, &mut data).unwrap();
+    let unpacked = PriceFeed::unpack(&data).unwrap();
+    assert_eq!(-250, unpacked.price);
+    assert_eq!(-2, unpacked.exponent);
+    assert_eq!(3, unpacked.confidence);
+    assert_eq!(1_612_137_600, unpacked.publish_time);
+
+    let empty = vec![0u8; PriceFeed::LEN];
+    assert_eq!(Err(ProgramError::UninitializedAccount), PriceFeed::unpack(&empty).map(|_| ()));
+}
+
+#[test]
+fn test_get_price() {
+    let now = 1_612_137_600;
+    // the price is discounted by its confidence
+    assert_eq!(Ok(247), price_feed(250, 3, now).get_price(now));
+    assert_eq!(Ok(247), price_feed(250, 3, now - MAX_PRICE_AGE).get_price(now));
+
+    let stale = Err(ProgramError::from(LoanError::StalePrice));
+    assert_eq!(stale, price_feed(250, 3, now - MAX_PRICE_AGE - 1).get_price(now));
+
+    let invalid = Err(ProgramError::from(LoanError::InvalidPrice));
+    assert_eq!(invalid, price_feed(250, 250, now).get_price(now));
+    assert_eq!(invalid, price_feed(-250, 0, now).get_price(now));
+    assert_eq!(invalid, price_feed(250, u64::MAX, now).get_price(now));
+}
+
+#[cfg(feature = "mock-oracle")]
+mod mock_oracle {
+    use solana_program::{instruction::InstructionError, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+    use solana_program_test::{processor, BanksClient, ProgramTest};
+    use solana_sdk::{
+        account::Account,
+        hash::Hash,
+        signature::{Keypair, Signer},
+        transaction::{Transaction, TransactionError},
+    };
+
+    use loans254::mock_oracle::{find_price_feed_address, mock_oracle_program, process_instruction, set_price};
+    use loans254::oracle::PriceFeed;
+
+    /// Starts the mock oracle with an empty price feed of `authority`
+    async fn start(authority: &Pubkey, base_mint: &Pubkey, quote_mint: &Pubkey) -> (BanksClient, Keypair, Hash) {
+        let mut program_test = ProgramTest::new("mock_oracle", mock_oracle_program::id(), processor!(process_instruction));
+        program_test.add_account(find_price_feed_address(authority, base_mint, quote_mint).0, Account::new(
+            Rent::default().minimum_balance(PriceFeed::LEN),
+            PriceFeed::LEN,
+            &mock_oracle_program::id(),
+        ));
+        program_test.start().await
+    }
+
+    #[tokio::test]
+    async fn test_mock_oracle_sets_price() {
+        let authority = Keypair::new();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = start(&authority.pubkey(), &base_mint, &quote_mint).await;
+
+        let mut transaction = Transaction::new_with_payer(
+            &[set_price(authority.pubkey(), base_mint, quote_mint, 250, -2, 3, 1_612_137_600)],
+            Some(&payer.pubkey()),
+        );
+        transaction.sign(&[&payer, &authority], recent_blockhash);
+        banks_client.process_transaction(transaction).await.unwrap();
+
+        let price_feed_pubkey = find_price_feed_address(&authority.pubkey(), &base_mint, &quote_mint).0;
+        let account = banks_client.get_account(price_feed_pubkey).await.unwrap().unwrap();
+        let feed = PriceFeed::unpack(&account.data).unwrap();
+        assert_eq!(base_mint, feed.base_mint);
+        assert_eq!(quote_mint, feed.quote_mint);
+        assert_eq!(250, feed.price);
+        assert_eq!(-2, feed.exponent);
+        assert_eq!(3, feed.confidence);
+        assert_eq!(1_612_137_600, feed.publish_time);
+    }
+
+    #[tokio::test]
+    async fn test_mock_oracle_rejects_other_authority() {
+        let authority = Keypair::new();
+        let other = Keypair::new();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = start(&authority.pubkey(), &base_mint, &quote_mint).await;
+
+        // the feed is not derived from the signer, so they cannot set its price
+        let mut instruction = set_price(other.pubkey(), base_mint, quote_mint, 250, -2, 3, 1_612_137_600);
+        instruction.accounts[0].pubkey = find_price_feed_address(&authority.pubkey(), &base_mint, &quote_mint).0;
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        transaction.sign(&[&payer, &other], recent_blockhash);
+        assert_eq!(
+            TransactionError::InstructionError(0, InstructionError::InvalidSeeds),
+            banks_client.process_transaction(transaction).await.unwrap_err().unwrap(),
+        );
+    }
+}
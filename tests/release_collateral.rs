@@ -0,0 +1,185 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::{Instruction, InstructionError}, program_option::COption, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::release_collateral;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+/// An accepted loan that is half repaid, so it needs half of its collateral
+fn half_repaid_loan(fixture: &LoanFixture) -> Loan {
+    Loan {
+        amount_repaid: AMOUNT / 2,
+        ..fixture.loan(LoanStatus::Accepted)
+    }
+}
+
+/// A half repaid loan collateralized in the mint of `priced_collateral`, with twice the
+/// collateral it needed to begin with
+fn priced_program_test(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> ProgramTest {
+    let loan = Loan {
+        amount_repaid: AMOUNT / 2,
+        ..priced_collateral.loan(fixture, LoanStatus::Accepted)
+    };
+    let mut program_test = fixture.program_test(loan, 2 * PRICED_COLLATERAL_AMOUNT);
+    priced_collateral.add_accounts(fixture, &mut program_test);
+    program_test
+}
+
+fn priced_release_collateral(fixture: &LoanFixture, priced_collateral: &PricedCollateral) -> Instruction {
+    release_collateral(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        priced_collateral.guarantor_collateral,
+        fixture.loan,
+        priced_collateral.mint,
+        COption::Some(priced_collateral.price_feed),
+    )
+}
+
+#[tokio::test]
+async fn test_release_collateral() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(half_repaid_loan(&fixture), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = release_collateral(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        fixture.guarantor_collateral,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await.unwrap();
+
+    assert_eq!(757_500, token_balance(&mut banks_client, fixture.collateral_vault).await);
+    assert_eq!(TOKEN_BALANCE + 757_500, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+}
+
+#[tokio::test]
+async fn test_release_collateral_without_excess() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = release_collateral(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        fixture.guarantor_collateral,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::InsufficientCollateral as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_release_collateral_once_due() {
+    let fixture = LoanFixture::new();
+    let loan = Loan {
+        due_at: 1,
+        ..half_repaid_loan(&fixture)
+    };
+    let program_test = fixture.program_test(loan, COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the collateral of an overdue loan is the lender's to claim
+    let instruction = release_collateral(
+        fixture.program_id,
+        fixture.guarantor.pubkey(),
+        fixture.guarantor_collateral,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::LoanExpired as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_release_collateral_rejects_other_guarantor() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(half_repaid_loan(&fixture), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // only the guarantor gets the collateral back
+    let instruction = release_collateral(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_release_collateral_with_stale_price() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral {
+        publish_time: 0,
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = priced_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = priced_release_collateral(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::StalePrice as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert_eq!(2 * PRICED_COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_release_collateral_rejects_feed_of_other_oracle() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral {
+        price_feed_owner: Pubkey::new_unique(),
+        ..PricedCollateral::new(&fixture)
+    };
+    let program_test = priced_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = priced_release_collateral(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert_eq!(2 * PRICED_COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_release_collateral_rejects_feed_of_other_publisher() {
+    let fixture = LoanFixture::new();
+    let priced_collateral = PricedCollateral::published_by(&fixture, &Pubkey::new_unique());
+    let program_test = priced_program_test(&fixture, &priced_collateral);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the other publisher could price the collateral high enough to release all of it
+    let instruction = priced_release_collateral(&fixture, &priced_collateral);
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::InvalidSeeds)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.guarantor]).await,
+    );
+    assert_eq!(2 * PRICED_COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+}
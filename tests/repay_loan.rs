@@ -0,0 +1,177 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption, pubkey::Pubkey};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::repay_loan;
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_repay_loan_in_installments() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let installment = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, installment, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Accepted, loan.status);
+    assert_eq!(AMOUNT / 2, loan.amount_repaid);
+    assert_eq!(TOKEN_BALANCE - AMOUNT / 2, token_balance(&mut banks_client, fixture.borrower_token).await);
+    assert_eq!(TOKEN_BALANCE + 2475, token_balance(&mut banks_client, fixture.guarantor_repayment).await);
+    assert_eq!(502_475, token_balance(&mut banks_client, fixture.repayment_vault).await);
+    assert_eq!(50, token_balance(&mut banks_client, fixture.treasury).await);
+    assert_eq!(COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.collateral_vault).await);
+
+    // the last installment completes the loan and hands the collateral back
+    let last_installment = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        fixture.loan,
+        fixture.mint,
+        COption::Some(fixture.guarantor.pubkey()),
+        COption::Some(fixture.guarantor_collateral),
+        AMOUNT - AMOUNT / 2,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, last_installment, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Repaid, loan.status);
+    assert_eq!(AMOUNT, loan.amount_repaid);
+    assert_eq!(TOKEN_BALANCE - AMOUNT, token_balance(&mut banks_client, fixture.borrower_token).await);
+    assert_eq!(TOKEN_BALANCE + 4950, token_balance(&mut banks_client, fixture.guarantor_repayment).await);
+    assert_eq!(1_004_950, token_balance(&mut banks_client, fixture.repayment_vault).await);
+    assert_eq!(100, token_balance(&mut banks_client, fixture.treasury).await);
+    assert_eq!(TOKEN_BALANCE + COLLATERAL_AMOUNT, token_balance(&mut banks_client, fixture.guarantor_collateral).await);
+    assert!(!account_exists(&mut banks_client, fixture.collateral_vault).await);
+}
+
+#[tokio::test]
+async fn test_repay_loan_rejects_other_token_program() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // a program standing in for the token program could fake the transfers
+    let mut instruction = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    instruction.accounts[7].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(0, loan.amount_repaid);
+}
+
+#[tokio::test]
+async fn test_repay_loan_rejects_other_lender_account() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the lender's share can only go to the loan's repayment vault, not even to the
+    // lender's own account
+    let instruction = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.lender_repayment,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::AccountMismatch as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}
+
+#[tokio::test]
+async fn test_repay_loan_after_lender_closes_their_account() {
+    let fixture = LoanFixture::new();
+    let mut loan = fixture.loan(LoanStatus::Accepted);
+    // the lender closed the account they named, hoping to make the repayments fail
+    // until the loan is due
+    loan.lender_repayment_pubkey = COption::Some(Pubkey::new_unique());
+    let program_test = fixture.program_test(loan, COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(AMOUNT / 2, loan.amount_repaid);
+    assert_eq!(502_475, token_balance(&mut banks_client, fixture.repayment_vault).await);
+}
+
+#[tokio::test]
+async fn test_repay_loan_without_repayment_vault() {
+    let fixture = LoanFixture::new();
+    // loans accepted before repayments were held in a vault pay the lender directly
+    let mut loan = fixture.loan(LoanStatus::Accepted);
+    loan.repayment_vault_pubkey = COption::None;
+    let program_test = fixture.program_test(loan, COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.lender_repayment,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+
+    assert_eq!(TOKEN_BALANCE + 502_475, token_balance(&mut banks_client, fixture.lender_repayment).await);
+}
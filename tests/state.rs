@@ -0,0 +1,314 @@
+use solana_program::{program_error::ProgramError, program_option::COption, program_pack::Pack, pubkey::Pubkey};
+
+use loans254::error::LoanError;
+use loans254::oracle::oracle_program;
+use loans254::state::{
+    Loan,
+    LoanAction,
+    LoanOffer,
+    LoanStatus,
+    ProgramConfig,
+    CONFIG_V1_LEN,
+    CONFIG_V2_LEN,
+    CONFIG_V3_LEN,
+    CONFIG_V4_LEN,
+    LOAN_DISCRIMINATOR,
+    LOAN_V1_LEN,
+    LOAN_VERSION,
+};
+
+fn v1_loan(initializer_pubkey: &Pubkey, loan_mint_pubkey: &Pubkey) -> Vec<u8> {
+    let mut data = vec![0u8; LOAN_V1_LEN];
+    data[0] = 1;
+    data[1] = LoanStatus::Initialized as u8;
+    data[2..34].copy_from_slice(initializer_pubkey.as_ref());
+    data[34..66].copy_from_slice(loan_mint_pubkey.as_ref());
+    data[278..286].copy_from_slice(&13337u64.to_le_bytes());
+    data[286..294].copy_from_slice(&13446u64.to_le_bytes());
+    data[294..298].copy_from_slice(&9u32.to_le_bytes());
+    data[298..302].copy_from_slice(&720u32.to_le_bytes());
+    data
+}
+
+#[test]
+fn test_unpack_v1_loan() {
+    let initializer_pubkey = Pubkey::new_unique();
+    let loan_mint_pubkey = Pubkey::new_unique();
+    let loan = Loan::unpack(&v1_loan(&initializer_pubkey, &loan_mint_pubkey)).unwrap();
+
+    assert_eq!(LoanStatus::Initialized, loan.status);
+    assert_eq!(initializer_pubkey, loan.initializer_pubkey);
+    assert_eq!(loan_mint_pubkey, loan.loan_mint_pubkey);
+    assert_eq!(13337, loan.expected_amount);
+    assert_eq!(13446, loan.amount);
+    assert_eq!(900, loan.interest_rate);
+    assert_eq!(720, loan.duration);
+    assert_eq!(COption::None, loan.guarantor_pubkey);
+    assert_eq!(COption::None, loan.collateral_mint_pubkey);
+}
+
+#[test]
+fn test_v1_loan_packs_into_current_layout() {
+    let initializer_pubkey = Pubkey::new_unique();
+    let loan = Loan::unpack(&v1_loan(&initializer_pubkey, &Pubkey::new_unique())).unwrap();
+    let mut data = vec![0u8; Loan::LEN];
+    Loan::pack(loan, &mut data).unwrap();
+
+    assert_eq!(LOAN_DISCRIMINATOR, data[0]);
+    assert_eq!(LOAN_VERSION, data[1]);
+    let loan = Loan::unpack(&data).unwrap();
+    assert_eq!(initializer_pubkey, loan.initializer_pubkey);
+    assert_eq!(900, loan.interest_rate);
+}
+
+#[test]
+fn test_unpack_unknown_layout() {
+    let mut data = vec![0u8; Loan::LEN];
+    assert_eq!(false, Loan::unpack_unchecked(&data).unwrap().is_initialized);
+
+    data[0] = LOAN_DISCRIMINATOR;
+    data[1] = LOAN_VERSION + 1;
+    assert_eq!(Err(ProgramError::InvalidAccountData), Loan::unpack_unchecked(&data).map(|_| ()));
+    assert_eq!(Err(ProgramError::InvalidAccountData), Loan::unpack_unchecked(&data[..300]).map(|_| ()));
+}
+
+#[test]
+fn test_unpack_invalid_status() {
+    let mut data = v1_loan(&Pubkey::new_unique(), &Pubkey::new_unique());
+    data[1] = 10;
+    assert_eq!(Err(ProgramError::InvalidAccountData), Loan::unpack(&data).map(|_| ()));
+}
+
+#[test]
+fn test_status_transitions() {
+    assert_eq!(Ok(LoanStatus::Initialized), LoanStatus::Uninitialized.transition(LoanAction::Init));
+    assert_eq!(Ok(LoanStatus::Guaranteed), LoanStatus::Initialized.transition(LoanAction::Guarantee));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Guaranteed.transition(LoanAction::Accept));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Accepted.transition(LoanAction::Repay));
+    assert_eq!(Ok(LoanStatus::Repaid), LoanStatus::Accepted.transition(LoanAction::RepayInFull));
+    assert_eq!(Ok(LoanStatus::Cancelled), LoanStatus::Guaranteed.transition(LoanAction::Cancel));
+    assert_eq!(Ok(LoanStatus::Defaulted), LoanStatus::Accepted.transition(LoanAction::ClaimDefault));
+    assert_eq!(Ok(LoanStatus::Guaranteed), LoanStatus::Guaranteed.transition(LoanAction::Offer));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Accepted.transition(LoanAction::Liquidate));
+    assert_eq!(Ok(LoanStatus::Liquidated), LoanStatus::Accepted.transition(LoanAction::LiquidateInFull));
+    assert_eq!(Ok(LoanStatus::Guaranteed), LoanStatus::Guaranteed.transition(LoanAction::AddCollateral));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Accepted.transition(LoanAction::AddCollateral));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Accepted.transition(LoanAction::ReleaseCollateral));
+    assert_eq!(Ok(LoanStatus::Expired), LoanStatus::Guaranteed.transition(LoanAction::Expire));
+    assert_eq!(Ok(LoanStatus::Accepted), LoanStatus::Accepted.transition(LoanAction::Migrate));
+    assert_eq!(Ok(LoanStatus::Closed), LoanStatus::Repaid.transition(LoanAction::Close));
+    assert_eq!(Ok(LoanStatus::Closed), LoanStatus::Expired.transition(LoanAction::Close));
+
+    let invalid = Err(ProgramError::from(LoanError::InvalidLoanStatus));
+    assert_eq!(invalid, LoanStatus::Initialized.transition(LoanAction::Init));
+    assert_eq!(invalid, LoanStatus::Initialized.transition(LoanAction::Accept));
+    assert_eq!(invalid, LoanStatus::Accepted.transition(LoanAction::Cancel));
+    assert_eq!(invalid, LoanStatus::Repaid.transition(LoanAction::Repay));
+    assert_eq!(invalid, LoanStatus::Defaulted.transition(LoanAction::Migrate));
+    assert_eq!(invalid, LoanStatus::Initialized.transition(LoanAction::Offer));
+    assert_eq!(invalid, LoanStatus::Accepted.transition(LoanAction::Offer));
+    assert_eq!(invalid, LoanStatus::Repaid.transition(LoanAction::CheckHealth));
+    assert_eq!(invalid, LoanStatus::Guaranteed.transition(LoanAction::Liquidate));
+    assert_eq!(invalid, LoanStatus::Liquidated.transition(LoanAction::Liquidate));
+    assert_eq!(invalid, LoanStatus::Initialized.transition(LoanAction::AddCollateral));
+    assert_eq!(invalid, LoanStatus::Repaid.transition(LoanAction::AddCollateral));
+    assert_eq!(invalid, LoanStatus::Defaulted.transition(LoanAction::ReleaseCollateral));
+    assert_eq!(invalid, LoanStatus::Accepted.transition(LoanAction::Expire));
+    assert_eq!(invalid, LoanStatus::Accepted.transition(LoanAction::Close));
+    assert_eq!(invalid, LoanStatus::Closed.transition(LoanAction::Close));
+}
+
+#[test]
+fn test_statuses_allowing_action() {
+    assert_eq!(vec![LoanStatus::Guaranteed], LoanStatus::allowing(LoanAction::Accept));
+    assert_eq!(
+        vec![LoanStatus::Initialized, LoanStatus::Guaranteed],
+        LoanStatus::allowing(LoanAction::Cancel),
+    );
+    assert_eq!(
+        vec![LoanStatus::Guaranteed, LoanStatus::Accepted],
+        LoanStatus::allowing(LoanAction::AddCollateral),
+    );
+    assert_eq!(
+        vec![LoanStatus::Accepted, LoanStatus::Repaid, LoanStatus::Defaulted, LoanStatus::Liquidated],
+        LoanStatus::allowing(LoanAction::WithdrawRepayment),
+    );
+}
+
+#[test]
+fn test_pack_unpack_offer() {
+    let loan_pubkey = Pubkey::new_unique();
+    let lender_pubkey = Pubkey::new_unique();
+    let lender_repayment_pubkey = Pubkey::new_unique();
+    let vault_pubkey = Pubkey::new_unique();
+    let mut data = vec![0u8; LoanOffer::LEN];
+    LoanOffer::pack(
+        LoanOffer {
+            is_initialized: true,
+            loan_pubkey,
+            lender_pubkey,
+            lender_repayment_pubkey,
+            vault_pubkey,
+            interest_rate: 1200,
+            duration: 24 * 30,
+            amount: 13337,
+            created_at: 1_600_000_000,
+        },
+        &mut data,
+    ).unwrap();
+    let offer = LoanOffer::unpack(&data).unwrap();
+    assert_eq!(loan_pubkey, offer.loan_pubkey);
+    assert_eq!(lender_pubkey, offer.lender_pubkey);
+    assert_eq!(lender_repayment_pubkey, offer.lender_repayment_pubkey);
+    assert_eq!(vault_pubkey, offer.vault_pubkey);
+    assert_eq!(1200, offer.interest_rate);
+    assert_eq!(24 * 30, offer.duration);
+    assert_eq!(13337, offer.amount);
+    assert_eq!(1_600_000_000, offer.created_at);
+
+    let empty = vec![0u8; LoanOffer::LEN];
+    assert_eq!(Err(ProgramError::UninitializedAccount), LoanOffer::unpack(&empty).map(|_| ()));
+}
+
+fn config(collateral_ratio: u32) -> ProgramConfig {
+    ProgramConfig {
+        is_initialized: true,
+        admin_pubkey: Pubkey::new_unique(),
+        interest_rate: 900,
+        duration: 24 * 30,
+        processing_fee: 100,
+        application_fee: 10,
+        guarantor_share: 5000,
+        lender_share: 5000,
+        bump_seed: 255,
+        collateral_ratio,
+        maintenance_ratio: 0,
+        liquidation_bonus: 0,
+        oracle_program_id: oracle_program::id(),
+        oracle_publisher: Pubkey::default(),
+    }
+}
+
+#[test]
+fn test_v1_config_requires_one_for_one_collateral() {
+    let mut data = vec![0u8; CONFIG_V1_LEN];
+    config(10_000).pack_into_account(&mut data).unwrap();
+    let unpacked = ProgramConfig::unpack(&data).unwrap();
+    assert_eq!(900, unpacked.interest_rate);
+    assert_eq!(255, unpacked.bump_seed);
+    assert_eq!(10_000, unpacked.collateral_ratio);
+
+    // there is no room for any other ratio
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidConfig)), config(15_000).pack_into_account(&mut data));
+
+    let mut data = vec![0u8; ProgramConfig::LEN];
+    config(15_000).pack_into_account(&mut data).unwrap();
+    assert_eq!(15_000, ProgramConfig::unpack(&data).unwrap().collateral_ratio);
+}
+
+#[test]
+fn test_v2_config_cannot_liquidate() {
+    let mut data = vec![0u8; CONFIG_V2_LEN];
+    config(15_000).pack_into_account(&mut data).unwrap();
+    let unpacked = ProgramConfig::unpack(&data).unwrap();
+    assert_eq!(15_000, unpacked.collateral_ratio);
+    assert_eq!(0, unpacked.maintenance_ratio);
+    assert_eq!(0, unpacked.liquidation_bonus);
+
+    // there is no room for the liquidation terms
+    let mut liquidating = config(15_000);
+    liquidating.maintenance_ratio = 12_000;
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidConfig)), liquidating.pack_into_account(&mut data));
+
+    let mut data = vec![0u8; ProgramConfig::LEN];
+    let mut liquidating = config(15_000);
+    liquidating.maintenance_ratio = 12_000;
+    liquidating.liquidation_bonus = 500;
+    liquidating.pack_into_account(&mut data).unwrap();
+    let unpacked = ProgramConfig::unpack(&data).unwrap();
+    assert_eq!(12_000, unpacked.maintenance_ratio);
+    assert_eq!(500, unpacked.liquidation_bonus);
+}
+
+#[test]
+fn test_v3_config_trusts_default_oracle() {
+    let mut data = vec![0u8; CONFIG_V3_LEN];
+    let mut liquidating = config(15_000);
+    liquidating.maintenance_ratio = 12_000;
+    liquidating.liquidation_bonus = 500;
+    liquidating.pack_into_account(&mut data).unwrap();
+    let unpacked = ProgramConfig::unpack(&data).unwrap();
+    assert_eq!(12_000, unpacked.maintenance_ratio);
+    assert_eq!(500, unpacked.liquidation_bonus);
+    assert_eq!(oracle_program::id(), unpacked.oracle_program_id);
+
+    // there is no room for another oracle program
+    let oracle_program_id = Pubkey::new_unique();
+    let other_oracle = || ProgramConfig { oracle_program_id, ..config(15_000) };
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidConfig)), other_oracle().pack_into_account(&mut data));
+
+    let mut data = vec![0u8; ProgramConfig::LEN];
+    other_oracle().pack_into_account(&mut data).unwrap();
+    assert_eq!(oracle_program_id, ProgramConfig::unpack(&data).unwrap().oracle_program_id);
+}
+
+#[test]
+fn test_v4_config_trusts_no_publisher() {
+    let mut data = vec![0u8; CONFIG_V4_LEN];
+    let oracle_program_id = Pubkey::new_unique();
+    let other_oracle = ProgramConfig { oracle_program_id, ..config(15_000) };
+    other_oracle.pack_into_account(&mut data).unwrap();
+    let unpacked = ProgramConfig::unpack(&data).unwrap();
+    assert_eq!(oracle_program_id, unpacked.oracle_program_id);
+    assert_eq!(Pubkey::default(), unpacked.oracle_publisher);
+
+    // there is no room for a publisher
+    let oracle_publisher = Pubkey::new_unique();
+    let publishing = || ProgramConfig { oracle_publisher, ..config(15_000) };
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidConfig)), publishing().pack_into_account(&mut data));
+
+    let mut data = vec![0u8; ProgramConfig::LEN];
+    publishing().pack_into_account(&mut data).unwrap();
+    assert_eq!(oracle_publisher, ProgramConfig::unpack(&data).unwrap().oracle_publisher);
+}
+
+#[test]
+fn test_required_collateral_ratio() {
+    let mut loan = Loan::unpack_unchecked(&[0u8; Loan::LEN]).unwrap();
+    assert_eq!(10_000, loan.required_collateral_ratio());
+    loan.collateral_ratio = 15_000;
+    assert_eq!(15_000, loan.required_collateral_ratio());
+}
+
+#[test]
+fn test_pack_unpack_liquidated_loan() {
+    let mut loan = Loan::unpack_unchecked(&[0u8; Loan::LEN]).unwrap();
+    loan.is_initialized = true;
+    loan.status = LoanStatus::Liquidated;
+    loan.amount_repaid = 13337;
+    loan.liquidated_amount = 4000;
+    loan.liquidated_collateral = 6300;
+    let mut data = vec![0u8; Loan::LEN];
+    Loan::pack(loan, &mut data).unwrap();
+
+    let loan = Loan::unpack(&data).unwrap();
+    assert_eq!(LoanStatus::Liquidated, loan.status);
+    assert_eq!(13337, loan.amount_repaid);
+    assert_eq!(4000, loan.liquidated_amount);
+    assert_eq!(6300, loan.liquidated_collateral);
+}
+
+#[test]
+fn test_pack_unpack_repayment_vault() {
+    // loans accepted before repayments were held in a vault read as having none
+    let mut loan = Loan::unpack_unchecked(&[0u8; Loan::LEN]).unwrap();
+    assert_eq!(COption::None, loan.repayment_vault_pubkey);
+
+    let repayment_vault_pubkey = Pubkey::new_unique();
+    loan.is_initialized = true;
+    loan.status = LoanStatus::Accepted;
+    loan.repayment_vault_pubkey = COption::Some(repayment_vault_pubkey);
+    let mut data = vec![0u8; Loan::LEN];
+    Loan::pack(loan, &mut data).unwrap();
+    assert_eq!(COption::Some(repayment_vault_pubkey), Loan::unpack(&data).unwrap().repayment_vault_pubkey);
+}
@@ -0,0 +1,72 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTest;
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::withdraw_treasury;
+use loans254::state::LoanStatus;
+
+use fixtures::*;
+
+const TREASURY_BALANCE: u64 = 5000;
+
+/// A treasury that has collected fees, and an empty token account of the admin
+fn treasury_program_test(fixture: &LoanFixture, admin_token: &Pubkey) -> ProgramTest {
+    let mut program_test = fixture.program_test(fixture.loan(LoanStatus::Initialized), 0);
+    program_test.add_account(fixture.treasury, token_account(&fixture.mint, &fixture.config, TREASURY_BALANCE));
+    program_test.add_account(*admin_token, token_account(&fixture.mint, &fixture.admin.pubkey(), 0));
+    program_test
+}
+
+#[tokio::test]
+async fn test_withdraw_treasury() {
+    let fixture = LoanFixture::new();
+    let admin_token = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = treasury_program_test(&fixture, &admin_token).start().await;
+
+    let instruction = withdraw_treasury(fixture.program_id, fixture.admin.pubkey(), fixture.mint, admin_token, 3000);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await.unwrap();
+
+    assert_eq!(TREASURY_BALANCE - 3000, token_balance(&mut banks_client, fixture.treasury).await);
+    assert_eq!(3000, token_balance(&mut banks_client, admin_token).await);
+}
+
+#[tokio::test]
+async fn test_withdraw_treasury_rejects_other_admin() {
+    let fixture = LoanFixture::new();
+    let admin_token = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = treasury_program_test(&fixture, &admin_token).start().await;
+
+    // only the admin can withdraw the program's fees
+    let instruction = withdraw_treasury(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.mint,
+        fixture.borrower_token,
+        3000,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+    assert_eq!(TREASURY_BALANCE, token_balance(&mut banks_client, fixture.treasury).await);
+}
+
+#[tokio::test]
+async fn test_withdraw_treasury_rejects_other_token_program() {
+    let fixture = LoanFixture::new();
+    let admin_token = Pubkey::new_unique();
+    let (mut banks_client, payer, recent_blockhash) = treasury_program_test(&fixture, &admin_token).start().await;
+
+    let mut instruction = withdraw_treasury(fixture.program_id, fixture.admin.pubkey(), fixture.mint, admin_token, 3000);
+    instruction.accounts[4].pubkey = Pubkey::new_unique();
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.admin]).await,
+    );
+}
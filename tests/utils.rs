@@ -0,0 +1,132 @@
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+use loans254::error::LoanError;
+use loans254::state::{Loan, ProgramConfig};
+use loans254::utils::{
+    apply_basis_points,
+    get_borrowed_amount,
+    get_basis_points,
+    get_collateral_amount,
+    get_collateral_value,
+    get_due_at,
+    get_repayment_split,
+};
+
+fn config() -> ProgramConfig {
+    ProgramConfig {
+        is_initialized: true,
+        admin_pubkey: Pubkey::new_unique(),
+        interest_rate: 900,
+        duration: 24 * 30,
+        processing_fee: 100,
+        application_fee: 10,
+        guarantor_share: 5000,
+        lender_share: 5000,
+        bump_seed: 255,
+        collateral_ratio: 15000,
+        maintenance_ratio: 12000,
+        liquidation_bonus: 500,
+        oracle_program_id: Pubkey::new_unique(),
+        oracle_publisher: Pubkey::new_unique(),
+    }
+}
+
+fn loan(expected_amount: u64) -> Loan {
+    let config = config();
+    let mut loan = Loan::unpack_unchecked(&[0u8; Loan::LEN]).unwrap();
+    loan.expected_amount = expected_amount;
+    loan.interest_rate = config.interest_rate;
+    loan.duration = config.duration;
+    loan.amount = get_borrowed_amount(
+        &config,
+        &loan.initializer_pubkey,
+        expected_amount,
+        loan.duration,
+        loan.interest_rate,
+    )
+    .unwrap();
+    loan
+}
+
+#[test]
+fn test_borrowed_amount_is_rounded_down() {
+    assert_eq!(13446, loan(13337).amount);
+    assert_eq!(0, loan(0).amount);
+}
+
+#[test]
+fn test_borrowed_amount_overflow() {
+    let result = get_borrowed_amount(&config(), &Pubkey::new_unique(), u64::MAX, 24 * 365, 10_000);
+    assert_eq!(Err(ProgramError::from(LoanError::MathOverflow)), result);
+}
+
+#[test]
+fn test_apply_basis_points() {
+    assert_eq!(Ok(13), apply_basis_points(13337, 10));
+    assert_eq!(Ok(u64::MAX), apply_basis_points(u64::MAX, 10_000));
+    assert_eq!(Err(ProgramError::from(LoanError::MathOverflow)), apply_basis_points(u64::MAX, 10_001));
+}
+
+#[test]
+fn test_repayment_split_in_full() {
+    let loan = loan(13337);
+    let split = get_repayment_split(&config(), &loan, loan.amount).unwrap();
+    assert_eq!(1, split.program_share);
+    assert_eq!(54, split.guarantor_share);
+    assert_eq!(13391, split.lender_share);
+}
+
+#[test]
+fn test_repayment_split_in_installments_leaves_no_dust() {
+    let config = config();
+    let mut loan = loan(1_000_003);
+    let mut total = 0;
+    let mut interest_total = 0;
+    while loan.outstanding_amount() > 0 {
+        let amount = std::cmp::min(77_777, loan.outstanding_amount());
+        let split = get_repayment_split(&config, &loan, amount).unwrap();
+        assert_eq!(amount, split.program_share + split.guarantor_share + split.lender_share);
+        total += amount;
+        interest_total += split.program_share + split.guarantor_share;
+        loan.amount_repaid += amount;
+    }
+    assert_eq!(loan.amount, total);
+    assert!(interest_total <= loan.amount - loan.expected_amount);
+}
+
+#[test]
+fn test_due_at() {
+    assert_eq!(Ok(1_612_137_600 + 720 * 60 * 60), get_due_at(1_612_137_600, 720));
+    assert_eq!(Err(ProgramError::from(LoanError::MathOverflow)), get_due_at(i64::MAX, 1));
+}
+
+#[test]
+fn test_collateral_amount_is_rounded_up() {
+    // 1 collateral token is worth 2.5 loan tokens, both mints have 6 decimals
+    assert_eq!(Ok(5335), get_collateral_amount(13337, 6, 6, 25, -1, 10_000));
+    assert_eq!(Ok(4), get_collateral_amount(10, 6, 6, 25, -1, 10_000));
+    assert_eq!(Ok(8003), get_collateral_amount(13337, 6, 6, 25, -1, 15_000));
+    // 1 collateral token with 9 decimals is worth 0.5 loan tokens with 6 decimals
+    assert_eq!(Ok(26_674_000), get_collateral_amount(13337, 6, 9, 5, -1, 10_000));
+    // 1 collateral token with 2 decimals is worth 1000 loan tokens with 6 decimals
+    assert_eq!(Ok(2), get_collateral_amount(13_337_000, 6, 2, 1, 3, 10_000));
+    assert_eq!(Err(ProgramError::from(LoanError::InvalidPrice)), get_collateral_amount(13337, 6, 6, 0, 0, 10_000));
+    assert_eq!(Err(ProgramError::from(LoanError::MathOverflow)), get_collateral_amount(13337, 0, 9, 1, -40, 10_000));
+}
+
+#[test]
+fn test_collateral_value_is_rounded_down() {
+    assert_eq!(Ok(13337), get_collateral_value(5335, 6, 6, 25, -1));
+    assert_eq!(Ok(13337), get_collateral_value(26_674_001, 6, 9, 5, -1));
+    assert_eq!(Ok(20_000_000), get_collateral_value(2, 6, 2, 1, 3));
+    assert_eq!(Ok(0), get_collateral_value(13337, 0, 9, 1, -40));
+    assert_eq!(Err(ProgramError::from(LoanError::MathOverflow)), get_collateral_value(u64::MAX, 9, 0, 1, 3));
+}
+
+#[test]
+fn test_basis_points() {
+    assert_eq!(15_000, get_basis_points(20_006, 13_337));
+    assert_eq!(10_000, get_basis_points(15_001, 15_000));
+    assert_eq!(0, get_basis_points(0, 13_337));
+    assert_eq!(u64::MAX, get_basis_points(13_337, 0));
+}
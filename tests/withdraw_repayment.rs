@@ -0,0 +1,97 @@
+// Mark this test as BPF-only due to current `ProgramTest` limitations when CPIing into the system program
+#![cfg(feature = "test-bpf")]
+
+mod fixtures;
+
+use solana_program::{instruction::InstructionError, program_option::COption};
+use solana_sdk::{signature::Signer, transaction::TransactionError};
+
+use loans254::error::LoanError;
+use loans254::instruction::{close_loan, repay_loan, withdraw_repayment};
+use loans254::state::{Loan, LoanStatus};
+
+use fixtures::*;
+
+#[tokio::test]
+async fn test_withdraw_repayment() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let instruction = repay_loan(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.guarantor_repayment,
+        fixture.repayment_vault,
+        fixture.loan,
+        fixture.mint,
+        COption::None,
+        COption::None,
+        AMOUNT / 2,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+    let instruction = withdraw_repayment(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.loan,
+        fixture.repayment_vault,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // the loan is still being repaid, so the vault stays open for the rest
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(COption::Some(fixture.repayment_vault), loan.repayment_vault_pubkey);
+    assert_eq!(TOKEN_BALANCE + 502_475, token_balance(&mut banks_client, fixture.lender_token).await);
+    assert_eq!(0, token_balance(&mut banks_client, fixture.repayment_vault).await);
+}
+
+#[tokio::test]
+async fn test_withdraw_repayment_of_repaid_loan() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Repaid), 0);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let vault_rent = banks_client.get_balance(fixture.repayment_vault).await.unwrap();
+
+    let instruction = withdraw_repayment(
+        fixture.program_id,
+        fixture.lender.pubkey(),
+        fixture.lender_token,
+        fixture.loan,
+        fixture.repayment_vault,
+    );
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.lender]).await.unwrap();
+
+    // nothing more is paid into a settled loan, the lender gets the vault rent back
+    let loan: Loan = get_packed(&mut banks_client, fixture.loan).await;
+    assert_eq!(LoanStatus::Repaid, loan.status);
+    assert_eq!(COption::None, loan.repayment_vault_pubkey);
+    assert!(!account_exists(&mut banks_client, fixture.repayment_vault).await);
+    assert_eq!(LAMPORTS + vault_rent, banks_client.get_balance(fixture.lender.pubkey()).await.unwrap());
+
+    // and the borrower can close the loan
+    let instruction = close_loan(fixture.program_id, fixture.borrower.pubkey(), fixture.loan);
+    process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await.unwrap();
+    assert!(!account_exists(&mut banks_client, fixture.loan).await);
+}
+
+#[tokio::test]
+async fn test_withdraw_repayment_rejects_other_signer() {
+    let fixture = LoanFixture::new();
+    let program_test = fixture.program_test(fixture.loan(LoanStatus::Accepted), COLLATERAL_AMOUNT);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // the repayments belong to the lender
+    let instruction = withdraw_repayment(
+        fixture.program_id,
+        fixture.borrower.pubkey(),
+        fixture.borrower_token,
+        fixture.loan,
+        fixture.repayment_vault,
+    );
+    assert_eq!(
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(LoanError::NotAuthorized as u32))),
+        process(&mut banks_client, &payer, recent_blockhash, instruction, &[&fixture.borrower]).await,
+    );
+}